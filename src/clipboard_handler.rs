@@ -6,18 +6,11 @@ use std::{
     },
 };
 
-use image::imageops::{
-    flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place,
-    rotate270, rotate90,
-};
-
-use crate::image_cache::image_loader::{
-    complex_load_image, errors::Error, LoadResult, Orientation,
-};
+use crate::image_cache::image_loader::{load_edited_image, ImageEdits};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum ClipboardState {
-    Pending(PathBuf),
+    Pending(PathBuf, ImageEdits),
     Succeeded,
     Failed,
 }
@@ -55,13 +48,13 @@ impl ClipboardHandler {
         }
     }
 
-    pub fn request_copy(&mut self, target: PathBuf) -> bool {
+    pub fn request_copy(&mut self, target: PathBuf, edits: ImageEdits) -> bool {
         {
             let mut state = self.request_handle.state.lock().unwrap();
             if let ClipboardState::Pending(..) = &*state {
                 return false;
             } else {
-                *state = ClipboardState::Pending(target);
+                *state = ClipboardState::Pending(target, edits);
             }
         }
         // Notify the condvar after releasing the mutex
@@ -94,11 +87,15 @@ impl ClipboardHandler {
         }
         while request_handle.run_thread.load(Ordering::Acquire) {
             let request_path;
+            let request_edits;
             {
                 let mut state_guard = request_handle.state.lock().unwrap();
                 'wait_for_request: loop {
-                    if let ClipboardState::Pending(path) = state_guard.clone() {
+                    if let ClipboardState::Pending(path, edits) =
+                        state_guard.clone()
+                    {
                         request_path = path;
+                        request_edits = edits;
                         break 'wait_for_request;
                     } else {
                         if !request_handle.run_thread.load(Ordering::Acquire) {
@@ -118,44 +115,10 @@ impl ClipboardHandler {
                     }
                 }
             }
-            let result = complex_load_image(&request_path, false, 0, |frame| {
-                if let LoadResult::Frame {
-                    mut image,
-                    orientation,
-                    ..
-                } = frame
-                {
-                    if let Ok(clipboard) = &mut clipboard {
-                        // Note: the imageops functions use clockwise rotation whereas the
-                        // `Orientation` type describes counter-clockwise rotation.
-                        image = match orientation {
-                            Orientation::Deg0 => image,
-                            Orientation::Deg0HorFlip => {
-                                flip_horizontal_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg90 => rotate270(&image),
-                            Orientation::Deg90VerFlip => {
-                                let mut result = rotate270(&image);
-                                flip_vertical_in_place(&mut result);
-                                result
-                            }
-                            Orientation::Deg180 => {
-                                rotate180_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg180HorFlip => {
-                                // This is identical to just a vertical flip with no rotation.
-                                flip_vertical_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg270 => rotate90(&image),
-                            Orientation::Deg270VerFlip => {
-                                let mut result = rotate90(&image);
-                                flip_vertical_in_place(&mut result);
-                                result
-                            }
-                        };
+            let mut result = false;
+            if let Ok(clipboard) = &mut clipboard {
+                match load_edited_image(&request_path, request_edits) {
+                    Ok(image) => {
                         let (w, h) = image.dimensions();
                         let cb_image = arboard::ImageData {
                             width: w as usize,
@@ -165,16 +128,16 @@ impl ClipboardHandler {
                         if let Err(e) = clipboard.set_image(cb_image) {
                             eprintln!("Could not set the clipboard image, error was: {e}");
                         } else {
-                            return Ok(());
+                            result = true;
                         }
                     }
+                    Err(e) => {
+                        eprintln!("Could not load the image for the clipboard, error was: {e}");
+                    }
                 }
-                Err(Error::Msg(
-                    "Could not set the clipboard image.".to_string(),
-                ))
-            });
+            }
             let mut state = request_handle.state.lock().unwrap();
-            *state = if result.is_ok() {
+            *state = if result {
                 ClipboardState::Succeeded
             } else {
                 ClipboardState::Failed
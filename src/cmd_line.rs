@@ -1,24 +1,137 @@
-use pico_args::Arguments;
+use std::{ffi::OsString, path::PathBuf};
 
-use crate::version::Version;
+use pico_args::{Arguments, Keys};
+
+use crate::{
+    configuration::{ExportFormat, PictureOrientation},
+    diff,
+    image_cache::{
+        filter::{normalize_extension, NamePattern},
+        image_loader::STDIN_PATH,
+    },
+    version::Version,
+};
 
 pub struct Args {
-    pub file_path: Option<String>,
+    /// The files, folders and playlists to show, in the given order
+    pub paths: Vec<PathBuf>,
+    /// Playlists whose files are shown after the ones of `paths`
+    pub playlists: Vec<PathBuf>,
+    /// The file with a list of paths separated by null characters, or `-`
+    /// for the standard input
+    pub files0_from: Option<String>,
+    /// List the images in the subfolders too
+    pub recursive: bool,
+    /// The number of subfolder levels listed in recursive mode
+    pub max_depth: Option<u32>,
+    /// Switch to every image that is added to the folder
+    pub follow: bool,
+    /// Only show the images whose name matches
+    pub name_filter: Option<NamePattern>,
+    /// Only show the images with these extensions
+    pub extensions: Option<Vec<String>>,
+    pub orientation: Option<PictureOrientation>,
+    /// Set when started with the `convert` subcommand
+    pub convert: Option<ConvertArgs>,
+    /// Set when started with the `diff` subcommand
+    pub diff: Option<DiffArgs>,
+}
+
+/// Arguments of the `convert` subcommand. Options that aren't given fall
+/// back to the `[export]` section of the configuration.
+pub struct ConvertArgs {
+    /// Files, directories or patterns with `*` and `?` wildcards
+    pub inputs: Vec<String>,
+    pub format: Option<ExportFormat>,
+    pub output: Option<PathBuf>,
+    pub resize: Option<Resize>,
+    pub quality: Option<u8>,
+    pub strip_metadata: bool,
+    /// Replace existing files instead of numbering the new ones
+    pub overwrite: bool,
+    pub jobs: Option<usize>,
+}
+
+/// Arguments of the `diff` subcommand
+pub struct DiffArgs {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    /// Where to save an image of the differences
+    pub output: Option<PathBuf>,
+    /// The largest difference of a channel, from 0 to 255, that is accepted
+    pub tolerance: u8,
+    pub min_psnr: Option<f64>,
+    pub min_ssim: Option<f64>,
+}
+
+/// How the `convert` subcommand resizes images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resize {
+    /// Keep the original size
+    Original,
+    /// Scale down to fit into the given width and height
+    Fit(u32, u32),
 }
 
 const HELP: &str = "\
 Alloy
 USAGE:
-  alloy [OPTIONS] [PATH]
+  alloy [OPTIONS] [PATH]...
+  alloy convert [CONVERT OPTIONS] <INPUT>...
+  alloy diff [DIFF OPTIONS] <A> <B>
 FLAGS:
   -h, --help            Prints help information
   -v, --version         Prints version
 OPTIONS:
+  -R, --recursive       Also show the images in the subfolders
+  --max-depth <N>       Number of subfolder levels shown in recursive mode
+  --follow              Switch to every image that is added to the folder,
+                        until another image is opened by hand
+  --filter <PATTERN>    Only show the images whose name matches PATTERN,
+                        where `*` and `?` are wildcards, like `*.png`
+  --filter-regex <RE>   Only show the images whose name matches the regular
+                        expression RE
+  --ext <LIST>          Only show the images with these extensions, given
+                        as a comma separated list like `jpg,jpeg`
+  --orientation <O>     Only show `portrait` or `landscape` images
+  --playlist <FILE>     Also show the files listed in FILE, one on each line
+                        like in an M3U playlist. Can be given more than once.
+  --files0-from <FILE>  Also show the files listed in FILE, separated by null
+                        characters like the output of `find -print0`. The
+                        list is read from the standard input if FILE is `-`.
 ARGS:
-  <PATH>                The file path of the image
+  <PATH>...             Images, folders or playlists ending in .m3u or .m3u8.
+                        A single image is shown with the other images of its
+                        folder, otherwise only the given files are shown.
+                        `-` on its own reads an image from the standard
+                        input.
+CONVERT OPTIONS:
+  -f, --format <FORMAT>   Output format: png, jpeg, webp, tiff, bmp or qoi
+  -o, --output <DIR>      Folder for the converted images, by default each
+                          image is saved next to its source
+  -r, --resize <SIZE>     Shrink larger images to fit into SIZE, which is
+                          either WIDTHxHEIGHT, a single number for a square
+                          or `none`
+  -q, --quality <1-100>   Quality of JPEG images
+  --strip-metadata        Don't copy the Exif data into JPEG images
+  --overwrite             Replace existing files of the same name, instead of
+                          adding a number to the names of the new files
+  -j, --jobs <N>          Number of images to convert at the same time
+CONVERT ARGS:
+  <INPUT>                 Image files, folders or patterns like `*.png`
+DIFF OPTIONS:
+  -o, --output <FILE>     Save the absolute difference of the images, the
+                          format is chosen by the extension
+  -t, --tolerance <0-255> Largest accepted difference of a color channel,
+                          0 by default
+  --min-psnr <DB>         Smallest accepted PSNR
+  --min-ssim <0-1>        Smallest accepted SSIM
+DIFF EXIT STATUS:
+  0 if the images are within the tolerance, 1 if they differ and 2 if an
+  image could not be loaded or the arguments are invalid
 ";
 
-/// Parses the command-line arguments and returns the file path
+/// Parses the command-line arguments
 pub fn parse_args() -> Args {
     let mut pargs = Arguments::from_env();
 
@@ -31,18 +144,277 @@ pub fn parse_args() -> Args {
         std::process::exit(0);
     }
 
-    // TODO: Options
+    let recursive = pargs.contains(["-R", "--recursive"]);
+    let max_depth = option(&mut pargs, "--max-depth", parse_max_depth);
+    let follow = pargs.contains("--follow");
+    let filter =
+        option(&mut pargs, "--filter", |s| Ok(NamePattern::wildcard(s)));
+    let filter_regex = option(&mut pargs, "--filter-regex", NamePattern::regex);
+    let extensions = option(&mut pargs, "--ext", parse_extensions);
+    let orientation = option(&mut pargs, "--orientation", parse_orientation);
+    let playlists = pargs
+        .values_from_os_str("--playlist", |s| Ok::<_, String>(PathBuf::from(s)))
+        .unwrap_or_else(|e| invalid_usage(&e.to_string()));
+    let files0_from = option(&mut pargs, "--files0-from", |s| Ok(s.to_owned()));
+    let viewer_options: Vec<&str> = [
+        ("--recursive", recursive),
+        ("--max-depth", max_depth.is_some()),
+        ("--follow", follow),
+        ("--filter", filter.is_some()),
+        ("--filter-regex", filter_regex.is_some()),
+        ("--ext", extensions.is_some()),
+        ("--orientation", orientation.is_some()),
+        ("--playlist", !playlists.is_empty()),
+        ("--files0-from", files0_from.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(option, given)| given.then_some(option))
+    .collect();
+    let name_filter = match (filter, filter_regex) {
+        (Some(_), Some(_)) => invalid_usage(
+            "The options --filter and --filter-regex can't be combined",
+        ),
+        (filter, filter_regex) => filter.or(filter_regex),
+    };
 
-    // Get filename
-    match pargs.free_from_str::<String>() {
-        Ok(file_path) if !file_path.starts_with('-') => Args {
-            file_path: Some(file_path),
-        },
-        Ok(_) => {
-            println!("Invalid usage\n");
-            print!("{}", HELP);
-            std::process::exit(1);
+    // The first free argument is either a subcommand or a path
+    let (paths, convert, diff) = match pargs.free_from_str::<String>() {
+        Ok(command) if command == "convert" => {
+            reject_viewer_options(&viewer_options, &command, 1);
+            (Vec::new(), Some(parse_convert_args(pargs)), None)
+        }
+        Ok(command) if command == "diff" => {
+            reject_viewer_options(&viewer_options, &command, diff::FAILED);
+            (Vec::new(), None, Some(parse_diff_args(pargs)))
         }
-        Err(_) => Args { file_path: None },
+        Ok(first_path) => (parse_paths(first_path, pargs), None, None),
+        Err(_) => (Vec::new(), None, None),
+    };
+    // The image from the standard input is shown on its own, since there's
+    // no folder to navigate in
+    let reads_stdin_image = paths.iter().any(|path| path == STDIN_PATH);
+    let other_paths =
+        paths.len() > 1 || !playlists.is_empty() || files0_from.is_some();
+    if reads_stdin_image && other_paths {
+        invalid_usage(
+            "The image from the standard input can't be combined with other \
+            paths, playlists or lists of files",
+        );
+    }
+    Args {
+        paths,
+        playlists,
+        files0_from,
+        recursive,
+        max_depth,
+        follow,
+        name_filter,
+        extensions,
+        orientation,
+        convert,
+        diff,
+    }
+}
+
+/// Exits if options of the viewer are given with the subcommand `command`,
+/// instead of ignoring them
+fn reject_viewer_options(options: &[&str], command: &str, exit_code: i32) {
+    if let Some(option) = options.first() {
+        exit_with_usage(
+            &format!("The option {option} can't be used with '{command}'"),
+            exit_code,
+        );
+    }
+}
+
+fn parse_paths(first_path: String, pargs: Arguments) -> Vec<PathBuf> {
+    let paths: Vec<OsString> = std::iter::once(OsString::from(first_path))
+        .chain(pargs.finish())
+        .collect();
+    // A single `-` stands for the image on the standard input
+    if let Some(arg) = paths.iter().find(|s| {
+        let s = s.to_string_lossy();
+        s.starts_with('-') && s != STDIN_PATH
+    }) {
+        invalid_usage(&format!("Unknown option '{}'", arg.to_string_lossy()));
+    }
+    paths.into_iter().map(PathBuf::from).collect()
+}
+
+fn parse_convert_args(mut pargs: Arguments) -> ConvertArgs {
+    let format = option(&mut pargs, ["-f", "--format"], parse_format);
+    let resize = option(&mut pargs, ["-r", "--resize"], parse_resize);
+    let quality = option(&mut pargs, ["-q", "--quality"], parse_quality);
+    let jobs = option(&mut pargs, ["-j", "--jobs"], parse_jobs);
+    let output = pargs
+        .opt_value_from_os_str(["-o", "--output"], |s| {
+            Ok::<_, String>(PathBuf::from(s))
+        })
+        .unwrap_or_else(|e| invalid_usage(&e.to_string()));
+    let strip_metadata = pargs.contains("--strip-metadata");
+    let overwrite = pargs.contains("--overwrite");
+
+    let inputs: Vec<String> = pargs
+        .finish()
+        .into_iter()
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect();
+    if let Some(arg) = inputs.iter().find(|s| s.starts_with('-')) {
+        invalid_usage(&format!("Unknown option '{arg}'"));
+    }
+    if inputs.is_empty() {
+        invalid_usage("No input images given");
+    }
+    ConvertArgs {
+        inputs,
+        format,
+        output,
+        resize,
+        quality,
+        strip_metadata,
+        overwrite,
+        jobs,
+    }
+}
+
+fn parse_diff_args(pargs: Arguments) -> DiffArgs {
+    // Exits with the code for failures, since the code of invalid usage is
+    // also the one for images that differ
+    try_parse_diff_args(pargs)
+        .unwrap_or_else(|message| exit_with_usage(&message, diff::FAILED))
+}
+
+fn try_parse_diff_args(mut pargs: Arguments) -> Result<DiffArgs, String> {
+    let tolerance = pargs
+        .opt_value_from_fn(["-t", "--tolerance"], parse_tolerance)
+        .map_err(|e| e.to_string())?;
+    let min_psnr = pargs
+        .opt_value_from_fn("--min-psnr", parse_psnr)
+        .map_err(|e| e.to_string())?;
+    let min_ssim = pargs
+        .opt_value_from_fn("--min-ssim", parse_ssim)
+        .map_err(|e| e.to_string())?;
+    let output = pargs
+        .opt_value_from_os_str(["-o", "--output"], |s| {
+            Ok::<_, String>(PathBuf::from(s))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let paths = pargs.finish();
+    if let Some(arg) =
+        paths.iter().find(|s| s.to_string_lossy().starts_with('-'))
+    {
+        return Err(format!("Unknown option '{}'", arg.to_string_lossy()));
+    }
+    let [a, b] = <[_; 2]>::try_from(paths)
+        .map_err(|_| "Expected two images to compare".to_owned())?;
+    Ok(DiffArgs {
+        a: PathBuf::from(a),
+        b: PathBuf::from(b),
+        output,
+        tolerance: tolerance.unwrap_or(0),
+        min_psnr,
+        min_ssim,
+    })
+}
+
+fn option<T>(
+    pargs: &mut Arguments,
+    keys: impl Into<Keys>,
+    parse: fn(&str) -> Result<T, String>,
+) -> Option<T> {
+    pargs
+        .opt_value_from_fn(keys, parse)
+        .unwrap_or_else(|e| invalid_usage(&e.to_string()))
+}
+
+fn parse_format(s: &str) -> Result<ExportFormat, String> {
+    match s.to_lowercase().as_str() {
+        "png" => Ok(ExportFormat::Png),
+        "jpg" | "jpeg" => Ok(ExportFormat::Jpeg),
+        "webp" => Ok(ExportFormat::WebP),
+        "tif" | "tiff" => Ok(ExportFormat::Tiff),
+        "bmp" => Ok(ExportFormat::Bmp),
+        "qoi" => Ok(ExportFormat::Qoi),
+        _ => Err(format!("unsupported output format '{s}'")),
+    }
+}
+
+fn parse_resize(s: &str) -> Result<Resize, String> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(Resize::Original);
+    }
+    let (w, h) = s.split_once(['x', 'X']).unwrap_or((s, s));
+    match (w.parse::<u32>(), h.parse::<u32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok(Resize::Fit(w, h)),
+        _ => Err(format!("invalid size '{s}'")),
+    }
+}
+
+fn parse_quality(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(quality @ 1..=100) => Ok(quality),
+        _ => Err(format!("invalid quality '{s}', expected 1 to 100")),
+    }
+}
+
+fn parse_tolerance(s: &str) -> Result<u8, String> {
+    s.parse::<u8>()
+        .map_err(|_| format!("invalid tolerance '{s}', expected 0 to 255"))
+}
+
+fn parse_psnr(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(psnr) if psnr >= 0.0 => Ok(psnr),
+        _ => Err(format!("invalid PSNR '{s}'")),
+    }
+}
+
+fn parse_ssim(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ssim) if (0.0..=1.0).contains(&ssim) => Ok(ssim),
+        _ => Err(format!("invalid SSIM '{s}', expected 0 to 1")),
     }
 }
+
+fn parse_max_depth(s: &str) -> Result<u32, String> {
+    s.parse::<u32>()
+        .map_err(|_| format!("invalid number of folder levels '{s}'"))
+}
+
+fn parse_extensions(s: &str) -> Result<Vec<String>, String> {
+    let extensions: Vec<String> = s
+        .split(',')
+        .map(normalize_extension)
+        .filter(|extension| !extension.is_empty())
+        .collect();
+    if extensions.is_empty() {
+        return Err(format!("invalid list of extensions '{s}'"));
+    }
+    Ok(extensions)
+}
+
+fn parse_orientation(s: &str) -> Result<PictureOrientation, String> {
+    match s.to_lowercase().as_str() {
+        "portrait" => Ok(PictureOrientation::Portrait),
+        "landscape" => Ok(PictureOrientation::Landscape),
+        _ => Err(format!("invalid orientation '{s}'")),
+    }
+}
+
+fn parse_jobs(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!("invalid number of jobs '{s}'")),
+    }
+}
+
+fn invalid_usage(message: &str) -> ! {
+    exit_with_usage(message, 1)
+}
+
+fn exit_with_usage(message: &str, exit_code: i32) -> ! {
+    println!("{message}\n");
+    print!("{}", HELP);
+    std::process::exit(exit_code);
+}
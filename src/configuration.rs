@@ -1,6 +1,7 @@
 use std::{collections::BTreeMap, fs, path::PathBuf};
 
 use directories::ProjectDirs;
+use image::ImageFormat;
 use serde::{Deserialize, Serialize};
 
 /// Application name for project directories
@@ -42,6 +43,8 @@ pub enum ScalingMode {
     Fixed,
     FitStretch,
     FitMin,
+    FitWidth,
+    FitHeight,
 }
 
 #[derive(
@@ -54,6 +57,144 @@ pub enum Antialias {
     Never,
 }
 
+/// The point at which two compared images of different sizes are aligned
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
+pub enum CompareAnchor {
+    #[default]
+    Center,
+    TopLeft,
+}
+
+impl CompareAnchor {
+    pub fn toggle(self) -> Self {
+        match self {
+            CompareAnchor::Center => CompareAnchor::TopLeft,
+            CompareAnchor::TopLeft => CompareAnchor::Center,
+        }
+    }
+}
+
+/// Where the filmstrip of the neighbouring images is shown in the bottom bar
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
+pub enum FilmstripPosition {
+    #[default]
+    Hidden,
+    AboveSlider,
+    InsteadOfSlider,
+}
+
+/// The shape of the images that are shown when filtering a folder
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PictureOrientation {
+    /// Taller than wide
+    Portrait,
+    /// Wider than tall
+    Landscape,
+}
+
+/// What stepping past the last or before the first image of a folder does
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
+pub enum EndOfFolder {
+    /// Continue at the other end of the folder
+    #[default]
+    Wrap,
+    /// Stay at the last or the first image
+    Stop,
+    /// Continue in the next or the previous folder in the parent folder
+    NextFolder,
+}
+
+/// What the images of a folder are ordered by
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Modified,
+    Created,
+    Size,
+    /// The Exif DateTimeOriginal tag, the time the photo was taken
+    ExifDate,
+    /// The number of pixels
+    Dimensions,
+    Extension,
+    /// A shuffle that is repeatable with the same seed
+    Random,
+}
+
+impl SortKey {
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Modified,
+            SortKey::Modified => SortKey::Created,
+            SortKey::Created => SortKey::Size,
+            SortKey::Size => SortKey::ExifDate,
+            SortKey::ExifDate => SortKey::Dimensions,
+            SortKey::Dimensions => SortKey::Extension,
+            SortKey::Extension => SortKey::Random,
+            SortKey::Random => SortKey::Name,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Modified => "modification time",
+            SortKey::Created => "creation time",
+            SortKey::Size => "file size",
+            SortKey::ExifDate => "date taken",
+            SortKey::Dimensions => "dimensions",
+            SortKey::Extension => "extension",
+            SortKey::Random => "random",
+        }
+    }
+}
+
+/// The formats that images can be saved in
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Bmp,
+    Qoi,
+}
+
+impl ExportFormat {
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            ExportFormat::Png => ImageFormat::Png,
+            ExportFormat::Jpeg => ImageFormat::Jpeg,
+            ExportFormat::WebP => ImageFormat::WebP,
+            ExportFormat::Tiff => ImageFormat::Tiff,
+            ExportFormat::Bmp => ImageFormat::Bmp,
+            ExportFormat::Qoi => ImageFormat::Qoi,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Jpeg => "jpg",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Tiff => "tiff",
+            ExportFormat::Bmp => "bmp",
+            ExportFormat::Qoi => "qoi",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ConfigWindow {
     pub title_folders: Option<u32>,
@@ -65,6 +206,55 @@ pub struct ConfigWindow {
 pub struct ConfigImage {
     pub scaling: Option<ScalingMode>,
     pub antialiasing: Option<Antialias>,
+    pub persist_views: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigExport {
+    pub format: Option<ExportFormat>,
+    pub jpeg_quality: Option<u8>,
+    pub max_size: Option<u32>,
+    pub strip_metadata: Option<bool>,
+    pub folder: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigGallery {
+    pub thumbnail_size: Option<u32>,
+    pub cache_size: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigFolder {
+    pub recursive: Option<bool>,
+    pub max_depth: Option<u32>,
+    pub sort: Option<SortKey>,
+    pub sort_descending: Option<bool>,
+    pub shuffle_seed: Option<u64>,
+    /// Only show the images whose name matches this wildcard pattern
+    pub filter: Option<String>,
+    /// Only show the images whose name matches this regular expression
+    pub filter_regex: Option<String>,
+    pub extensions: Option<Vec<String>>,
+    pub orientation: Option<PictureOrientation>,
+    pub end_of_folder: Option<EndOfFolder>,
+    /// The older form of `end_of_folder = "NextFolder"`, which is still
+    /// accepted when `end_of_folder` isn't given
+    pub continue_into_siblings: Option<bool>,
+    /// Start the slideshow over after the last image
+    pub loop_slideshow: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigFilmstrip {
+    pub position: Option<FilmstripPosition>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigCompare {
+    pub anchor: Option<CompareAnchor>,
+    pub onion_opacity: Option<u8>,
+    pub mask_threshold: Option<u8>,
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
@@ -85,6 +275,11 @@ pub struct Command {
 pub struct Configuration {
     pub window: Option<ConfigWindow>,
     pub image: Option<ConfigImage>,
+    pub export: Option<ConfigExport>,
+    pub folder: Option<ConfigFolder>,
+    pub compare: Option<ConfigCompare>,
+    pub gallery: Option<ConfigGallery>,
+    pub filmstrip: Option<ConfigFilmstrip>,
     pub bindings: Option<BTreeMap<String, Vec<String>>>,
     pub commands: Option<Vec<Command>>,
 }
@@ -141,6 +336,187 @@ impl Configuration {
         }
     }
 
+    /// Whether the zoom and pan of recently viewed images should be kept
+    /// between sessions.
+    pub fn persist_views(&self) -> bool {
+        self.image
+            .as_ref()
+            .and_then(|i| i.persist_views)
+            .unwrap_or_default()
+    }
+
+    pub fn export_format(&self) -> ExportFormat {
+        self.export
+            .as_ref()
+            .and_then(|e| e.format)
+            .unwrap_or_default()
+    }
+
+    pub fn jpeg_quality(&self) -> u8 {
+        self.export
+            .as_ref()
+            .and_then(|e| e.jpeg_quality)
+            .unwrap_or(90)
+            .clamp(1, 100)
+    }
+
+    /// The maximum width and height of saved images. Larger images are
+    /// scaled down, `None` means that they are saved at full size.
+    pub fn export_max_size(&self) -> Option<u32> {
+        self.export
+            .as_ref()
+            .and_then(|e| e.max_size)
+            .filter(|&size| size > 0)
+    }
+
+    pub fn strip_metadata(&self) -> bool {
+        self.export
+            .as_ref()
+            .and_then(|e| e.strip_metadata)
+            .unwrap_or_default()
+    }
+
+    /// The folder where images are saved. A relative path is relative to
+    /// the folder of the source image, `None` means the same folder.
+    pub fn export_folder(&self) -> Option<PathBuf> {
+        self.export.as_ref().and_then(|e| e.folder.clone())
+    }
+
+    /// The size of the thumbnails in the gallery in logical pixels
+    pub fn thumbnail_size(&self) -> u32 {
+        self.gallery
+            .as_ref()
+            .and_then(|g| g.thumbnail_size)
+            .unwrap_or(160)
+            .clamp(32, 1024)
+    }
+
+    /// The GPU memory that the thumbnails may use in bytes
+    pub fn thumbnail_cache_size(&self) -> isize {
+        let megabytes = self
+            .gallery
+            .as_ref()
+            .and_then(|g| g.cache_size)
+            .unwrap_or(256);
+        megabytes as isize * 1024 * 1024
+    }
+
+    /// Whether the images in the subfolders are listed too at startup
+    pub fn recursive(&self) -> bool {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.recursive)
+            .unwrap_or_default()
+    }
+
+    /// The number of subfolder levels that are listed in recursive mode
+    pub fn max_depth(&self) -> u32 {
+        self.folder.as_ref().and_then(|f| f.max_depth).unwrap_or(8)
+    }
+
+    pub fn sort_key(&self) -> SortKey {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.sort)
+            .unwrap_or_default()
+    }
+
+    pub fn sort_descending(&self) -> bool {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.sort_descending)
+            .unwrap_or_default()
+    }
+
+    /// The seed of the `Random` sort order
+    pub fn shuffle_seed(&self) -> u64 {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.shuffle_seed)
+            .unwrap_or_default()
+    }
+
+    pub fn set_sort(&mut self, key: SortKey, descending: bool, seed: u64) {
+        let folder = self.folder.get_or_insert_with(Default::default);
+        folder.sort = Some(key);
+        folder.sort_descending = Some(descending);
+        folder.shuffle_seed = Some(seed);
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.folder.as_ref().and_then(|f| f.filter.as_deref())
+    }
+
+    pub fn filter_regex(&self) -> Option<&str> {
+        self.folder.as_ref().and_then(|f| f.filter_regex.as_deref())
+    }
+
+    /// The extensions of the images that are shown, empty to show all
+    pub fn extensions(&self) -> &[String] {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.extensions.as_deref())
+            .unwrap_or_default()
+    }
+
+    pub fn orientation(&self) -> Option<PictureOrientation> {
+        self.folder.as_ref().and_then(|f| f.orientation)
+    }
+
+    pub fn end_of_folder(&self) -> EndOfFolder {
+        let Some(folder) = &self.folder else {
+            return EndOfFolder::default();
+        };
+        match (folder.end_of_folder, folder.continue_into_siblings) {
+            (Some(end_of_folder), _) => end_of_folder,
+            (None, Some(true)) => EndOfFolder::NextFolder,
+            (None, _) => EndOfFolder::default(),
+        }
+    }
+
+    /// Whether the slideshows start over after the last image instead of
+    /// stopping
+    pub fn loop_slideshow(&self) -> bool {
+        self.folder
+            .as_ref()
+            .and_then(|f| f.loop_slideshow)
+            .unwrap_or(true)
+    }
+
+    pub fn filmstrip_position(&self) -> FilmstripPosition {
+        self.filmstrip
+            .as_ref()
+            .and_then(|f| f.position)
+            .unwrap_or_default()
+    }
+
+    pub fn compare_anchor(&self) -> CompareAnchor {
+        self.compare
+            .as_ref()
+            .and_then(|c| c.anchor)
+            .unwrap_or_default()
+    }
+
+    /// The opacity of the right image in the onion skin overlay, from 0 to 1
+    pub fn onion_opacity(&self) -> f32 {
+        let percent = self
+            .compare
+            .as_ref()
+            .and_then(|c| c.onion_opacity)
+            .unwrap_or(50)
+            .min(100);
+        percent as f32 / 100.0
+    }
+
+    /// The difference of a color channel, from 0 to 255, above which a
+    /// pixel is highlighted by the changed pixels overlay
+    pub fn mask_threshold(&self) -> u8 {
+        self.compare
+            .as_ref()
+            .and_then(|c| c.mask_threshold)
+            .unwrap_or(8)
+    }
+
     pub fn title_folders(&self) -> u32 {
         self.window
             .as_ref()
@@ -201,3 +577,37 @@ pub fn data_dir() -> PathBuf {
     }
     data_dir
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn end_of_folder(folder_section: &str) -> EndOfFolder {
+        let config: Configuration =
+            toml::from_str(&format!("[folder]\n{folder_section}")).unwrap();
+        config.end_of_folder()
+    }
+
+    #[test]
+    fn end_of_folder_accepts_the_older_key() {
+        assert_eq!(end_of_folder(""), EndOfFolder::Wrap);
+        assert_eq!(
+            end_of_folder("end_of_folder = \"Stop\""),
+            EndOfFolder::Stop
+        );
+        assert_eq!(
+            end_of_folder("continue_into_siblings = true"),
+            EndOfFolder::NextFolder
+        );
+        assert_eq!(
+            end_of_folder("continue_into_siblings = false"),
+            EndOfFolder::Wrap
+        );
+        assert_eq!(
+            end_of_folder(
+                "end_of_folder = \"Stop\"\ncontinue_into_siblings = true"
+            ),
+            EndOfFolder::Stop
+        );
+    }
+}
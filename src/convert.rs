@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    error::Error as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use lexical_sort::natural_lexical_cmp;
+
+use crate::{
+    cmd_line::{ConvertArgs, Resize},
+    configuration::Configuration,
+    export::{export_image, export_path, ExportSettings},
+    image_cache::{
+        filter::wildcard_match,
+        image_loader::{errors::Error, is_file_supported, ImageEdits},
+    },
+};
+
+/// Runs the `convert` subcommand without opening a window. Prints a line for
+/// each image and returns the exit code of the process.
+pub fn run(args: ConvertArgs, config: &Configuration) -> i32 {
+    let settings = match convert_settings(&args, config) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+
+    let mut sources = Vec::new();
+    let mut invalid_inputs = false;
+    for input in &args.inputs {
+        match collect_sources(input) {
+            Ok(paths) if paths.is_empty() => {
+                eprintln!("No images found for {input:?}");
+                invalid_inputs = true;
+            }
+            Ok(paths) => sources.extend(paths),
+            Err(e) => {
+                eprintln!("Could not read {input:?}: {e}");
+                invalid_inputs = true;
+            }
+        }
+    }
+
+    if settings.overwrite {
+        // Which of the images would end up in the file depended on the order
+        // that the threads finish in
+        if let Some(message) = conflicting_targets(&sources, &settings) {
+            eprintln!("{message}");
+            return 1;
+        }
+    }
+
+    let start = Instant::now();
+    let threads = args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .min(sources.len().max(1));
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(source) = sources.get(index) else {
+                    break;
+                };
+                match export_image(
+                    source,
+                    ImageEdits::default(),
+                    None,
+                    &settings,
+                ) {
+                    Ok(target) => println!("{source:?} -> {target:?}"),
+                    Err(e) => {
+                        eprintln!("{source:?} failed: {}", describe(&e));
+                        *failed.lock().unwrap() += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner().unwrap();
+    println!(
+        "Converted {} of {} images in {:.1} s",
+        sources.len() - failed,
+        sources.len(),
+        start.elapsed().as_secs_f32()
+    );
+    if failed > 0 || invalid_inputs {
+        1
+    } else {
+        0
+    }
+}
+
+/// The settings of the `[export]` section overridden by the arguments
+fn convert_settings(
+    args: &ConvertArgs,
+    config: &Configuration,
+) -> std::io::Result<ExportSettings> {
+    let mut settings = ExportSettings::from_config(config);
+    if let Some(format) = args.format {
+        settings.format = format;
+    }
+    if let Some(quality) = args.quality {
+        settings.jpeg_quality = quality;
+    }
+    match args.resize {
+        Some(Resize::Original) => settings.max_size = None,
+        Some(Resize::Fit(w, h)) => settings.max_size = Some((w, h)),
+        None => (),
+    }
+    settings.strip_metadata |= args.strip_metadata;
+    settings.overwrite = args.overwrite;
+    if let Some(output) = &args.output {
+        // The export folder is relative to the source images otherwise
+        settings.folder = Some(std::env::current_dir()?.join(output));
+    }
+    Ok(settings)
+}
+
+/// Returns the images that `input` refers to. That is, the supported images
+/// in a folder, the files matching a pattern or the file itself.
+fn collect_sources(input: &str) -> std::io::Result<Vec<PathBuf>> {
+    let path = Path::new(input);
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let mut paths = if path.is_dir() {
+        list_images(path, |_| true)?
+    } else if name.contains(['*', '?']) {
+        let pattern: Vec<char> = name.chars().collect();
+        let folder = match path.parent() {
+            Some(folder) if !folder.as_os_str().is_empty() => folder,
+            _ => Path::new("."),
+        };
+        list_images(folder, |file_name| {
+            let file_name: Vec<char> = file_name.chars().collect();
+            wildcard_match(&pattern, &file_name)
+        })?
+    } else {
+        // Let the loader report files that don't exist or aren't images
+        return Ok(vec![path.to_owned()]);
+    };
+    paths.sort_by(|a, b| {
+        natural_lexical_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+    });
+    Ok(paths)
+}
+
+/// Describes two of `sources` that are saved to the same file, if there are
+/// any
+fn conflicting_targets(
+    sources: &[PathBuf],
+    settings: &ExportSettings,
+) -> Option<String> {
+    let mut targets = HashMap::new();
+    for source in sources {
+        let target = export_path(source, None, settings);
+        if let Some(other) = targets.insert(target.clone(), source) {
+            return Some(format!(
+                "Both {other:?} and {source:?} would be saved as {target:?}"
+            ));
+        }
+    }
+    None
+}
+
+fn list_images(
+    folder: &Path,
+    filter: impl Fn(&str) -> bool,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .map(|n| filter(&n.to_string_lossy()))
+            .unwrap_or(false);
+        if matches && path.is_file() && is_file_supported(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Formats the error together with its causes, since some of the messages
+/// are not very helpful on their own.
+pub fn describe(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        // Some errors already include the message of their cause
+        if !message.contains(&cause_message) {
+            message += &format!(": {cause_message}");
+        }
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{configuration::ExportFormat, temp_dir::TempDir};
+
+    #[test]
+    fn sources_from_patterns() {
+        let folder = TempDir::new("convert");
+        fs::create_dir(folder.join("a_folder.png")).unwrap();
+        for name in ["a10.png", "a2.png", "a1.jpg", "b1.png", "a3.txt"] {
+            fs::write(folder.join(name), b"").unwrap();
+        }
+        let names = |input: &Path| -> Vec<String> {
+            collect_sources(input.to_str().unwrap())
+                .unwrap()
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        // Sorted naturally, without folders or unsupported files
+        assert_eq!(names(&folder.join("a*")), ["a1.jpg", "a2.png", "a10.png"]);
+        assert_eq!(names(&folder.join("?1.*")), ["a1.jpg", "b1.png"]);
+        assert!(names(&folder.join("c*")).is_empty());
+        assert_eq!(names(&folder), ["a1.jpg", "a2.png", "a10.png", "b1.png"]);
+        // Plain paths are passed on even when they don't exist
+        assert_eq!(names(&folder.join("missing.png")), ["missing.png"]);
+    }
+
+    #[test]
+    fn conflicting_targets_are_found() {
+        let settings = ExportSettings {
+            format: ExportFormat::Png,
+            folder: Some(PathBuf::from("out")),
+            ..ExportSettings::default()
+        };
+        let sources = |paths: &[&str]| {
+            paths.iter().map(PathBuf::from).collect::<Vec<_>>()
+        };
+        let conflicts =
+            |paths: &[&str]| conflicting_targets(&sources(paths), &settings);
+        assert_eq!(conflicts(&["a/x.jpg", "a/y.jpg", "b/x.jpg"]), None);
+        let message = conflicts(&["a/x.jpg", "a/y.jpg", "a/x.webp"]).unwrap();
+        let target = Path::new("a").join("out").join("x.png");
+        assert!(message.ends_with(&format!("{target:?}")), "{message}");
+    }
+}
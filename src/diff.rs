@@ -0,0 +1,300 @@
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    cmd_line::DiffArgs,
+    convert::describe,
+    image_cache::image_loader::{load_edited_image, ImageEdits},
+};
+
+/// Exit code for images that are within the tolerance
+const WITHIN_TOLERANCE: i32 = 0;
+/// Exit code for images that differ by more than the tolerance
+const DIFFERENT: i32 = 1;
+/// Exit code for images that could not be loaded or saved, and for invalid
+/// arguments
+pub const FAILED: i32 = 2;
+
+/// The width and height of the windows that the SSIM is averaged over
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+
+/// Runs the `diff` subcommand without opening a window. Prints the
+/// differences of the images and returns the exit code of the process.
+pub fn run(args: DiffArgs) -> i32 {
+    let (a, b) = match (load(&args.a), load(&args.b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return FAILED,
+    };
+    let (w, h) = a.dimensions();
+    if a.dimensions() != b.dimensions() {
+        let (b_w, b_h) = b.dimensions();
+        println!("Dimensions differ: {w} × {h} and {b_w} × {b_h}");
+        if let Some(output) = &args.output {
+            println!("No difference image saved to {output:?}");
+        }
+        return DIFFERENT;
+    }
+    println!("Dimensions: {w} × {h}");
+
+    let stats = Stats::compare(&a, &b);
+    let psnr = stats.psnr();
+    let ssim = ssim(&a, &b);
+    println!("Max error:  {}", stats.max_error);
+    println!("Mean error: {:.4}", stats.mean_error);
+    println!("PSNR:       {psnr:.2} dB");
+    println!("SSIM:       {ssim:.5}");
+
+    let mut exceeded = Vec::new();
+    if stats.max_error > args.tolerance {
+        exceeded.push(format!(
+            "max error {} > {}",
+            stats.max_error, args.tolerance
+        ));
+    }
+    if let Some(min_psnr) = args.min_psnr.filter(|&min| psnr < min) {
+        exceeded.push(format!("PSNR {psnr:.2} < {min_psnr}"));
+    }
+    if let Some(min_ssim) = args.min_ssim.filter(|&min| ssim < min) {
+        exceeded.push(format!("SSIM {ssim:.5} < {min_ssim}"));
+    }
+    let mut exit_code = if exceeded.is_empty() {
+        println!("Within tolerance");
+        WITHIN_TOLERANCE
+    } else {
+        println!("Exceeds tolerance: {}", exceeded.join(", "));
+        DIFFERENT
+    };
+
+    if let Some(output) = &args.output {
+        match difference_image(&a, &b).save(output) {
+            Ok(()) => println!("Difference image saved to {output:?}"),
+            Err(e) => {
+                eprintln!("Could not save {output:?}: {e}");
+                exit_code = FAILED;
+            }
+        }
+    }
+    exit_code
+}
+
+/// Loads the image the same way as the viewer, with the orientation applied
+fn load(path: &Path) -> Option<RgbaImage> {
+    match load_edited_image(path, ImageEdits::default()) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            eprintln!("Could not load {path:?}: {}", describe(&e));
+            None
+        }
+    }
+}
+
+/// The differences of the color channels of two images, in levels from 0 to
+/// 255. The colors are composited over black, so that differences in
+/// transparency count as well, but the alpha channel itself doesn't dilute
+/// the errors of opaque images.
+struct Stats {
+    max_error: u8,
+    mean_error: f64,
+    mean_squared_error: f64,
+}
+
+impl Stats {
+    fn compare(a: &RgbaImage, b: &RgbaImage) -> Self {
+        let mut max_error = 0;
+        let mut sum = 0u64;
+        let mut sum_of_squares = 0u64;
+        for (a, b) in a.pixels().zip(b.pixels()) {
+            for channel in 0..3 {
+                let error =
+                    composited(a, channel).abs_diff(composited(b, channel));
+                max_error = max_error.max(error);
+                sum += error as u64;
+                sum_of_squares += error as u64 * error as u64;
+            }
+        }
+        let count =
+            (a.width() as usize * a.height() as usize * 3).max(1) as f64;
+        Stats {
+            max_error,
+            mean_error: sum as f64 / count,
+            mean_squared_error: sum_of_squares as f64 / count,
+        }
+    }
+
+    /// The peak signal-to-noise ratio in decibels, infinite for identical
+    /// images
+    fn psnr(&self) -> f64 {
+        if self.mean_squared_error == 0.0 {
+            f64::INFINITY
+        } else {
+            10.0 * (255.0 * 255.0 / self.mean_squared_error).log10()
+        }
+    }
+}
+
+/// The color channel of `pixel` composited over black
+fn composited(pixel: &Rgba<u8>, channel: usize) -> u8 {
+    ((pixel[channel] as u32 * pixel[3] as u32 + 127) / 255) as u8
+}
+
+/// The structural similarity of the luma of the images, averaged over
+/// overlapping windows. It's 1 for identical images.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let (w, h) = a.dimensions();
+    if w == 0 || h == 0 {
+        return 1.0;
+    }
+    let luma_a = luma(a);
+    let luma_b = luma(b);
+    let (window_w, window_h) = (w.min(SSIM_WINDOW), h.min(SSIM_WINDOW));
+    let mut total = 0.0;
+    let mut count = 0;
+    for y in window_starts(h, window_h) {
+        for x in window_starts(w, window_w) {
+            let pixels = (y..y + window_h).flat_map(|y| {
+                (x..x + window_w).map(move |x| (y * w + x) as usize)
+            });
+            let pairs = pixels.map(|i| (luma_a[i], luma_b[i]));
+            total += window_ssim(pairs, (window_w * window_h) as f64);
+            count += 1;
+        }
+    }
+    total / count as f64
+}
+
+fn window_ssim(pairs: impl Iterator<Item = (f64, f64)>, count: f64) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (mut sum_a, mut sum_b) = (0.0, 0.0);
+    let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        sum_a += a;
+        sum_b += b;
+        sum_aa += a * a;
+        sum_bb += b * b;
+        sum_ab += a * b;
+    }
+    let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+    let var_a = sum_aa / count - mean_a * mean_a;
+    let var_b = sum_bb / count - mean_b * mean_b;
+    let covariance = sum_ab / count - mean_a * mean_b;
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+/// The start positions of the windows along a side of `len` pixels. The
+/// last window ends at the edge.
+fn window_starts(len: u32, window: u32) -> Vec<u32> {
+    let last = len - window;
+    let mut starts: Vec<u32> = (0..=last).step_by(SSIM_STEP as usize).collect();
+    if starts.last() != Some(&last) {
+        starts.push(last);
+    }
+    starts
+}
+
+/// The luma of the pixels, premultiplied by their alpha
+fn luma(image: &RgbaImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|&Rgba([r, g, b, a])| {
+            let luma = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+            luma * a as f64 / 255.0
+        })
+        .collect()
+}
+
+/// An opaque image of the absolute differences of the color channels. A
+/// difference in transparency shows up in all three of them.
+fn difference_image(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    let mut result = RgbaImage::new(a.width(), a.height());
+    for ((out, a), b) in result.pixels_mut().zip(a.pixels()).zip(b.pixels()) {
+        let alpha_diff = a[3].abs_diff(b[3]);
+        let channel = |i: usize| a[i].abs_diff(b[i]).max(alpha_diff);
+        *out = Rgba([channel(0), channel(1), channel(2), 255]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(w: u32, h: u32) -> RgbaImage {
+        RgbaImage::from_fn(w, h, |x, y| {
+            Rgba([(x * 10) as u8, (y * 10) as u8, ((x + y) * 5) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn identical_images() {
+        let a = gradient(20, 13);
+        let stats = Stats::compare(&a, &a);
+        assert_eq!(stats.max_error, 0);
+        assert_eq!(stats.mean_error, 0.0);
+        assert_eq!(stats.psnr(), f64::INFINITY);
+        assert!((ssim(&a, &a) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn uniform_error() {
+        let a = gradient(20, 13);
+        let mut b = a.clone();
+        for pixel in b.pixels_mut() {
+            pixel[0] += 4;
+        }
+        let stats = Stats::compare(&a, &b);
+        assert_eq!(stats.max_error, 4);
+        // One of the three color channels is off by 4
+        assert!((stats.mean_error - 4.0 / 3.0).abs() < 1e-9);
+        let expected_psnr = 10.0 * (255.0f64 * 255.0 / (16.0 / 3.0)).log10();
+        assert!((stats.psnr() - expected_psnr).abs() < 1e-9);
+        let similarity = ssim(&a, &b);
+        assert!(similarity < 1.0 && similarity > 0.9, "{similarity}");
+    }
+
+    #[test]
+    fn transparency_counts_as_a_difference() {
+        let a = RgbaImage::from_pixel(2, 2, Rgba([200, 100, 0, 255]));
+        let b = RgbaImage::from_pixel(2, 2, Rgba([200, 100, 0, 0]));
+        assert_eq!(Stats::compare(&a, &b).max_error, 200);
+        // Fully transparent pixels are equal whatever their color
+        let c = RgbaImage::from_pixel(2, 2, Rgba([0, 50, 255, 0]));
+        assert_eq!(Stats::compare(&b, &c).max_error, 0);
+    }
+
+    #[test]
+    fn inverted_image_is_dissimilar() {
+        let a = gradient(20, 13);
+        let mut b = a.clone();
+        for pixel in b.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = 255 - *channel;
+            }
+        }
+        assert!(ssim(&a, &b) < 0.0);
+    }
+
+    #[test]
+    fn windows_cover_the_edges() {
+        assert_eq!(window_starts(8, 8), vec![0]);
+        assert_eq!(window_starts(13, 8), vec![0, 4, 5]);
+        assert_eq!(window_starts(16, 8), vec![0, 4, 8]);
+        // Images smaller than a window are compared as one window
+        let a = gradient(3, 2);
+        assert!((ssim(&a, &a) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn difference_image_shows_alpha() {
+        let a = RgbaImage::from_pixel(2, 1, Rgba([10, 20, 30, 255]));
+        let mut b = a.clone();
+        b.put_pixel(0, 0, Rgba([13, 20, 30, 255]));
+        b.put_pixel(1, 0, Rgba([10, 20, 30, 155]));
+        let diff = difference_image(&a, &b);
+        assert_eq!(diff.get_pixel(0, 0), &Rgba([3, 0, 0, 255]));
+        assert_eq!(diff.get_pixel(1, 0), &Rgba([100, 100, 100, 255]));
+    }
+}
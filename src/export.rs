@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, RgbaImage,
+};
+
+use crate::{
+    configuration::{Configuration, ExportFormat},
+    image_cache::image_loader::{
+        errors::*, is_stdin_image, load_edited_image, read_exif, ImageEdits,
+        Orientation,
+    },
+    orientation_saver::{replace_file, write_jpeg_exif},
+};
+
+/// Describes how images are saved, see the `[export]` config section.
+#[derive(Clone, Debug, Default)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    /// From 1 to 100
+    pub jpeg_quality: u8,
+    /// Larger images are scaled down to fit into this width and height.
+    pub max_size: Option<(u32, u32)>,
+    /// If false, the Exif data of the source is copied into JPEG files.
+    pub strip_metadata: bool,
+    /// Relative to the folder of the source, `None` means the same folder.
+    pub folder: Option<PathBuf>,
+    /// Replace an existing file of the same name instead of adding a number
+    /// to the new name. The source itself is never replaced.
+    pub overwrite: bool,
+}
+
+impl ExportSettings {
+    pub fn from_config(config: &Configuration) -> Self {
+        ExportSettings {
+            format: config.export_format(),
+            jpeg_quality: config.jpeg_quality(),
+            max_size: config.export_max_size().map(|size| (size, size)),
+            strip_metadata: config.strip_metadata(),
+            folder: config.export_folder(),
+            overwrite: false,
+        }
+    }
+}
+
+/// Loads the image at `source`, applies `edits` and saves the result
+/// according to `settings`. The name of the new file is the name of the
+/// source with `suffix` appended. Returns the path of the new file.
+pub fn export_image(
+    source: &Path,
+    edits: ImageEdits,
+    suffix: Option<&str>,
+    settings: &ExportSettings,
+) -> Result<PathBuf> {
+    let mut image = load_edited_image(source, edits)?;
+    if let Some((max_w, max_h)) = settings.max_size {
+        image = shrink_to_fit(image, max_w, max_h);
+    }
+    let data = match settings.format {
+        ExportFormat::Jpeg => {
+            let mut data = Vec::new();
+            let rgb = DynamicImage::ImageRgba8(image).into_rgb8();
+            JpegEncoder::new_with_quality(&mut data, settings.jpeg_quality)
+                .encode_image(&rgb)?;
+            if !settings.strip_metadata {
+                if let Ok(exif) = read_exif(source) {
+                    // The orientation is already applied to the pixels
+                    data = write_jpeg_exif(
+                        &data,
+                        Some(&exif),
+                        Orientation::Deg0,
+                        true,
+                    )?;
+                }
+            }
+            data
+        }
+        format => {
+            let mut data = Cursor::new(Vec::new());
+            image.write_to(&mut data, format.image_format())?;
+            data.into_inner()
+        }
+    };
+    let (target, file) = create_export_file(source, suffix, settings)?;
+    match file {
+        Some(mut file) => {
+            if let Err(e) = file.write_all(&data) {
+                drop(file);
+                let _ = fs::remove_file(&target);
+                return Err(e.into());
+            }
+        }
+        None => replace_file(&target, &data)?,
+    }
+    Ok(target)
+}
+
+/// Scales `image` down so that it's at most `max_w` wide and `max_h` high,
+/// keeping the aspect ratio.
+fn shrink_to_fit(image: RgbaImage, max_w: u32, max_h: u32) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    if w <= max_w && h <= max_h {
+        return image;
+    }
+    let scale = (max_w as f64 / w as f64).min(max_h as f64 / h as f64);
+    let new_w = ((w as f64 * scale).round() as u32).max(1);
+    let new_h = ((h as f64 * scale).round() as u32).max(1);
+    image::imageops::resize(&image, new_w, new_h, FilterType::Lanczos3)
+}
+
+/// The path that the export of `source` is saved at, unless a file of that
+/// name exists already. The name is derived from `source`, like
+/// `photo-crop.png` for `photo.jpg`.
+pub fn export_path(
+    source: &Path,
+    suffix: Option<&str>,
+    settings: &ExportSettings,
+) -> PathBuf {
+    let (folder, name) = export_folder_and_name(source, suffix, settings);
+    folder.join(format!("{name}.{}", settings.format.extension()))
+}
+
+fn export_folder_and_name(
+    source: &Path,
+    suffix: Option<&str>,
+    settings: &ExportSettings,
+) -> (PathBuf, String) {
+    let source_folder = source.parent().unwrap_or_else(|| Path::new(""));
+    let folder = match &settings.folder {
+        Some(folder) => source_folder.join(folder),
+        None => source_folder.to_owned(),
+    };
+    let stem = if is_stdin_image(source) {
+        "stdin".into()
+    } else {
+        source
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default()
+    };
+    let name = match suffix {
+        Some(suffix) => format!("{stem}-{suffix}"),
+        None => stem.into_owned(),
+    };
+    (folder, name)
+}
+
+/// Creates the file that the export of `source` is saved in. A number is
+/// added to the name to avoid overwriting an existing file, which also holds
+/// when several images are exported at the same time. With
+/// `settings.overwrite` the existing file is kept and no file is returned,
+/// it's replaced once the image is encoded.
+fn create_export_file(
+    source: &Path,
+    suffix: Option<&str>,
+    settings: &ExportSettings,
+) -> Result<(PathBuf, Option<fs::File>)> {
+    let (folder, name) = export_folder_and_name(source, suffix, settings);
+    fs::create_dir_all(&folder)?;
+    let ext = settings.format.extension();
+    let mut path = folder.join(format!("{name}.{ext}"));
+    let mut number = 2;
+    loop {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, Some(file))),
+            Err(e)
+                if e.kind() == io::ErrorKind::AlreadyExists
+                    && settings.overwrite =>
+            {
+                if is_same_file(source, &path) {
+                    return Err(Error::Msg(format!(
+                        "The image would replace its source {path:?}"
+                    )));
+                }
+                return Ok((path, None));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                path = folder.join(format!("{name}-{number}.{ext}"));
+                number += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
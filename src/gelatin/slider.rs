@@ -1,4 +1,8 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use cgmath::{Matrix4, Vector3};
 use glium::{
@@ -13,12 +17,16 @@ use crate::{
         misc::{
             Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
         },
-        window::RenderValidity,
+        window::{RenderValidity, Window},
         DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData,
         WidgetError,
     },
 };
 
+/// While dragging, the value change is reported after the knob rests for this
+/// long, so that moving through the steps doesn't report each of them.
+const DRAG_SETTLE_TIME: Duration = Duration::from_millis(300);
+
 struct SliderData {
     placement: WidgetPlacement,
     drawn_bounds: LogicalRect,
@@ -29,6 +37,12 @@ struct SliderData {
     click: bool,
     hover: bool,
     on_value_change: Option<Rc<dyn Fn()>>,
+    /// The step under the cursor while hovering or dragging
+    preview: Option<u32>,
+    on_preview_change: Option<Rc<dyn Fn()>>,
+    /// The time of the last value change during a drag that hasn't been
+    /// reported yet
+    unreported_change: Option<Instant>,
     shadow_color: [f32; 3],
 
     render_validity: RenderValidity,
@@ -48,6 +62,16 @@ impl WidgetData for SliderData {
     }
 }
 
+impl SliderData {
+    fn value_at(&self, cursor_x: f32) -> u32 {
+        let relative_cursor_x = cursor_x - self.drawn_bounds.pos.vec.x;
+        let proportion =
+            (relative_cursor_x / self.drawn_bounds.size.vec.x).clamp(0.0, 1.0);
+        let stepsf = self.steps as f32;
+        (proportion * (1.0 + 1.0 / stepsf) * (stepsf - 1.0)).floor() as u32
+    }
+}
+
 pub struct Slider {
     data: RefCell<SliderData>,
 }
@@ -66,6 +90,9 @@ impl Slider {
                 click: false,
                 hover: false,
                 on_value_change: None,
+                preview: None,
+                on_preview_change: None,
+                unreported_change: None,
                 shadow_color: [0.0, 0.0, 0.0],
                 render_validity: Default::default(),
                 //rendered_valid: false,
@@ -86,7 +113,10 @@ impl Slider {
         let prev_steps = borrowed.steps;
         let prev_value = borrowed.value;
         borrowed.steps = steps;
-        borrowed.value = value;
+        // The knob stays where the user drags it
+        if !borrowed.click {
+            borrowed.value = value;
+        }
         if prev_steps != steps || prev_value != value {
             borrowed.render_validity.invalidate();
         }
@@ -105,6 +135,29 @@ impl Slider {
         borrowed.on_value_change = Some(Rc::new(callback));
     }
 
+    /// The step under the cursor while the slider is hovered or dragged
+    pub fn preview(&self) -> Option<u32> {
+        self.data.borrow().preview
+    }
+
+    /// Called when the step under the cursor changes, see `preview`
+    pub fn set_on_preview_change<T: Fn() + 'static>(&self, callback: T) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.on_preview_change = Some(Rc::new(callback));
+    }
+
+    /// The middle of the top edge of `step` in logical pixels relative to the
+    /// window
+    pub fn step_position(&self, step: u32) -> LogicalVector {
+        let borrowed = self.data.borrow();
+        let bounds = borrowed.drawn_bounds;
+        let ratio = (step as f32 + 0.5) / borrowed.steps as f32;
+        LogicalVector::new(
+            bounds.left() + ratio * bounds.size.vec.x,
+            bounds.top(),
+        )
+    }
+
     pub fn set_shadow_color(&self, color: [f32; 3]) {
         let mut borrowed = self.data.borrow_mut();
         borrowed.shadow_color = color;
@@ -113,6 +166,26 @@ impl Slider {
 }
 
 impl Widget for Slider {
+    fn before_draw(&self, _window: &Window) -> NextUpdate {
+        let on_value_change;
+        {
+            let mut borrowed = self.data.borrow_mut();
+            let Some(change_time) = borrowed.unreported_change else {
+                return NextUpdate::Latest;
+            };
+            let report_time = change_time + DRAG_SETTLE_TIME;
+            if Instant::now() < report_time {
+                return NextUpdate::WaitUntil(report_time);
+            }
+            borrowed.unreported_change = None;
+            on_value_change = borrowed.on_value_change.clone();
+        }
+        if let Some(callback) = on_value_change {
+            callback();
+        }
+        NextUpdate::Latest
+    }
+
     fn draw(
         &self,
         target: &mut Frame,
@@ -213,62 +286,48 @@ impl Widget for Slider {
         if !self.data.borrow().visible {
             return;
         }
-        let check_value_change = || {
-            // We jugle around the `on_value_change` callback so that when it gets called,
-            // `self.data` is not borrowed.
-            let on_value_change;
-            {
-                let mut borrowed = self.data.borrow_mut();
-                borrowed.hover =
-                    borrowed.drawn_bounds.contains(event.cursor_pos);
-                if borrowed.click {
-                    let prev_value = borrowed.value;
-                    let relative_cursor_x = event.cursor_pos.vec.x
-                        - borrowed.drawn_bounds.pos.vec.x;
-                    let proportion = (relative_cursor_x
-                        / borrowed.drawn_bounds.size.vec.x)
-                        .max(0.0)
-                        .min(1.0);
-                    let stepsf = borrowed.steps as f32;
-                    borrowed.value =
-                        (proportion * (1.0 + 1.0 / stepsf) * (stepsf - 1.0))
-                            .floor() as u32;
-                    if borrowed.value != prev_value {
-                        borrowed.render_validity.invalidate();
-                        on_value_change = borrowed.on_value_change.clone();
-                    } else {
-                        on_value_change = None;
+        // The callbacks are called when `self.data` is not borrowed.
+        let mut callbacks: Vec<Rc<dyn Fn()>> = Vec::new();
+        {
+            let mut borrowed = self.data.borrow_mut();
+            match event.kind {
+                EventKind::MouseButton {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    borrowed.click = borrowed.hover;
+                }
+                EventKind::MouseButton {
+                    state: ElementState::Released,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    borrowed.click = false;
+                    // The change is reported as soon as the knob is released
+                    if borrowed.unreported_change.take().is_some() {
+                        callbacks.extend(borrowed.on_value_change.clone());
                     }
-                } else {
-                    on_value_change = None;
                 }
+                EventKind::MouseMove => (),
+                _ => return,
             }
-            if let Some(callback) = on_value_change {
-                callback();
+            borrowed.hover = borrowed.drawn_bounds.contains(event.cursor_pos);
+            let cursor_value = borrowed.value_at(event.cursor_pos.vec.x);
+            if borrowed.click && borrowed.value != cursor_value {
+                borrowed.value = cursor_value;
+                borrowed.unreported_change = Some(Instant::now());
+                borrowed.render_validity.invalidate();
             }
-        };
-        match event.kind {
-            EventKind::MouseMove => {
-                check_value_change();
+            let preview =
+                (borrowed.hover || borrowed.click).then_some(cursor_value);
+            if preview != borrowed.preview {
+                borrowed.preview = preview;
+                callbacks.extend(borrowed.on_preview_change.clone());
             }
-            EventKind::MouseButton {
-                state,
-                button: MouseButton::Left,
-                ..
-            } => match state {
-                ElementState::Pressed => {
-                    {
-                        let mut borrowed = self.data.borrow_mut();
-                        borrowed.click = borrowed.hover;
-                    }
-                    check_value_change();
-                }
-                ElementState::Released => {
-                    let mut borrowed = self.data.borrow_mut();
-                    borrowed.click = false;
-                }
-            },
-            _ => (),
+        }
+        for callback in callbacks {
+            callback();
         }
     }
 
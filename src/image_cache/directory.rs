@@ -1,12 +1,19 @@
 use std::{
-    ffi::{OsStr, OsString},
+    cmp::Ordering as CmpOrdering,
+    collections::HashSet,
     fmt, fs,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
 };
 
 use log::debug;
 
-use super::image_loader::is_file_supported;
+use super::{
+    filter::ImageFilter,
+    image_loader::{is_file_supported, is_stdin_image},
+    sort::{insert_image, sort_images, SortOrder},
+    watcher::{FolderChanges, FolderWatcher},
+};
 use crate::parallel_action::ParallelAction;
 
 #[derive(Debug)]
@@ -33,6 +40,15 @@ impl From<std::io::Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Larger batches of folder changes are applied by filtering and ordering
+/// the whole folder in the background, since each added image has to be
+/// compared with other images.
+const MAX_INCREMENTAL_CHANGES: usize = 32;
+
+/// The number of files and folders that `sibling_folder` looks at in the
+/// folders next to this one, at most. It's called on the UI thread.
+const MAX_SIBLING_ENTRIES: usize = 1000;
+
 macro_rules! step_to_next_img {
     ($this:ident, $iter:ident) => {
         for (i, file) in $iter {
@@ -45,6 +61,13 @@ macro_rules! step_to_next_img {
     };
 }
 
+/// An end of the list of images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FolderEnd {
+    First,
+    Last,
+}
+
 #[derive(Clone)]
 pub struct DirItem {
     pub path: PathBuf,
@@ -61,9 +84,25 @@ pub struct DirItem {
 // }
 
 pub struct Directory {
+    /// Empty when the files of a list are shown
     path: PathBuf,
+    /// The files and folders that are shown in place of a folder, in the
+    /// order of the images. `None` when the files of `path` are shown.
+    list: Option<Vec<PathBuf>>,
     files: Vec<DirItem>,
 
+    /// The number of subfolder levels below `path` whose files are listed as
+    /// well. `None` if only the files directly in `path` are listed.
+    max_depth: Option<u32>,
+
+    /// The order of the images. The files are always ordered by name.
+    sort_order: SortOrder,
+    /// The files that aren't shown as images of the folder
+    filter: ImageFilter,
+    /// Reports the files that are added to or removed from the folder.
+    /// `None` if the folder can't be watched.
+    watcher: Option<FolderWatcher>,
+
     /// Maps image indicies to indicies for the `files` vector.
     /// For example one could use it like `files[image_indicies[i]]`
     img_i_to_file_i: Vec<usize>,
@@ -73,10 +112,6 @@ pub struct Directory {
     /// when it will first be located by its file index.
     file_i_to_img_i: Vec<Option<usize>>,
 
-    /// A monotonically increasing integer used for identifying
-    /// each load request
-    current_req_id: u32,
-
     /// current file index
     /// This must never be exposed to users of this object.
     curr_file_idx: usize,
@@ -85,58 +120,104 @@ pub struct Directory {
     /// Use this value to index the `image_indicies` vector to find the apppropriate file index.
     curr_image_idx: usize,
 
+    /// The image to select once the folder has been filtered, since the
+    /// first and the last image depend on the filter and the sort order
+    pending_end: Option<FolderEnd>,
+
     //filter_state: Arc<Mutex<FilterState>>,
-    filter_action: ParallelAction<Vec<DirItem>, Vec<usize>>,
+    filter_action: ParallelAction<FilterInput, Vec<usize>>,
+
+    /// Changes whenever the images or their order change
+    version: u64,
+}
+
+struct FilterInput {
+    files: Vec<DirItem>,
+    sort_order: SortOrder,
+    filter: ImageFilter,
 }
 
-fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
-    |input: Vec<DirItem>| {
-        input
-            .into_iter()
+/// Returns the indices of the image files in the order of the images
+fn get_action() -> impl FnMut(FilterInput) -> Vec<usize> {
+    |input: FilterInput| {
+        let mut images = input
+            .files
+            .iter()
             .enumerate()
             .filter_map(|(i, item)| {
-                if is_file_supported(&item.path) {
+                if is_file_supported(&item.path)
+                    && input.filter.matches(&item.path)
+                {
                     Some(i)
                 } else {
                     None
                 }
             })
-            .collect()
+            .collect();
+        sort_images(&input.files, &mut images, input.sort_order);
+        images
     }
 }
 
+/// Returns a new integer for identifying a load request. The ids are unique
+/// across directories because the loaders share the priority request id.
+pub fn next_request_id() -> u32 {
+    static NEXT_REQUEST_ID: AtomicU32 = AtomicU32::new(1);
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 impl Directory {
     pub fn new() -> Self {
         Directory {
             path: PathBuf::new(),
+            list: None,
             files: Vec::new(),
+            max_depth: None,
+            sort_order: SortOrder::default(),
+            filter: ImageFilter::default(),
+            watcher: None,
             img_i_to_file_i: Vec::new(),
             file_i_to_img_i: Vec::new(),
             curr_file_idx: 0,
             curr_image_idx: 0,
-            current_req_id: 0,
+            pending_end: None,
             filter_action: ParallelAction::new(get_action()),
+            version: 0,
         }
     }
 
     pub fn change_directory(&mut self, path: &Path) -> Result<()> {
         if self.path != path {
             self.path = path.to_owned();
+            self.list = None;
             self.collect_directory()
         } else {
             Ok(())
         }
     }
 
-    pub fn change_directory_with_filename(
+    /// Shows the files of `entries` in the given order, in place of the files
+    /// of a folder. The files of the folders in `entries` are listed like the
+    /// files of a folder.
+    pub fn change_to_list(&mut self, entries: Vec<PathBuf>) -> Result<()> {
+        self.path = PathBuf::new();
+        self.list = Some(entries);
+        self.pending_end = None;
+        self.collect_directory()
+    }
+
+    /// Changes to the folder at `path` and selects `file`, which has to be
+    /// the path of the file as it's listed in that folder.
+    pub fn change_directory_with_file(
         &mut self,
         path: &Path,
-        filename: &OsStr,
+        file: &Path,
     ) -> Result<()> {
         self.change_directory(path)?;
-        // Look up the index of the filename in the directory
+        self.pending_end = None;
+        // Look up the index of the file in the directory
         for (index, desc) in self.files.iter().enumerate() {
-            if desc.path.file_name().unwrap() == filename {
+            if desc.path == file {
                 self.curr_file_idx = index;
                 self.set_image_index_from_file_index();
                 // If we already finished filtering somehow
@@ -147,14 +228,331 @@ impl Directory {
 
         Err(Error::Other(format!(
             "Could not find file {:?} in directory {:?}",
-            filename, path
+            file, path
         )))
     }
 
-    pub fn curr_filename(&self) -> Option<OsString> {
-        self.files
-            .get(self.curr_file_idx)
-            .and_then(|x| x.path.file_name().map(|x| x.to_owned()))
+    /// Selects the first or the last image of the folder. Until the folder
+    /// has been filtered, the first or the last supported file is selected.
+    pub fn select_end(&mut self, end: FolderEnd) {
+        let supported =
+            |(_, item): &(usize, &DirItem)| is_file_supported(&item.path);
+        let mut files = self.files.iter().enumerate();
+        let file = match end {
+            FolderEnd::First => files.find(supported),
+            FolderEnd::Last => files.rev().find(supported),
+        };
+        if let Some((index, _)) = file {
+            self.curr_file_idx = index;
+            self.set_image_index_from_file_index();
+        }
+        self.pending_end = Some(end);
+        if self.check_filter_ready() {
+            self.select_pending_end();
+        }
+    }
+
+    /// Returns true while the image selected by `select_end` isn't known yet
+    pub fn is_selecting_end(&mut self) -> bool {
+        self.check_filter_ready();
+        self.pending_end.is_some()
+    }
+
+    /// Returns the closest folder next to this one that has images, going
+    /// forward or backward in the natural order of the folder names, and
+    /// wrapping around at the ends if `wrap` is set. Returns `None` if there's
+    /// no such folder in the parent folder.
+    ///
+    /// The folders are only checked roughly, see `may_have_images`, so the
+    /// folder that is returned can turn out to have no images once it's
+    /// filtered.
+    pub fn sibling_folder(&self, forward: bool, wrap: bool) -> Option<PathBuf> {
+        let folder = fs::canonicalize(&self.path).ok()?;
+        let mut siblings: Vec<PathBuf> = fs::read_dir(folder.parent()?)
+            .ok()?
+            .flatten()
+            .filter(is_dir_entry)
+            .map(|entry| entry.path())
+            .collect();
+        siblings.sort_unstable_by(|a, b| natural_path_cmp(a, b));
+        let index = siblings.iter().position(|path| *path == folder)?;
+        let count = siblings.len();
+        let distances = if wrap {
+            count - 1
+        } else if forward {
+            count - 1 - index
+        } else {
+            index
+        };
+        let mut budget = MAX_SIBLING_ENTRIES;
+        (1..=distances)
+            .map(|distance| {
+                if forward {
+                    &siblings[(index + distance) % count]
+                } else {
+                    &siblings[(index + count - distance) % count]
+                }
+            })
+            .find(|sibling| self.may_have_images(sibling, &mut budget))
+            .cloned()
+    }
+
+    /// Returns true if `folder` may have images when listed like this
+    /// folder. The orientation filter isn't applied, since it would open
+    /// every file. Once `budget` files and folders have been looked at, the
+    /// folder is assumed to have images.
+    fn may_have_images(&self, folder: &Path, budget: &mut usize) -> bool {
+        let mut folders = vec![(folder.to_owned(), 0)];
+        while let Some((folder, depth)) = folders.pop() {
+            let Ok(entries) = fs::read_dir(&folder) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if *budget == 0 {
+                    return true;
+                }
+                *budget -= 1;
+                let path = entry.path();
+                if is_dir_entry(&entry) {
+                    if self.max_depth.is_some_and(|max| depth < max) {
+                        folders.push((path, depth + 1));
+                    }
+                } else if is_file_supported(&path)
+                    && self.filter.matches_name(&path)
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Returns true if the file at `path` would be listed as an image
+    pub fn is_image(&self, path: &Path) -> bool {
+        is_file_supported(path) && self.filter.matches(path)
+    }
+
+    /// The request id of the listed file at `path`
+    pub fn request_id_of(&self, path: &Path) -> Option<u32> {
+        let desc = self.files.iter().find(|desc| desc.path == path)?;
+        Some(desc.request_id)
+    }
+
+    /// Returns true if `file` is one of the listed files
+    pub fn contains_file(&self, file: &Path) -> bool {
+        self.files.iter().any(|desc| desc.path == file)
+    }
+
+    pub fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
+
+    /// Sets how many levels of subfolders are listed, see `max_depth`. When
+    /// the recursion is turned off, the folder of the current file becomes
+    /// the listed folder.
+    pub fn set_max_depth(&mut self, max_depth: Option<u32>) -> Result<()> {
+        if self.max_depth == max_depth {
+            return Ok(());
+        }
+        self.max_depth = max_depth;
+        if self.list.is_some() {
+            // The folders of the list are listed again to the new depth
+            return self.update_directory();
+        }
+        if self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        if max_depth.is_none() {
+            let parent = self
+                .curr_descriptor()
+                .and_then(|desc| desc.path.parent())
+                .map(Path::to_owned);
+            if let Some(parent) = parent {
+                self.path = parent;
+            }
+        }
+        self.update_directory()
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    /// Orders the images anew in the background. The current image stays
+    /// selected.
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        if self.sort_order == sort_order {
+            return;
+        }
+        self.sort_order = sort_order;
+        self.filter_files();
+    }
+
+    /// Narrows the images down to the files that match `filter`. The current
+    /// file stays selected even if it doesn't match.
+    pub fn set_filter(&mut self, filter: ImageFilter) {
+        self.filter = filter;
+        self.filter_files();
+    }
+
+    /// The changes in the folder since the last call
+    pub fn folder_changes(&self) -> FolderChanges {
+        match &self.watcher {
+            Some(watcher) => watcher.changes(),
+            None => FolderChanges::default(),
+        }
+    }
+
+    /// Applies the files that were created, renamed or removed to the list of
+    /// files, without listing the folder again. The current file stays
+    /// selected, or the one after it if it was removed. When the images are
+    /// already ordered, the changed files are added to and removed from the
+    /// images in place, so they stay available.
+    ///
+    /// Returns true if the list of files changed.
+    pub fn apply_folder_changes(
+        &mut self,
+        changes: &FolderChanges,
+    ) -> Result<bool> {
+        if changes.rescan {
+            self.update_directory()?;
+            return Ok(true);
+        }
+        let curr_path = self.curr_descriptor().map(|desc| desc.path.clone());
+        let in_place = changes.paths.len() <= MAX_INCREMENTAL_CHANGES
+            && self.check_filter_ready();
+        let mut changed = false;
+        for path in &changes.paths {
+            let listed = self.files.iter().position(|desc| desc.path == *path);
+            if path.is_dir() {
+                // The files of new subfolders are only found by listing
+                if self.max_depth.is_some() {
+                    self.update_directory()?;
+                    return Ok(true);
+                }
+            } else if path.is_file() {
+                if listed.is_none() && self.is_listed_location(path) {
+                    let index = self.files.partition_point(|desc| {
+                        listing_cmp(&self.path, &desc.path, path)
+                            == CmpOrdering::Less
+                    });
+                    let item = DirItem {
+                        path: path.clone(),
+                        request_id: next_request_id(),
+                    };
+                    self.insert_file(index, item, in_place);
+                    changed = true;
+                }
+            } else if let Some(index) = listed {
+                self.remove_file(index, in_place);
+                changed = true;
+            } else {
+                // A removed subfolder takes its files with it
+                for index in (0..self.files.len()).rev() {
+                    if self.files[index].path.starts_with(path) {
+                        self.remove_file(index, in_place);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            return Ok(false);
+        }
+        if let Some(curr_path) = curr_path {
+            if let Some(index) =
+                self.files.iter().position(|desc| desc.path == curr_path)
+            {
+                self.curr_file_idx = index;
+            } else {
+                // The files that are ordered before the removed one
+                let index = self.files.partition_point(|desc| {
+                    listing_cmp(&self.path, &desc.path, &curr_path)
+                        == CmpOrdering::Less
+                });
+                let count = self.files.len();
+                let supported = (index..count)
+                    .chain((0..index.min(count)).rev())
+                    .find(|&i| is_file_supported(&self.files[i].path));
+                self.curr_file_idx = supported.unwrap_or(0);
+            }
+        }
+        if in_place {
+            self.finished_filtering();
+        } else {
+            self.filter_files();
+        }
+        Ok(true)
+    }
+
+    /// Inserts `item` into the files at `index`. If `in_place` is set, it's
+    /// also added to the ordered images when it's an image.
+    fn insert_file(&mut self, index: usize, item: DirItem, in_place: bool) {
+        self.files.insert(index, item);
+        if !in_place {
+            return;
+        }
+        for file_i in &mut self.img_i_to_file_i {
+            if *file_i >= index {
+                *file_i += 1;
+            }
+        }
+        if self.is_image(&self.files[index].path) {
+            insert_image(
+                &self.files,
+                &mut self.img_i_to_file_i,
+                index,
+                self.sort_order,
+            );
+        }
+    }
+
+    /// Removes the file at `index`. If `in_place` is set, it's also removed
+    /// from the ordered images.
+    fn remove_file(&mut self, index: usize, in_place: bool) {
+        self.files.remove(index);
+        if !in_place {
+            return;
+        }
+        self.img_i_to_file_i.retain(|&file_i| file_i != index);
+        for file_i in &mut self.img_i_to_file_i {
+            if *file_i > index {
+                *file_i -= 1;
+            }
+        }
+    }
+
+    /// Returns true if a file at `path` belongs to the listed files
+    fn is_listed_location(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.path) else {
+            return false;
+        };
+        let depth = relative.components().count().saturating_sub(1);
+        match self.max_depth {
+            Some(max_depth) => depth <= max_depth as usize,
+            None => depth == 0,
+        }
+    }
+
+    /// Filters and orders the images of the current files anew in the
+    /// background
+    fn filter_files(&mut self) {
+        self.filter_action.give_input(FilterInput {
+            files: self.files.clone(),
+            sort_order: self.sort_order,
+            filter: self.filter.clone(),
+        });
+        self.img_i_to_file_i.clear();
+        self.file_i_to_img_i.clear();
+    }
+
+    /// Changes whenever the images or their order change
+    pub fn version(&self) -> u64 {
+        self.version
     }
 
     pub fn curr_descriptor(&self) -> Option<&DirItem> {
@@ -180,6 +578,10 @@ impl Directory {
     }
 
     pub fn jump_to_prev(&mut self) {
+        if self.step_image(-1) {
+            return;
+        }
+        self.pending_end = None;
         let skip = (self.files.len() - 1) - self.curr_file_idx;
         let iter = self
             .files
@@ -194,6 +596,10 @@ impl Directory {
     }
 
     pub fn jump_to_next(&mut self) {
+        if self.step_image(1) {
+            return;
+        }
+        self.pending_end = None;
         // Go forwards until a valid image is found or until we arrived back to the starting file
         let iter = self
             .files
@@ -206,6 +612,45 @@ impl Directory {
         step_to_next_img!(self, iter);
     }
 
+    /// Moves `offset` images forward in the order of the images, wrapping
+    /// around at the ends. Returns false without moving if the images haven't
+    /// been ordered yet.
+    fn step_image(&mut self, offset: isize) -> bool {
+        if !self.check_filter_ready() {
+            return false;
+        }
+        if self.img_i_to_file_i.is_empty() {
+            // Every file was filtered out
+            return true;
+        }
+        let img_idx = match self.file_i_to_img_i.get(self.curr_file_idx) {
+            Some(&Some(img_idx)) => {
+                let count = self.img_i_to_file_i.len() as isize;
+                (img_idx as isize + offset).rem_euclid(count) as usize
+            }
+            // The current file was filtered out, so continue with the closest
+            // image in the direction of the step
+            _ => {
+                let count = self.file_i_to_img_i.len();
+                let closest = (1..count).find_map(|distance| {
+                    let file_idx = if offset < 0 {
+                        self.curr_file_idx + count - distance
+                    } else {
+                        self.curr_file_idx + distance
+                    };
+                    self.file_i_to_img_i[file_idx % count]
+                });
+                let Some(img_idx) = closest else {
+                    return true;
+                };
+                img_idx
+            }
+        };
+        self.curr_image_idx = img_idx;
+        self.curr_file_idx = self.img_i_to_file_i[img_idx];
+        true
+    }
+
     /// Returns none when the folder hasn't finished filtering
     pub fn curr_img_index(&mut self) -> Option<usize> {
         if !self.check_filter_ready() {
@@ -237,17 +682,16 @@ impl Directory {
     }
 
     pub fn update_directory(&mut self) -> Result<()> {
-        let curr_filename = self.curr_filename();
-        let curr_filename = curr_filename.as_deref();
+        let curr_path = self.curr_descriptor().map(|desc| desc.path.clone());
         let curr_index = self.curr_file_idx;
         debug!(
-			"Directory: `update_directory`. Current filename: {:?}, curr_index: {:?}",
-			curr_filename, curr_index
+			"Directory: `update_directory`. Current path: {:?}, curr_index: {:?}",
+			curr_path, curr_index
 		);
         self.collect_directory()?;
-        if curr_filename.is_some() {
+        if curr_path.is_some() {
             for (index, desc) in self.files.iter().enumerate() {
-                if desc.path.file_name() == curr_filename {
+                if Some(&desc.path) == curr_path.as_ref() {
                     debug!("Found file the previously 'current' file in the directory.");
                     self.curr_file_idx = index;
                     self.set_image_index_from_file_index();
@@ -280,32 +724,10 @@ impl Directory {
     }
 
     pub fn collect_directory(&mut self) -> Result<()> {
-        let mut dir_files: Vec<_> = fs::read_dir(&self.path)?
-            .filter_map(|x| match x {
-                Ok(entry) => match entry.file_type() {
-                    Ok(file_type) => {
-                        if file_type.is_file() || file_type.is_symlink() {
-                            self.current_req_id += 1;
-                            Some(DirItem {
-                                path: entry.path(),
-                                request_id: self.current_req_id,
-                            })
-                        } else {
-                            None
-                        }
-                    }
-                    Err(_) => None,
-                },
-                Err(_) => None,
-            })
-            .collect();
-
-        dir_files.sort_unstable_by(|a, b| {
-            lexical_sort::natural_lexical_cmp(
-                &a.path.file_name().unwrap().to_string_lossy(),
-                &b.path.file_name().unwrap().to_string_lossy(),
-            )
-        });
+        let dir_files = match &self.list {
+            Some(entries) => self.list_entries(entries),
+            None => self.list_folder(&self.path)?,
+        };
 
         // Set the current file index to the first image
         for (i, item) in dir_files.iter().enumerate() {
@@ -314,27 +736,139 @@ impl Directory {
                 break;
             }
         }
-        self.filter_action.give_input(dir_files.clone());
+        self.filter_action.give_input(FilterInput {
+            files: dir_files.clone(),
+            sort_order: self.sort_order,
+            filter: self.filter.clone(),
+        });
         self.img_i_to_file_i.clear();
         self.file_i_to_img_i.clear();
         self.files = dir_files;
+
+        if self.list.is_some() {
+            self.watcher = None;
+            return Ok(());
+        }
+        let recursive = self.max_depth.is_some();
+        let watching = self
+            .watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.watches(&self.path, recursive));
+        if !watching {
+            self.watcher = FolderWatcher::new(&self.path, recursive)
+                .map_err(|e| debug!("Could not watch {:?}: {}", self.path, e))
+                .ok();
+        }
         Ok(())
     }
 
+    /// Returns the files of `root` and of its subfolders up to `max_depth`,
+    /// in the order of their paths
+    fn list_folder(&self, root: &Path) -> Result<Vec<DirItem>> {
+        let mut dir_files = Vec::new();
+        // Contains the canonical paths of the folders that were listed, so
+        // that symlinks which point to a parent folder are only followed once
+        let mut visited = HashSet::new();
+        if let Ok(canonical) = fs::canonicalize(root) {
+            visited.insert(canonical);
+        }
+        let mut folders = vec![(root.to_owned(), 0)];
+        while let Some((folder, depth)) = folders.pop() {
+            let entries = match fs::read_dir(&folder) {
+                Ok(entries) => entries,
+                // Unreadable subfolders are skipped
+                Err(e) if depth > 0 => {
+                    debug!("Could not list {:?}: {}", folder, e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                let is_dir = if file_type.is_symlink() {
+                    path.is_dir()
+                } else {
+                    file_type.is_dir()
+                };
+                if is_dir {
+                    let descend = self.max_depth.is_some_and(|max| depth < max);
+                    let first_visit = descend
+                        && fs::canonicalize(&path)
+                            .is_ok_and(|canonical| visited.insert(canonical));
+                    if first_visit {
+                        folders.push((path, depth + 1));
+                    }
+                } else if file_type.is_file() || file_type.is_symlink() {
+                    dir_files.push(DirItem {
+                        path,
+                        request_id: next_request_id(),
+                    });
+                }
+            }
+        }
+        dir_files.sort_unstable_by(|a, b| listing_cmp(root, &a.path, &b.path));
+        Ok(dir_files)
+    }
+
+    /// Returns the files of a list, with the folders replaced by their files.
+    /// Files that are given more than once are only listed the first time.
+    fn list_entries(&self, entries: &[PathBuf]) -> Vec<DirItem> {
+        let mut dir_files = Vec::new();
+        let mut listed = HashSet::new();
+        for entry in entries {
+            let files = if entry.is_file() || is_stdin_image(entry) {
+                vec![DirItem {
+                    path: entry.clone(),
+                    request_id: next_request_id(),
+                }]
+            } else if entry.is_dir() {
+                match self.list_folder(entry) {
+                    Ok(files) => files,
+                    Err(e) => {
+                        debug!("Could not list {:?}: {}", entry, e);
+                        continue;
+                    }
+                }
+            } else {
+                debug!("Skipping {:?}, which is not a file", entry);
+                continue;
+            };
+            dir_files.extend(
+                files
+                    .into_iter()
+                    .filter(|item| listed.insert(item.path.clone())),
+            );
+        }
+        dir_files
+    }
+
     fn finished_filtering(&mut self) {
+        self.version += 1;
+        // The images aren't in the order of the files when they are sorted
         self.file_i_to_img_i.clear();
-        self.file_i_to_img_i.reserve(self.files.len());
-        let mut last_file_i: isize = -1;
-        for (curr_img_i, &curr_file_i) in
-            self.img_i_to_file_i.iter().enumerate()
-        {
-            for _ in (last_file_i + 1) as usize..curr_file_i {
-                self.file_i_to_img_i.push(None);
-            }
-            self.file_i_to_img_i.push(Some(curr_img_i));
-            last_file_i = curr_file_i as isize;
+        self.file_i_to_img_i.resize(self.files.len(), None);
+        for (img_i, &file_i) in self.img_i_to_file_i.iter().enumerate() {
+            self.file_i_to_img_i[file_i] = Some(img_i);
         }
         self.set_image_index_from_file_index();
+        self.select_pending_end();
+    }
+
+    fn select_pending_end(&mut self) {
+        let Some(end) = self.pending_end.take() else {
+            return;
+        };
+        let img_idx = match end {
+            FolderEnd::First => 0,
+            FolderEnd::Last => self.img_i_to_file_i.len().saturating_sub(1),
+        };
+        if let Some(&file_idx) = self.img_i_to_file_i.get(img_idx) {
+            self.curr_file_idx = file_idx;
+            self.curr_image_idx = img_idx;
+        }
     }
 
     fn set_image_index_from_file_index(&mut self) {
@@ -354,3 +888,135 @@ impl Directory {
         self.filter_action.is_ready()
     }
 }
+
+/// Returns true if `entry` is a folder or a symlink to a folder
+fn is_dir_entry(entry: &fs::DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(file_type) if file_type.is_symlink() => entry.path().is_dir(),
+        Ok(file_type) => file_type.is_dir(),
+        Err(_) => false,
+    }
+}
+
+/// The order of the files of the folder `root`. The files are ordered by
+/// their path relative to the folder, so the files of a subfolder follow each
+/// other.
+fn listing_cmp(root: &Path, a: &Path, b: &Path) -> CmpOrdering {
+    let a = a.strip_prefix(root).unwrap_or(a);
+    let b = b.strip_prefix(root).unwrap_or(b);
+    natural_path_cmp(a, b)
+}
+
+/// Compares paths component by component, ordering numbers in the names by
+/// their value
+fn natural_path_cmp(a: &Path, b: &Path) -> CmpOrdering {
+    let mut a = a.components();
+    let mut b = b.components();
+    loop {
+        match (a.next(), b.next()) {
+            (Some(a), Some(b)) => {
+                let ordering = lexical_sort::natural_lexical_cmp(
+                    &a.as_os_str().to_string_lossy(),
+                    &b.as_os_str().to_string_lossy(),
+                );
+                if ordering != CmpOrdering::Equal {
+                    return ordering;
+                }
+            }
+            (None, Some(_)) => return CmpOrdering::Less,
+            (Some(_), None) => return CmpOrdering::Greater,
+            (None, None) => return CmpOrdering::Equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    /// Waits for the images of `dir` to be ordered on the filter thread
+    fn wait_for_images(dir: &mut Directory) -> Vec<String> {
+        let start = Instant::now();
+        while dir.image_count().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        image_names(dir)
+    }
+
+    fn image_names(dir: &mut Directory) -> Vec<String> {
+        (0..dir.image_count().unwrap())
+            .map(|i| {
+                let path = &dir.image_by_index(i).unwrap().path;
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folder_changes_keep_the_images_ordered() {
+        let folder = TempDir::new("directory");
+        for name in ["a2.png", "a10.png", "b.txt"] {
+            fs::write(folder.join(name), b"").unwrap();
+        }
+        let mut dir = Directory::new();
+        dir.set_sort_order(SortOrder {
+            descending: true,
+            ..SortOrder::default()
+        });
+        dir.change_directory_with_file(&folder, &folder.join("a10.png"))
+            .unwrap();
+        assert_eq!(wait_for_images(&mut dir), ["a10.png", "a2.png"]);
+
+        fs::write(folder.join("a3.png"), b"").unwrap();
+        fs::write(folder.join("c.png"), b"").unwrap();
+        fs::remove_file(folder.join("a2.png")).unwrap();
+        let changes = FolderChanges {
+            paths: ["a3.png", "c.png", "a2.png"]
+                .iter()
+                .map(|name| folder.join(name))
+                .collect(),
+            ..FolderChanges::default()
+        };
+        assert!(dir.apply_folder_changes(&changes).unwrap());
+        // The images are available right away
+        assert_eq!(image_names(&mut dir), ["c.png", "a10.png", "a3.png"]);
+        assert_eq!(dir.curr_img_index(), Some(1));
+    }
+
+    #[test]
+    fn paths_order_naturally_by_component() {
+        let cmp =
+            |a: &str, b: &str| natural_path_cmp(Path::new(a), Path::new(b));
+        assert_eq!(cmp("a2.png", "a10.png"), CmpOrdering::Less);
+        assert_eq!(cmp("a10.png", "A3.png"), CmpOrdering::Greater);
+        assert_eq!(cmp("x/a.png", "x/a.png"), CmpOrdering::Equal);
+        // A folder's files follow each other, even when a file of the
+        // parent has a name in between
+        assert_eq!(cmp("a/z.png", "a-b.png"), CmpOrdering::Less);
+        assert_eq!(cmp("2/z.png", "10/a.png"), CmpOrdering::Less);
+        assert_eq!(cmp("a", "a/b.png"), CmpOrdering::Less);
+    }
+
+    #[test]
+    fn sibling_folders_without_images_are_skipped() {
+        let folder = TempDir::new("siblings");
+        let parent = fs::canonicalize(&*folder).unwrap();
+        for (folder, file) in
+            [("1", "a.png"), ("2", "b.txt"), ("10", "c.png"), ("3", "")]
+        {
+            fs::create_dir_all(parent.join(folder)).unwrap();
+            if !file.is_empty() {
+                fs::write(parent.join(folder).join(file), b"").unwrap();
+            }
+        }
+        let mut dir = Directory::new();
+        dir.change_directory(&parent.join("1")).unwrap();
+        assert_eq!(dir.sibling_folder(true, false), Some(parent.join("10")));
+        assert_eq!(dir.sibling_folder(false, false), None);
+        assert_eq!(dir.sibling_folder(false, true), Some(parent.join("10")));
+    }
+}
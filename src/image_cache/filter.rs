@@ -0,0 +1,200 @@
+//! Narrows the images of a folder down to the ones that match a file name
+//! pattern, a list of extensions or an orientation. Like the sort keys, the
+//! filter is applied on the thread that filters the folder for images, since
+//! the orientation needs every file to be opened.
+
+use std::path::Path;
+
+use regex::{Regex, RegexBuilder};
+
+use super::image_loader::{detect_orientation, is_stdin_image};
+use crate::configuration::PictureOrientation;
+
+/// A pattern that the file names are matched against, ignoring case
+#[derive(Clone, Debug)]
+pub enum NamePattern {
+    /// `*` stands for any number of characters and `?` for a single one
+    Wildcard(Vec<char>),
+    /// Matches if the regular expression matches any part of the name
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub fn wildcard(pattern: &str) -> Self {
+        NamePattern::Wildcard(pattern.to_lowercase().chars().collect())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(NamePattern::Regex)
+            .map_err(|e| format!("invalid regular expression '{pattern}': {e}"))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Wildcard(pattern) => {
+                let name: Vec<char> = name.to_lowercase().chars().collect();
+                wildcard_match(pattern, &name)
+            }
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// The conditions that the images of a folder have to meet to be shown. The
+/// default filter shows every image.
+#[derive(Clone, Debug, Default)]
+pub struct ImageFilter {
+    pub name: Option<NamePattern>,
+    /// Lowercase extensions without the dot. Empty to allow every extension.
+    pub extensions: Vec<String>,
+    pub orientation: Option<PictureOrientation>,
+}
+
+impl ImageFilter {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.extensions.is_empty()
+            && self.orientation.is_none()
+    }
+
+    /// Returns whether the image at `path` is shown. Opens the file if the
+    /// filter has an orientation.
+    pub fn matches(&self, path: &Path) -> bool {
+        // The image from the standard input has neither a name nor a file
+        if is_stdin_image(path) {
+            return true;
+        }
+        if !self.matches_name(path) {
+            return false;
+        }
+        match self.orientation {
+            Some(orientation) => has_orientation(path, orientation),
+            None => true,
+        }
+    }
+
+    /// Returns whether the name and the extension of `path` match, without
+    /// opening the file
+    pub fn matches_name(&self, path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(pattern) = &self.name {
+            if !pattern.matches(&name) {
+                return false;
+            }
+        }
+        if !self.extensions.is_empty() {
+            let extension = path.extension().unwrap_or_default();
+            let extension = extension.to_string_lossy().to_lowercase();
+            if !self.extensions.contains(&extension) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Turns extensions like `JPG` or `.jpg` into the form that `ImageFilter`
+/// expects
+pub fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Square images are neither portrait nor landscape
+fn has_orientation(path: &Path, orientation: PictureOrientation) -> bool {
+    let Ok((mut width, mut height)) = image::image_dimensions(path) else {
+        return false;
+    };
+    // The image is shown rotated by its Exif orientation
+    if detect_orientation(path).is_ok_and(|o| o.swaps_dimensions()) {
+        std::mem::swap(&mut width, &mut height);
+    }
+    match orientation {
+        PictureOrientation::Portrait => height > width,
+        PictureOrientation::Landscape => width > height,
+    }
+}
+
+/// Matches `name` against `pattern` where `*` stands for any number of
+/// characters and `?` for a single character.
+///
+/// When a character doesn't match, only the last `*` is tried with one more
+/// character, since the earlier ones can't lead to another match. This keeps
+/// the time linear in the length of the name for each `*`.
+pub fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` and the position in the name that it
+    // currently extends to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        wildcard_match(&pattern, &name)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.png", "photo.png"));
+        assert!(matches("*.png", ".png"));
+        assert!(!matches("*.png", "photo.png.txt"));
+        assert!(matches("img_??.jpg", "img_07.jpg"));
+        assert!(!matches("img_??.jpg", "img_7.jpg"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*a*b*", "xxbxxaxx"));
+        assert!(matches("a*b*c", "abbbc"));
+        assert!(matches("**", ""));
+        assert!(!matches("?", ""));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+        assert!(matches("fötö*", "fötö.png"));
+    }
+
+    #[test]
+    fn many_stars_are_fast() {
+        let pattern = "*a".repeat(30);
+        let name = "a".repeat(29) + "b";
+        assert!(!matches(&pattern, &name));
+        assert!(matches(&pattern, &"a".repeat(30)));
+    }
+
+    #[test]
+    fn name_patterns_ignore_case() {
+        let filter = ImageFilter {
+            name: Some(NamePattern::wildcard("IMG_*")),
+            extensions: vec!["jpg".into()],
+            orientation: None,
+        };
+        assert!(filter.matches(Path::new("/photos/img_1.JPG")));
+        assert!(!filter.matches(Path::new("/photos/img_1.png")));
+        assert!(!filter.matches(Path::new("/img_photos/1.jpg")));
+    }
+}
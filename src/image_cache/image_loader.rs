@@ -1,24 +1,33 @@
 use std::{
+    borrow::Cow,
     fs,
-    io::{BufReader, Read},
+    io::{self, BufRead, BufReader, Cursor, Read, Seek},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
         mpsc::{channel, Receiver, Sender, TryRecvError},
-        Arc, Mutex,
+        Arc, Mutex, OnceLock,
     },
     thread,
+    time::SystemTime,
 };
 
 use image::{
     self,
-    codecs::{gif::GifDecoder, png::PngDecoder},
-    AnimationDecoder, ImageFormat,
+    codecs::{gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder},
+    imageops::{
+        flip_horizontal_in_place, flip_vertical_in_place, rotate180_in_place,
+        rotate270, rotate90,
+    },
+    AnimationDecoder, DynamicImage, ImageBuffer, ImageFormat, Pixel,
 };
 use resvg::{
     tiny_skia::{Pixmap, Transform},
     usvg::{self, fontdb::Database},
 };
+use serde::{Deserialize, Serialize};
+
+use super::thumbnail_store::ThumbnailStore;
 
 pub mod errors {
     use std::io;
@@ -46,25 +55,95 @@ pub mod errors {
 
 use self::errors::*;
 
-/// We want to prevent prefetch operations taking place when the target image is not yet loaded.
-/// To implement this we define a variable that is read by the loader threads and
-/// which will only carry out the request if the focused request id matches their request or
-/// if the focused is set to `NON_EXISTENT_REQUEST_ID`
-pub static PRIORITY_REQUEST_ID: AtomicU32 = AtomicU32::new(0); // The first request usually
 pub const NON_EXISTENT_REQUEST_ID: u32 = std::u32::MAX;
 
+/// The path that stands for the image read from the standard input
+pub const STDIN_PATH: &str = "-";
+
+/// The encoded image read from the standard input, and when it was read
+static STDIN_IMAGE: OnceLock<(Vec<u8>, SystemTime)> = OnceLock::new();
+
 pub enum ImgFormat {
     Image(ImageFormat),
     Svg,
 }
 
+/// Where the encoded bytes of an image are read from
+enum ImageSource<'a> {
+    File(&'a Path),
+    /// The whole encoded image, which isn't stored in a file
+    Memory(&'static [u8]),
+}
+
+trait BufReadSeek: BufRead + Seek {}
+impl<T: BufRead + Seek> BufReadSeek for T {}
+
+impl<'a> ImageSource<'a> {
+    /// The image from the standard input for `STDIN_PATH` once it has been
+    /// read, otherwise the file at `path`
+    fn of(path: &'a Path) -> Self {
+        match STDIN_IMAGE.get() {
+            Some((data, _)) if path == Path::new(STDIN_PATH) => {
+                ImageSource::Memory(data)
+            }
+            _ => ImageSource::File(path),
+        }
+    }
+
+    fn reader(&self) -> io::Result<Box<dyn BufReadSeek>> {
+        match *self {
+            ImageSource::File(path) => {
+                Ok(Box::new(BufReader::new(fs::File::open(path)?)))
+            }
+            ImageSource::Memory(data) => Ok(Box::new(Cursor::new(data))),
+        }
+    }
+
+    fn bytes(&self) -> io::Result<Cow<'static, [u8]>> {
+        match *self {
+            ImageSource::File(path) => Ok(Cow::Owned(fs::read(path)?)),
+            ImageSource::Memory(data) => Ok(Cow::Borrowed(data)),
+        }
+    }
+}
+
+/// Reads the whole standard input as the image that `STDIN_PATH` stands for
+pub fn read_stdin_image() -> io::Result<()> {
+    let mut data = Vec::new();
+    io::stdin().lock().read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the standard input is empty",
+        ));
+    }
+    let _ = STDIN_IMAGE.set((data, SystemTime::now()));
+    Ok(())
+}
+
+/// Returns true if `path` stands for the image read from the standard input
+pub fn is_stdin_image(path: &Path) -> bool {
+    matches!(ImageSource::of(path), ImageSource::Memory(_))
+}
+
+/// The time the file at `path` was last modified. The image from the standard
+/// input never changes after it was read.
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    match STDIN_IMAGE.get() {
+        Some((_, read_time)) if is_stdin_image(path) => Some(*read_time),
+        _ => fs::metadata(path).ok()?.modified().ok(),
+    }
+}
+
 /// These values define the transformation for a pixel array which is to be displayed.
 ///
 /// The default case is when the 0th row is at the top and the 0th column is at the left side of the
 /// image. This is represented by the value `Deg0`. All other cases must be interpreted as relative
 /// to this. The rotation part is counter-clockwise. When there's a flip it's always interpreted as
 /// if it happened after the rotation.
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize,
+)]
 pub enum Orientation {
     /// Exif 1
     #[default]
@@ -92,51 +171,184 @@ pub enum Orientation {
     Deg90,
 }
 
+impl Orientation {
+    /// According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        match value {
+            1 => Some(Orientation::Deg0),
+            2 => Some(Orientation::Deg0HorFlip),
+            3 => Some(Orientation::Deg180),
+            4 => Some(Orientation::Deg180HorFlip),
+            5 => Some(Orientation::Deg90VerFlip),
+            6 => Some(Orientation::Deg270),
+            7 => Some(Orientation::Deg270VerFlip),
+            8 => Some(Orientation::Deg90),
+            _ => None,
+        }
+    }
+
+    pub fn to_exif(self) -> u16 {
+        match self {
+            Orientation::Deg0 => 1,
+            Orientation::Deg0HorFlip => 2,
+            Orientation::Deg180 => 3,
+            Orientation::Deg180HorFlip => 4,
+            Orientation::Deg90VerFlip => 5,
+            Orientation::Deg270 => 6,
+            Orientation::Deg270VerFlip => 7,
+            Orientation::Deg90 => 8,
+        }
+    }
+
+    /// Returns the number of counter-clockwise quarter turns, and whether
+    /// they are followed by a horizontal flip. (A vertical flip is a
+    /// horizontal flip after a half turn.)
+    fn to_parts(self) -> (u8, bool) {
+        match self {
+            Orientation::Deg0 => (0, false),
+            Orientation::Deg0HorFlip => (0, true),
+            Orientation::Deg90 => (1, false),
+            Orientation::Deg270VerFlip => (1, true),
+            Orientation::Deg180 => (2, false),
+            Orientation::Deg180HorFlip => (2, true),
+            Orientation::Deg270 => (3, false),
+            Orientation::Deg90VerFlip => (3, true),
+        }
+    }
+
+    fn from_parts(quarter_turns: u8, hor_flip: bool) -> Orientation {
+        match (quarter_turns % 4, hor_flip) {
+            (0, false) => Orientation::Deg0,
+            (0, true) => Orientation::Deg0HorFlip,
+            (1, false) => Orientation::Deg90,
+            (1, true) => Orientation::Deg270VerFlip,
+            (2, false) => Orientation::Deg180,
+            (2, true) => Orientation::Deg180HorFlip,
+            (3, false) => Orientation::Deg270,
+            _ => Orientation::Deg90VerFlip,
+        }
+    }
+
+    /// Returns the orientation that results from first applying `self` and
+    /// then `next`.
+    pub fn then(self, next: Orientation) -> Orientation {
+        let (turns, flip) = self.to_parts();
+        let (next_turns, next_flip) = next.to_parts();
+        // A flip reverses the direction of the rotations that follow it.
+        let turns = if flip {
+            turns + 4 - next_turns
+        } else {
+            turns + next_turns
+        };
+        Orientation::from_parts(turns, flip != next_flip)
+    }
+
+    /// True if the width and the height of the image are swapped when shown
+    pub fn swaps_dimensions(self) -> bool {
+        self.to_parts().0 % 2 == 1
+    }
+}
+
+/// Transforms the pixel array so that it appears the way `orientation` describes.
+pub fn apply_orientation<P>(
+    mut image: ImageBuffer<P, Vec<P::Subpixel>>,
+    orientation: Orientation,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+{
+    // Note: the imageops functions use clockwise rotation whereas the
+    // `Orientation` type describes counter-clockwise rotation.
+    match orientation {
+        Orientation::Deg0 => image,
+        Orientation::Deg0HorFlip => {
+            flip_horizontal_in_place(&mut image);
+            image
+        }
+        Orientation::Deg90 => rotate270(&image),
+        Orientation::Deg90VerFlip => {
+            let mut result = rotate270(&image);
+            flip_vertical_in_place(&mut result);
+            result
+        }
+        Orientation::Deg180 => {
+            rotate180_in_place(&mut image);
+            image
+        }
+        Orientation::Deg180HorFlip => {
+            // This is identical to just a vertical flip with no rotation.
+            flip_vertical_in_place(&mut image);
+            image
+        }
+        Orientation::Deg270 => rotate90(&image),
+        Orientation::Deg270VerFlip => {
+            let mut result = rotate90(&image);
+            flip_vertical_in_place(&mut result);
+            result
+        }
+    }
+}
+
 /// Detects the format of an image file. It looks at the first 512 bytes;
 /// if that fails, it uses the file ending.
 pub fn detect_format(path: &Path) -> Result<ImgFormat> {
-    let mut file = fs::File::open(path)?;
-    let mut file_start_bytes = [0; 512];
+    if let ImageSource::Memory(data) = ImageSource::of(path) {
+        return detect_data_format(data);
+    }
+    let file = fs::File::open(path)?;
+    let mut file_start_bytes = Vec::with_capacity(512);
 
-    // Try to detect the format from the first 512 bytes
-    if file.read_exact(&mut file_start_bytes).is_ok() {
-        if path.extension() == Some(std::ffi::OsStr::new("svg")) {
-            return Ok(ImgFormat::Svg);
-        }
-        if let Ok(format) = image::guess_format(&file_start_bytes) {
-            return Ok(ImgFormat::Image(format));
-        }
+    // Try to detect the format from the first 512 bytes, which may be the
+    // whole file
+    file.take(512).read_to_end(&mut file_start_bytes)?;
+    if let Ok(format) = image::guess_format(&file_start_bytes) {
+        return Ok(ImgFormat::Image(format));
+    }
+    // SVG files are text without a fixed signature
+    let is_svg = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return Ok(ImgFormat::Svg);
     }
 
     // If that didn't work, try to detect the format from the file ending
     Ok(ImgFormat::Image(ImageFormat::from_path(path)?))
 }
 
+/// Detects the format of an image without a file ending to fall back on
+fn detect_data_format(data: &[u8]) -> Result<ImgFormat> {
+    let start = &data[..data.len().min(512)];
+    if let Ok(format) = image::guess_format(start) {
+        return Ok(ImgFormat::Image(format));
+    }
+    if String::from_utf8_lossy(start).contains("<svg") {
+        return Ok(ImgFormat::Svg);
+    }
+    Err(Error::Msg(
+        "Could not detect the format of the image".into(),
+    ))
+}
+
+/// Reads the Exif data of the image at `path`
+pub fn read_exif(path: &Path) -> Result<exif::Exif> {
+    let mut reader = ImageSource::of(path).reader()?;
+    Ok(exif::Reader::new().read_from_container(&mut reader)?)
+}
+
 pub fn detect_orientation(path: &Path) -> Result<Orientation> {
-    let file = std::fs::File::open(path)?;
-    let mut bufreader = std::io::BufReader::new(&file);
-    let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader)?;
+    let exif = read_exif(path)?;
     if let Some(orientation) =
         exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
     {
         if let exif::Value::Short(ref shorts) = orientation.value {
             if let Some(&exif_orientation) = shorts.first() {
-                // According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
-                match exif_orientation {
-                    1 => Ok(Orientation::Deg0),
-                    2 => Ok(Orientation::Deg0HorFlip),
-                    3 => Ok(Orientation::Deg180),
-                    4 => Ok(Orientation::Deg180HorFlip),
-                    5 => Ok(Orientation::Deg90VerFlip),
-                    6 => Ok(Orientation::Deg270),
-                    7 => Ok(Orientation::Deg270VerFlip),
-                    8 => Ok(Orientation::Deg90),
-                    _ => {
+                Ok(Orientation::from_exif(exif_orientation).unwrap_or_else(
+                    || {
                         eprintln!("Invalid Exif orientation. Using default orientation.");
-                        Ok(Orientation::Deg0)
-                    }
-                }
+                        Orientation::Deg0
+                    },
+                ))
             } else {
                 Ok(Orientation::Deg0)
             }
@@ -152,7 +364,7 @@ pub fn simple_load_image(
     path: &Path,
     image_format: ImageFormat,
 ) -> Result<image::RgbaImage> {
-    let reader = BufReader::new(fs::File::open(path)?);
+    let reader = ImageSource::of(path).reader()?;
     Ok(image::load(reader, image_format)?.into_rgba8())
 }
 
@@ -161,15 +373,15 @@ pub fn load_gif(
     path: &Path,
     req_id: u32,
 ) -> Result<impl Iterator<Item = Result<LoadResult>>> {
-    let file = fs::File::open(path)?;
-    let decoder = GifDecoder::new(file)?;
+    let reader = ImageSource::of(path).reader()?;
+    let decoder = GifDecoder::new(reader)?;
     Ok(load_animation(req_id, decoder))
 }
 
 /// Parse, render and gather an SVG into a ImageBuffer<Rgba>
 pub fn load_svg(path: &std::path::Path) -> Result<image::RgbaImage> {
     let opt = usvg::Options::default();
-    let data = std::fs::read(path)?;
+    let data = ImageSource::of(path).bytes()?;
     let mut font_db = Database::new();
 
     font_db.load_system_fonts();
@@ -216,8 +428,8 @@ where
             }
         }
         ImgFormat::Image(ImageFormat::Png) => {
-            let file = fs::File::open(path)?;
-            let decoder = PngDecoder::new(file)?;
+            let reader = ImageSource::of(path).reader()?;
+            let decoder = PngDecoder::new(reader)?;
             if decoder.is_apng() {
                 let mut animation = load_animation(req_id, decoder.apng());
                 if allow_animation {
@@ -260,6 +472,118 @@ where
     Ok(())
 }
 
+/// A rectangle of pixels to keep from an image
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// Changes made by the user that are applied to an image after it's loaded
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ImageEdits {
+    /// Applied on top of the orientation stored in the file
+    pub orientation: Orientation,
+    /// Applied after the orientation
+    pub crop: Option<CropRect>,
+}
+
+impl ImageEdits {
+    pub fn apply(
+        &self,
+        image: image::RgbaImage,
+        file_orientation: Orientation,
+    ) -> image::RgbaImage {
+        let orientation = file_orientation.then(self.orientation);
+        let mut image = apply_orientation(image, orientation);
+        match self.crop {
+            Some(CropRect { x, y, w, h }) => {
+                image::imageops::crop(&mut image, x, y, w, h).to_image()
+            }
+            None => image,
+        }
+    }
+}
+
+/// Loads the first frame of the image at `path` with its orientation and
+/// `edits` applied.
+pub fn load_edited_image(
+    path: &Path,
+    edits: ImageEdits,
+) -> Result<image::RgbaImage> {
+    let mut result = None;
+    complex_load_image(path, false, 0, |frame| {
+        if let LoadResult::Frame {
+            image, orientation, ..
+        } = frame
+        {
+            result = Some(edits.apply(image, orientation));
+        }
+        Ok(())
+    })?;
+    result.ok_or_else(|| Error::Msg(format!("Could not load {path:?}")))
+}
+
+/// Loads the first frame of the image at `path` with its orientation applied,
+/// scaled down to fit into a square of `size` pixels.
+///
+/// The thumbnail is taken from the shared thumbnail cache if it's up to date
+/// there, otherwise it's generated and added to the cache.
+pub fn load_thumbnail(path: &Path, size: u32) -> Result<image::RgbaImage> {
+    let store = match ImageSource::of(path) {
+        ImageSource::File(_) => ThumbnailStore::for_size(size),
+        // There's no file to store the thumbnail of
+        ImageSource::Memory(_) => None,
+    };
+    if let Some(thumbnail) = store.as_ref().and_then(|store| store.load(path)) {
+        return Ok(fit_into_square(thumbnail, size));
+    }
+    let generated_size = store.as_ref().map_or(size, ThumbnailStore::size);
+    let thumbnail = fit_into_square(
+        decode_for_thumbnail(path, generated_size)?,
+        generated_size,
+    );
+    if let Some(store) = &store {
+        if let Err(e) = store.save(path, &thumbnail) {
+            eprintln!("Could not save the thumbnail of {path:?}: {e}");
+        }
+    }
+    Ok(fit_into_square(thumbnail, size))
+}
+
+/// Decodes the image, possibly at a reduced size that is still larger than
+/// `size`
+fn decode_for_thumbnail(path: &Path, size: u32) -> Result<image::RgbaImage> {
+    match detect_format(path)? {
+        ImgFormat::Image(ImageFormat::Jpeg) => {
+            // The decoder can skip most of the work for large photos
+            let reader = ImageSource::of(path).reader()?;
+            let mut decoder = JpegDecoder::new(reader)?;
+            let scaled_size = size.min(u16::MAX as u32) as u16;
+            decoder.scale(scaled_size, scaled_size)?;
+            let image = DynamicImage::from_decoder(decoder)?.into_rgba8();
+            let orientation =
+                detect_orientation(path).unwrap_or(Orientation::Deg0);
+            Ok(apply_orientation(image, orientation))
+        }
+        _ => load_edited_image(path, ImageEdits::default()),
+    }
+}
+
+/// Scales the image down so that neither side is larger than `size`
+fn fit_into_square(image: image::RgbaImage, size: u32) -> image::RgbaImage {
+    let (w, h) = image.dimensions();
+    if w <= size && h <= size {
+        return image;
+    }
+    let scale = size as f32 / w.max(h) as f32;
+    let thumb_w = ((w as f32 * scale).round() as u32).max(1);
+    let thumb_h = ((h as f32 * scale).round() as u32).max(1);
+    image::imageops::thumbnail(&image, thumb_w, thumb_h)
+}
+
 fn load_animation(
     req_id: u32,
     decoder: impl AnimationDecoder<'static>,
@@ -309,12 +633,15 @@ pub fn is_file_supported(filename: &Path) -> bool {
 pub struct LoadRequest {
     pub req_id: u32,
     pub path: PathBuf,
+    /// When set, only a single frame is loaded and scaled down to fit into
+    /// a square of this size.
+    pub thumbnail_size: Option<u32>,
 }
 
 pub enum LoadResult {
     Start {
         req_id: u32,
-        metadata: fs::Metadata,
+        mod_time: Option<SystemTime>,
     },
     Frame {
         req_id: u32,
@@ -345,6 +672,12 @@ impl LoadResult {
 
 pub struct ImageLoader {
     running: Arc<AtomicBool>,
+    /// We want to prevent prefetch operations taking place when the target image is not yet loaded.
+    /// To implement this we define a variable that is read by the loader threads and
+    /// which will only carry out the request if the focused request id matches their request or
+    /// if the focused is set to `NON_EXISTENT_REQUEST_ID`. Each loader has its own, so that
+    /// the caches don't hold up each other.
+    priority_request_id: Arc<AtomicU32>,
     join_handles: Option<Vec<thread::JoinHandle<()>>>,
     image_rx: Receiver<LoadResult>,
     path_tx: Sender<LoadRequest>,
@@ -355,6 +688,8 @@ impl ImageLoader {
     /// * `capacity` - Number of bytes. The last image loaded will be the one at which the allocated memory reaches or exceeds capacity
     pub fn new(threads: u32) -> ImageLoader {
         let running = Arc::new(AtomicBool::from(true));
+        // The first request usually
+        let priority_request_id = Arc::new(AtomicU32::new(0));
         let (load_request_tx, load_request_rx) = channel();
         let load_request_rx = Arc::new(Mutex::new(load_request_rx));
 
@@ -363,12 +698,14 @@ impl ImageLoader {
         let mut join_handles = Vec::new();
         for _ in 0..threads {
             let running = running.clone();
+            let priority_request_id = priority_request_id.clone();
             let request_recv = load_request_rx.clone();
             let request_send = load_request_tx.clone();
             let img_sender = loaded_img_tx.clone();
             join_handles.push(thread::spawn(move || {
                 Self::thread_loop(
                     running,
+                    priority_request_id,
                     request_recv,
                     request_send,
                     img_sender,
@@ -378,6 +715,7 @@ impl ImageLoader {
 
         ImageLoader {
             running,
+            priority_request_id,
             join_handles: Some(join_handles),
 
             image_rx: loaded_img_rx,
@@ -387,6 +725,7 @@ impl ImageLoader {
 
     fn thread_loop(
         running: Arc<AtomicBool>,
+        priority_request_id: Arc<AtomicU32>,
         request_recv: Arc<Mutex<Receiver<LoadRequest>>>,
         request_send: Sender<LoadRequest>,
         img_sender: Sender<LoadResult>,
@@ -400,9 +739,12 @@ impl ImageLoader {
             {
                 // It is very important that we release the mutex before starting to load the image
                 let load_request = request_recv.lock().unwrap();
-                let priority = PRIORITY_REQUEST_ID.load(Ordering::SeqCst);
+                let priority = priority_request_id.load(Ordering::SeqCst);
                 request = load_request.recv().unwrap();
-                let focus_test_passed = priority == request.req_id
+                // Thumbnails are loaded by separate threads, waiting for the
+                // shown image wouldn't make it any faster.
+                let focus_test_passed = request.thumbnail_size.is_some()
+                    || priority == request.req_id
                     || priority == NON_EXISTENT_REQUEST_ID;
                 if !focus_test_passed {
                     //println!("Priority test failed, priority was {}", priority);
@@ -427,18 +769,48 @@ impl ImageLoader {
         self.path_tx.send(request).unwrap();
     }
 
+    /// Holds back the other requests until the request `req_id` is loaded
+    pub fn set_priority(&self, req_id: u32) {
+        self.priority_request_id.store(req_id, Ordering::SeqCst);
+    }
+
+    /// Lets the other requests be loaded again, if `req_id` is the request
+    /// that they are waiting for
+    pub fn clear_priority(&self, req_id: u32) {
+        let _ = self.priority_request_id.compare_exchange(
+            req_id,
+            NON_EXISTENT_REQUEST_ID,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+
     fn load_and_send(img_sender: &Sender<LoadResult>, request: LoadRequest) {
         fn try_load_and_send(
             img_sender: &Sender<LoadResult>,
             request: &LoadRequest,
         ) -> Result<()> {
-            let metadata = fs::metadata(&request.path)?;
+            let mod_time = match ImageSource::of(&request.path) {
+                ImageSource::File(path) => fs::metadata(path)?.modified().ok(),
+                ImageSource::Memory(_) => modified_time(&request.path),
+            };
             img_sender
                 .send(LoadResult::Start {
                     req_id: request.req_id,
-                    metadata,
+                    mod_time,
                 })
                 .unwrap();
+            if let Some(size) = request.thumbnail_size {
+                img_sender
+                    .send(LoadResult::Frame {
+                        req_id: request.req_id,
+                        image: load_thumbnail(&request.path, size)?,
+                        delay_nano: 0,
+                        orientation: Orientation::Deg0,
+                    })
+                    .unwrap();
+                return Ok(());
+            }
             complex_load_image(&request.path, true, request.req_id, |frame| {
                 img_sender.send(frame).unwrap();
                 Ok(())
@@ -474,6 +846,7 @@ impl Drop for ImageLoader {
                     .send(LoadRequest {
                         req_id: 0,
                         path: PathBuf::from(""),
+                        thumbnail_size: None,
                     })
                     .unwrap();
             }
@@ -486,3 +859,85 @@ impl Drop for ImageLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    const ORIENTATIONS: [Orientation; 8] = [
+        Orientation::Deg0,
+        Orientation::Deg0HorFlip,
+        Orientation::Deg90,
+        Orientation::Deg90VerFlip,
+        Orientation::Deg180,
+        Orientation::Deg180HorFlip,
+        Orientation::Deg270,
+        Orientation::Deg270VerFlip,
+    ];
+
+    fn test_image() -> GrayImage {
+        GrayImage::from_fn(3, 2, |x, y| Luma([(y * 3 + x) as u8]))
+    }
+
+    #[test]
+    fn exif_values_round_trip() {
+        for orientation in ORIENTATIONS {
+            let exif = orientation.to_exif();
+            assert_eq!(Orientation::from_exif(exif), Some(orientation));
+        }
+    }
+
+    #[test]
+    fn composition_matches_the_pixels() {
+        for first in ORIENTATIONS {
+            for next in ORIENTATIONS {
+                let twice = apply_orientation(
+                    apply_orientation(test_image(), first),
+                    next,
+                );
+                let composed =
+                    apply_orientation(test_image(), first.then(next));
+                assert_eq!(twice, composed, "{first:?} then {next:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn formats_are_detected_from_the_contents_first() {
+        let folder = TempDir::new("formats");
+        let mut png = Cursor::new(Vec::new());
+        test_image().write_to(&mut png, ImageFormat::Png).unwrap();
+        let files = [
+            (
+                "short.svg",
+                b"<svg xmlns='http://www.w3.org/2000/svg'/>".to_vec(),
+            ),
+            ("upper.SVG", b"<svg/>".to_vec()),
+            ("png.svg", png.get_ref().clone()),
+            ("png.dat", png.into_inner()),
+        ];
+        let mut formats = Vec::new();
+        for (name, data) in files {
+            let path = folder.join(name);
+            fs::write(&path, data).unwrap();
+            formats.push(match detect_format(&path).unwrap() {
+                ImgFormat::Svg => None,
+                ImgFormat::Image(format) => Some(format),
+            });
+        }
+        let png = Some(ImageFormat::Png);
+        assert_eq!(formats, [None, None, png, png]);
+    }
+
+    #[test]
+    fn quarter_turns_swap_dimensions() {
+        for orientation in ORIENTATIONS {
+            let (w, h) =
+                apply_orientation(test_image(), orientation).dimensions();
+            assert_eq!(orientation.swaps_dimensions(), (w, h) == (2, 3));
+        }
+    }
+}
@@ -1,15 +1,19 @@
 mod directory;
+pub mod filter;
 pub mod image_loader;
 mod pending_requests;
+pub mod sort;
+mod thumbnail_store;
+pub mod thumbnails;
+mod watcher;
 
 use std::{
     borrow::Cow,
     collections::BTreeMap,
-    ffi::{OsStr, OsString},
-    fs, mem,
+    ffi::OsString,
+    mem,
     path::{Path, PathBuf},
     rc::Rc,
-    sync::atomic::Ordering,
     time::SystemTime,
 };
 
@@ -25,6 +29,7 @@ use self::{
     image_loader::*,
     pending_requests::PendingRequests,
 };
+use crate::configuration::EndOfFolder;
 
 pub mod errors {
     use std::io;
@@ -51,6 +56,8 @@ pub mod errors {
         WaitingOnLoader,
         #[error("ImageCache is waiting for the directory items to be filtered for image files")]
         WaitingOnDirFilter,
+        #[error("Reached the {0:?} image of the folder")]
+        ReachedEnd(super::directory::FolderEnd),
         #[error("Failed to load {req_id}")]
         FailedToLoadImage { req_id: u32 },
         #[error("{0}")]
@@ -58,8 +65,8 @@ pub mod errors {
     }
 }
 
-pub use self::errors::Result;
 use self::errors::*;
+pub use self::{directory::FolderEnd, errors::Result};
 
 pub fn get_image_size_estimate(width: u32, height: u32) -> isize {
     // In an RGBA image, each pixel is 4 bytes.
@@ -177,14 +184,6 @@ impl AnimationFrameTexture {
             grid_cols,
         })
     }
-
-    pub fn oriented_dimensions(&self) -> (u32, u32) {
-        use Orientation::*;
-        match self.orientation {
-            Deg0 | Deg0HorFlip | Deg180 | Deg180HorFlip => (self.w, self.h),
-            Deg90 | Deg90VerFlip | Deg270 | Deg270VerFlip => (self.h, self.w),
-        }
-    }
 }
 
 /// img_bytes has to be an rgba8 buffer.
@@ -236,6 +235,19 @@ fn texture_from_img_rect(
     Ok(SrgbTexture2d::with_mipmaps(display, raw_image, mipmaps)?)
 }
 
+/// What changed in the watched folder
+#[derive(Debug, Default)]
+pub struct FolderUpdate {
+    /// Files were added to or removed from the folder
+    pub listing_changed: bool,
+    /// The file of the current image was written to
+    pub current_written: bool,
+    /// The images that were added to the folder
+    pub created: Vec<PathBuf>,
+    /// The files whose contents were written
+    pub written: Vec<PathBuf>,
+}
+
 struct CachedTexture {
     /// Contains the load request id
     _req_id: u32,
@@ -285,7 +297,12 @@ pub struct ImageCache {
 
     pending_requests: PendingRequests,
     texture_cache: BTreeMap<u32, CachedTexture>,
+    /// Changes whenever a texture is added to or removed from the cache
+    texture_version: u64,
     loader: ImageLoader,
+
+    /// What stepping past the last or before the first image does
+    end_of_folder: EndOfFolder,
 }
 
 /// This is a store for the supported images loaded from a folder
@@ -308,17 +325,119 @@ impl ImageCache {
 
             pending_requests: PendingRequests::new(),
             texture_cache: BTreeMap::new(),
+            texture_version: 0,
             loader: ImageLoader::new(threads),
+            end_of_folder: EndOfFolder::default(),
         }
     }
 
-    pub fn current_filename(&self) -> Option<OsString> {
-        self.dir.curr_filename()
+    pub fn current_file_path(&self) -> Option<PathBuf> {
+        self.dir.curr_descriptor().map(|desc| desc.path.clone())
+    }
+
+    /// The folder whose subfolders are listed as well, or `None` if only the
+    /// files in the folder of the current image are listed
+    pub fn recursive_root(&self) -> Option<&Path> {
+        self.dir.max_depth().map(|_| self.dir.path())
     }
 
-    pub fn current_file_path(&self) -> Option<PathBuf> {
-        self.current_filename()
-            .map(|name| self.dir.path().join(name))
+    pub fn sort_order(&self) -> sort::SortOrder {
+        self.dir.sort_order()
+    }
+
+    /// Orders the images of the folder in the background
+    pub fn set_sort_order(&mut self, sort_order: sort::SortOrder) {
+        self.dir.set_sort_order(sort_order);
+    }
+
+    /// Narrows the images of the folder down in the background
+    pub fn set_filter(&mut self, filter: filter::ImageFilter) {
+        self.dir.set_filter(filter);
+    }
+
+    pub fn set_end_of_folder(&mut self, end_of_folder: EndOfFolder) {
+        self.end_of_folder = end_of_folder;
+    }
+
+    /// Opens the closest folder next to the current one that has images,
+    /// going forward or backward in the natural order of the folder names.
+    /// Selects the first image of the folder once it has been filtered.
+    /// Returns false if no other folder in the parent folder has images.
+    pub fn change_to_sibling_folder(&mut self, forward: bool) -> Result<bool> {
+        self.change_to_sibling_at(forward, FolderEnd::First)
+    }
+
+    fn change_to_sibling_at(
+        &mut self,
+        forward: bool,
+        end: FolderEnd,
+    ) -> Result<bool> {
+        let Some(folder) = self.dir.sibling_folder(forward, true) else {
+            return Ok(false);
+        };
+        self.change_directory(&folder)?;
+        self.dir.select_end(end);
+        self.current_frame_idx = 0;
+        Ok(true)
+    }
+
+    /// Shows the images of `entries` in the given order, in place of the
+    /// images of a folder. Selects the first image once the list has been
+    /// filtered.
+    pub fn change_to_list(&mut self, entries: Vec<PathBuf>) -> Result<()> {
+        self.clear_textures();
+        self.dir
+            .change_to_list(entries)
+            .map_err(|e| Error::Msg(format!("{e}")))?;
+        self.dir.select_end(FolderEnd::First);
+        self.current_frame_idx = 0;
+        Ok(())
+    }
+
+    /// Returns true if the current image is the last one of the folder, or
+    /// the first one if `forward` is false
+    fn is_at_folder_end(&mut self, forward: bool) -> bool {
+        match (self.dir.curr_img_index(), self.dir.image_count()) {
+            // Every file of the folder was filtered out
+            (Some(_), Some(0)) => true,
+            (Some(index), Some(count)) if forward => index + 1 >= count,
+            (Some(index), Some(_)) => index == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the current image is the last one that stepping
+    /// forward reaches without starting over, either in this folder or in
+    /// the folders after it.
+    pub fn is_at_sequence_end(&mut self) -> bool {
+        if !self.is_at_folder_end(true) {
+            return false;
+        }
+        match self.end_of_folder {
+            EndOfFolder::NextFolder => {
+                self.dir.sibling_folder(true, false).is_none()
+            }
+            EndOfFolder::Wrap | EndOfFolder::Stop => true,
+        }
+    }
+
+    /// Sets how many levels of subfolders of the folder are listed, or turns
+    /// off the recursion with `None`. The current image stays selected.
+    pub fn set_max_depth(&mut self, max_depth: Option<u32>) -> Result<()> {
+        if self.dir.max_depth() == max_depth {
+            return Ok(());
+        }
+        // The request ids of the files change, so the cached textures can't
+        // be found anymore
+        self.texture_cache.clear();
+        self.texture_version += 1;
+        self.remaining_capacity = self.total_capacity;
+        for (_, request) in self.pending_requests.iter_mut() {
+            request.cancel();
+        }
+        self.dir
+            .set_max_depth(max_depth)
+            .map_err(|e| Error::Msg(format!("{e}")))
     }
 
     /// Returns `None` when the directory hasn't finished filtering image files.
@@ -331,6 +450,56 @@ impl ImageCache {
         self.dir.image_count()
     }
 
+    /// Returns the paths of all images in the folder. Returns `None` when the
+    /// directory hasn't finished filtering image files.
+    pub fn image_paths(&mut self) -> Option<Vec<PathBuf>> {
+        let img_count = self.dir.image_count()?;
+        let paths = (0..img_count)
+            .filter_map(|index| {
+                self.dir.image_by_index(index).map(|item| item.path.clone())
+            })
+            .collect();
+        Some(paths)
+    }
+
+    /// Changes whenever the images of the folder, their order or the cached
+    /// textures change, so that views of them know when to update
+    pub fn version(&self) -> (u64, u64) {
+        (self.dir.version(), self.texture_version)
+    }
+
+    /// Returns the path of the image at `index` in the folder, along with the
+    /// first frame of its texture if that is already uploaded to the GPU.
+    /// Returns `None` when the directory hasn't finished filtering image files
+    /// or there's no image at `index`.
+    pub fn image_at_index(
+        &mut self,
+        index: usize,
+    ) -> Option<(PathBuf, Option<AnimationFrameTexture>)> {
+        let item = self.dir.image_by_index(index)?;
+        let texture = self
+            .texture_cache
+            .get(&item.request_id)
+            .and_then(|cached| cached.frames.first().cloned());
+        Some((item.path.clone(), texture))
+    }
+
+    /// Returns the path of the image `offset` places after the current one,
+    /// wrapping around at the ends of the folder. Returns `None` when the
+    /// directory hasn't finished filtering image files.
+    pub fn file_path_at_offset(&mut self, offset: isize) -> Option<PathBuf> {
+        let curr_index = self.dir.curr_img_index()?;
+        let img_count = self.dir.image_count()?;
+        if img_count == 0 {
+            return None;
+        }
+        let index =
+            (curr_index as isize + offset).rem_euclid(img_count as isize);
+        self.dir
+            .image_by_index(index as usize)
+            .map(|item| item.path.clone())
+    }
+
     fn curr_dir_item(&self) -> Result<DirItem> {
         if let Some(desc) = self.dir.curr_descriptor() {
             Ok(desc.clone())
@@ -365,15 +534,84 @@ impl ImageCache {
     /// returns `Ok(())`
     pub fn update_directory(&mut self) -> Result<()> {
         self.dir.update_directory()?;
+        self.mark_textures_outdated();
+        Ok(())
+    }
+
+    /// Like `update_directory`, but the folder is only listed again if it
+    /// isn't watched for changes
+    pub fn refresh_directory(&mut self) -> Result<()> {
+        if !self.dir.is_watched() {
+            self.dir.update_directory()?;
+        }
+        self.mark_textures_outdated();
+        Ok(())
+    }
+
+    pub fn is_folder_watched(&self) -> bool {
+        self.dir.is_watched()
+    }
+
+    /// Applies the changes in the watched folder since the last call
+    pub fn apply_folder_changes(&mut self) -> Result<FolderUpdate> {
+        let changes = self.dir.folder_changes();
+        if changes.is_empty() {
+            return Ok(FolderUpdate::default());
+        }
+        // The rewritten files are checked against their modification time
+        // when they are requested again
+        for path in &changes.written {
+            let Some(req_id) = self.dir.request_id_of(path) else {
+                continue;
+            };
+            if let Some(texture) = self.texture_cache.get_mut(&req_id) {
+                texture.needs_update = true;
+            }
+            // A load that is still running may have read the file before the
+            // write, so it's loaded again when it's requested
+            for (_, request) in self
+                .pending_requests
+                .iter_mut()
+                .filter(|(id, _)| **id == req_id)
+            {
+                request.cancel();
+            }
+        }
+        let current_written = self
+            .current_file_path()
+            .is_some_and(|path| changes.written.contains(&path));
+        let listed_before: Vec<bool> = changes
+            .paths
+            .iter()
+            .map(|path| self.dir.contains_file(path))
+            .collect();
+        let listing_changed = self.dir.apply_folder_changes(&changes)?;
+        let created = changes
+            .paths
+            .iter()
+            .zip(listed_before)
+            .filter(|&(path, listed_before)| {
+                !listed_before
+                    && self.dir.contains_file(path)
+                    && self.dir.is_image(path)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        Ok(FolderUpdate {
+            listing_changed,
+            current_written,
+            created,
+            written: changes.written,
+        })
+    }
 
+    fn mark_textures_outdated(&mut self) {
         // indicate that the an update directory
         // call was made since those were created and they should all be
         // checked against the modification time of the file system file.
         for texture in self.texture_cache.values_mut() {
             texture.needs_update = true;
         }
-
-        Ok(())
     }
 
     pub fn load_at_index(
@@ -404,20 +642,20 @@ impl ImageCache {
         trace!("Begin `load_specific`");
         self.receive_prefetched();
         trace!("Receive prefetched done");
-        let target_file_name;
+        let target_file;
         let parent;
         if path.is_dir() {
             parent = path.to_owned();
-            target_file_name = None;
+            target_file = None;
         } else {
-            let filename_and_parent = get_file_name_and_parent(path)?;
-            target_file_name = Some(filename_and_parent.0);
-            parent = filename_and_parent.1;
+            let (folder, file) = self.folder_listing_file(path)?;
+            parent = folder;
+            target_file = Some(file);
         }
 
         let prev_img_index = self.dir.curr_img_index();
-        if let Some(target_file_name) = target_file_name {
-            self.change_directory_with_filename(&parent, &target_file_name)?;
+        if let Some(target_file) = target_file {
+            self.change_directory_with_file(&parent, &target_file)?;
         } else {
             self.change_directory(&parent)?;
             self.current_frame_idx = 0;
@@ -508,6 +746,11 @@ impl ImageCache {
         frame_jump_count: isize,
     ) -> Result<(AnimationFrameTexture, PathBuf)> {
         if file_jump_count == 0 {
+            // The image to show in the folder that was changed to isn't
+            // known until the folder has been filtered
+            if self.dir.is_selecting_end() {
+                return Err(Error::WaitingOnLoader);
+            }
             // Here, it is possible that the current image was already
             // requested but not yet loaded.
             let target_frame =
@@ -525,19 +768,52 @@ impl ImageCache {
 
         let target_path;
         if file_jump_count.abs() == 1 {
-            if file_jump_count > 0 {
+            let forward = file_jump_count > 0;
+            if self.is_at_folder_end(forward) {
+                let (end, opposite) = if forward {
+                    (FolderEnd::Last, FolderEnd::First)
+                } else {
+                    (FolderEnd::First, FolderEnd::Last)
+                };
+                match self.end_of_folder {
+                    EndOfFolder::Wrap => (),
+                    EndOfFolder::Stop => return Err(Error::ReachedEnd(end)),
+                    EndOfFolder::NextFolder => {
+                        // The image is loaded by the jump in place that
+                        // follows
+                        if self.change_to_sibling_at(forward, opposite)? {
+                            return Err(Error::WaitingOnLoader);
+                        }
+                    }
+                }
+            }
+            if forward {
                 self.dir.jump_to_next();
             } else {
                 self.dir.jump_to_prev();
             }
             target_path = self.dir.curr_descriptor().unwrap().path.clone();
-        } else if let (Some(curr_index), Some(img_count)) =
+        } else if let (Some(curr_index), Some(img_count @ 1..)) =
             (self.dir.curr_img_index(), self.dir.image_count())
         {
-            // rem_euclid calculates the least nonnegative remainder
-            let target_index = (curr_index as isize + file_jump_count as isize)
-                .rem_euclid(img_count as isize)
-                as usize;
+            let target_index = curr_index as isize + file_jump_count as isize;
+            let last_index = img_count as isize - 1;
+            let target_index = match self.end_of_folder {
+                EndOfFolder::Stop if target_index > last_index => {
+                    if curr_index as isize == last_index {
+                        return Err(Error::ReachedEnd(FolderEnd::Last));
+                    }
+                    last_index
+                }
+                EndOfFolder::Stop if target_index < 0 => {
+                    if curr_index == 0 {
+                        return Err(Error::ReachedEnd(FolderEnd::First));
+                    }
+                    0
+                }
+                // rem_euclid calculates the least nonnegative remainder
+                _ => target_index.rem_euclid(img_count as isize),
+            } as usize;
 
             target_path =
                 self.dir.image_by_index(target_index).unwrap().path.clone();
@@ -617,13 +893,23 @@ impl ImageCache {
             // And just let the next blok deal with locating the appropriate frame.
         }
 
+        let modified = image_loader::modified_time(&path);
+        if let Some(tex) = self.texture_cache.get(&req_id) {
+            // The file was rewritten since it was loaded, so it's loaded again
+            let outdated = tex.mod_time.is_some_and(|mod_time| {
+                modified.is_some_and(|modified| modified != mod_time)
+            });
+            if outdated && (tex.fully_loaded || tex.failed) {
+                self.texture_cache.remove(&req_id);
+                self.texture_version += 1;
+            }
+        }
+
         // Check if it is inside the texture cache first
         if let Some(tex) = self.texture_cache.get(&req_id) {
             if tex.failed {
                 return Err(Error::FailedToLoadImage { req_id });
             }
-            let modified =
-                fs::metadata(&path).ok().and_then(|m| m.modified().ok());
             let mut get_from_cache = false;
             if let Some(curr_mod_time) = modified {
                 if let Some(mod_time) = tex.mod_time {
@@ -652,7 +938,7 @@ impl ImageCache {
             return Err(Error::WaitingOnLoader);
         }
         if self.pending_requests.contains(&req_id) {
-            PRIORITY_REQUEST_ID.store(req_id, Ordering::SeqCst);
+            self.loader.set_priority(req_id);
 
             return Err(Error::WaitingOnLoader);
         }
@@ -673,8 +959,8 @@ impl ImageCache {
     ) -> Result<Option<AnimationFrameTexture>> {
         use std::collections::btree_map::Entry;
         match load_result {
-            LoadResult::Start { req_id, metadata } => {
-                let curr_mod_time = metadata.modified().ok();
+            LoadResult::Start { req_id, mod_time } => {
+                let curr_mod_time = mod_time;
                 if let Some(cancelled) =
                     self.pending_requests.cancelled(&req_id)
                 {
@@ -694,6 +980,7 @@ impl ImageCache {
                             failed: false,
                             frames: Vec::new(),
                         });
+                        self.texture_version += 1;
                     }
                     Entry::Occupied(mut entry) => {
                         let mut overwrite = true;
@@ -712,6 +999,7 @@ impl ImageCache {
                             let mut_entry = entry.get_mut();
                             mut_entry.frames.clear();
                             mut_entry.mod_time = curr_mod_time;
+                            self.texture_version += 1;
                         }
                     }
                 }
@@ -742,6 +1030,7 @@ impl ImageCache {
                         orientation,
                     )?;
                     entry.frames.push(anim_frame.clone());
+                    self.texture_version += 1;
                     self.remaining_capacity -= size_estimate;
                     return Ok(Some(anim_frame));
                 }
@@ -751,12 +1040,7 @@ impl ImageCache {
                 if let Some(tex) = self.texture_cache.get_mut(&req_id) {
                     tex.fully_loaded = true;
                 }
-                let _ = PRIORITY_REQUEST_ID.compare_exchange(
-                    req_id,
-                    NON_EXISTENT_REQUEST_ID,
-                    Ordering::SeqCst,
-                    Ordering::SeqCst,
-                );
+                self.loader.clear_priority(req_id);
                 self.pending_requests.set_finished(&req_id);
                 Ok(None)
             }
@@ -765,12 +1049,7 @@ impl ImageCache {
                     tex.fully_loaded = true;
                     tex.failed = true;
                 }
-                let _ = PRIORITY_REQUEST_ID.compare_exchange(
-                    req_id,
-                    NON_EXISTENT_REQUEST_ID,
-                    Ordering::SeqCst,
-                    Ordering::SeqCst,
-                );
+                self.loader.clear_priority(req_id);
                 self.pending_requests.set_finished(&req_id);
                 Err(errors::Error::FailedToLoadImage { req_id })
             }
@@ -794,6 +1073,20 @@ impl ImageCache {
         }
     }
 
+    /// Starts loading the listed file at `path` in the background, so that
+    /// it's ready when it's shown. If the file is written to afterwards, it's
+    /// loaded again when it's requested.
+    pub fn prefetch_file(&mut self, path: &Path) -> bool {
+        match self.dir.request_id_of(path) {
+            Some(req_id) => self.send_request_for_file(
+                path.to_owned(),
+                req_id,
+                RequestKind::NonPriority,
+            ),
+            None => false,
+        }
+    }
+
     pub fn prefetch_at_index(&mut self, index: usize) -> bool {
         if self.remaining_capacity > self.curr_est_size {
             let params = self
@@ -841,9 +1134,7 @@ impl ImageCache {
             } else {
                 texture.needs_update = false;
                 if let Some(existing_mod_time) = texture.mod_time {
-                    let new_mod_time = fs::metadata(&file_path)
-                        .ok()
-                        .and_then(|m| m.modified().ok());
+                    let new_mod_time = image_loader::modified_time(&file_path);
                     if let Some(new_mod_time) = new_mod_time {
                         if new_mod_time == existing_mod_time {
                             return false;
@@ -856,9 +1147,10 @@ impl ImageCache {
         }
         if cache_enty_invalid {
             self.texture_cache.remove(&req_id);
+            self.texture_version += 1;
         }
         if kind.priority() {
-            PRIORITY_REQUEST_ID.store(req_id, Ordering::SeqCst);
+            self.loader.set_priority(req_id);
         }
         if self.pending_requests.contains(&req_id) {
             return false;
@@ -866,6 +1158,7 @@ impl ImageCache {
         let request = LoadRequest {
             req_id,
             path: file_path,
+            thumbnail_size: None,
         };
         self.pending_requests.add_request(request.clone());
         self.loader.send_load_request(request);
@@ -876,28 +1169,59 @@ impl ImageCache {
         if self.dir.path() == dir_path {
             return Ok(());
         }
+        self.clear_textures();
+        self.dir.change_directory(dir_path)?;
+        Ok(())
+    }
+
+    /// Drops the textures and the pending load requests of the listed files
+    fn clear_textures(&mut self) {
         self.texture_cache.clear();
+        self.texture_version += 1;
         self.remaining_capacity = self.total_capacity;
 
         // Cancel all pending load requests
         for (_, request) in self.pending_requests.iter_mut() {
             request.cancel();
         }
-
-        self.dir.change_directory(dir_path)?;
-        Ok(())
     }
 
-    fn change_directory_with_filename(
+    fn change_directory_with_file(
         &mut self,
         dir_path: &Path,
-        filename: &OsStr,
+        file: &Path,
     ) -> Result<()> {
         self.dir
-            .change_directory_with_filename(dir_path, filename)
+            .change_directory_with_file(dir_path, file)
             .map_err(|e| Error::Msg(format!("{e}")))
     }
 
+    /// Returns the folder to list for showing the file at `path`, and the path
+    /// of the file as it's listed in that folder. In recursive mode the
+    /// folder stays the same for the files in its subfolders.
+    fn folder_listing_file(&self, path: &Path) -> Result<(PathBuf, PathBuf)> {
+        if self.dir.contains_file(path) {
+            return Ok((self.dir.path().to_owned(), path.to_owned()));
+        }
+        let (file_name, parent) = get_file_name_and_parent(path)?;
+        if let Some(max_depth) = self.dir.max_depth() {
+            let root = self.dir.path();
+            let canonical_root = root.canonicalize().ok();
+            let relative =
+                canonical_root.as_deref().and_then(|canonical_root| {
+                    parent.strip_prefix(canonical_root).ok()
+                });
+            if let Some(relative) = relative {
+                if relative.components().count() <= max_depth as usize {
+                    let file = root.join(relative).join(file_name);
+                    return Ok((root.to_owned(), file));
+                }
+            }
+        }
+        let file = parent.join(file_name);
+        Ok((parent, file))
+    }
+
     // fn collect_directory(&mut self) -> Result<Vec<DirItem>> {
     // 	let start = std::time::Instant::now();
     // 	let mut dir_files: Vec<_> = fs::read_dir(&self.dir.path)?
@@ -0,0 +1,285 @@
+//! Orders the images of a folder by the keys of `SortKey`. The keys are read
+//! from the files on the thread that filters the folder for images, since
+//! some of them need to open every file.
+
+use std::{cmp::Ordering, fs, path::Path, time::SystemTime};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::directory::DirItem;
+use crate::configuration::SortKey;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+    /// The seed of the shuffle for `SortKey::Random`
+    pub seed: u64,
+}
+
+impl SortOrder {
+    /// A short description for the window title, or `None` for the default
+    /// order
+    pub fn description(&self) -> Option<String> {
+        if *self == SortOrder::default() {
+            return None;
+        }
+        let mut description = format!("Sorted by {}", self.key.name());
+        if self.descending {
+            description.push_str(", descending");
+        }
+        Some(description)
+    }
+}
+
+/// The value of a file that it's ordered by
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum KeyValue {
+    Time(SystemTime),
+    Number(u64),
+    Text(String),
+}
+
+/// Reorders `images`, which are indices into `files` in the order of the
+/// file names. Images with the same key stay in the order of their names, and
+/// the ones whose key can't be read are placed last.
+pub fn sort_images(
+    files: &[DirItem],
+    images: &mut Vec<usize>,
+    order: SortOrder,
+) {
+    match order.key {
+        SortKey::Name => {
+            if order.descending {
+                images.reverse();
+            }
+        }
+        SortKey::Random => {
+            images.shuffle(&mut StdRng::seed_from_u64(order.seed));
+            if order.descending {
+                images.reverse();
+            }
+        }
+        key => {
+            let mut keyed: Vec<(usize, Option<KeyValue>)> = images
+                .iter()
+                .map(|&i| (i, key_value(&files[i].path, key)))
+                .collect();
+            // The sort is stable, so the images with equal keys stay in name
+            // order
+            keyed.sort_by(|(_, a), (_, b)| cmp_keys(a, b, order.descending));
+            *images = keyed.into_iter().map(|(i, _)| i).collect();
+        }
+    }
+}
+
+/// Inserts the image `new` into `images`, which are ordered by `order` and
+/// don't contain it yet. Only the keys of the images that `new` is compared
+/// with are read, so this is much faster than ordering all of them again.
+pub fn insert_image(
+    files: &[DirItem],
+    images: &mut Vec<usize>,
+    new: usize,
+    order: SortOrder,
+) {
+    let index = match order.key {
+        // The indices of the files are in the order of the names
+        SortKey::Name if order.descending => {
+            images.partition_point(|&i| i > new)
+        }
+        SortKey::Name => images.partition_point(|&i| i < new),
+        SortKey::Random => rand::thread_rng().gen_range(0..=images.len()),
+        key => {
+            let new_key = key_value(&files[new].path, key);
+            images.partition_point(|&i| {
+                let key = key_value(&files[i].path, key);
+                // Equal keys are in name order
+                cmp_keys(&key, &new_key, order.descending).then(i.cmp(&new))
+                    == Ordering::Less
+            })
+        }
+    };
+    images.insert(index, new);
+}
+
+/// Orders by the key in the direction of `descending`, except that the
+/// missing keys are always last
+fn cmp_keys(
+    a: &Option<KeyValue>,
+    b: &Option<KeyValue>,
+    descending: bool,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Reads the value of `key` for the file at `path`. The name and the random
+/// order have no value.
+fn key_value(path: &Path, key: SortKey) -> Option<KeyValue> {
+    match key {
+        SortKey::Name | SortKey::Random => None,
+        SortKey::Modified => {
+            let time = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            Some(KeyValue::Time(time))
+        }
+        SortKey::Created => {
+            let time = fs::metadata(path).and_then(|m| m.created()).ok()?;
+            Some(KeyValue::Time(time))
+        }
+        SortKey::Size => Some(KeyValue::Number(fs::metadata(path).ok()?.len())),
+        SortKey::ExifDate => exif_date_time(path).map(KeyValue::Text),
+        SortKey::Dimensions => {
+            let (w, h) = image::image_dimensions(path).ok()?;
+            Some(KeyValue::Number(w as u64 * h as u64))
+        }
+        SortKey::Extension => {
+            let extension = path.extension().unwrap_or_default();
+            let extension = extension.to_string_lossy().to_lowercase();
+            Some(KeyValue::Text(extension))
+        }
+    }
+}
+
+/// The Exif DateTimeOriginal tag as written in the file, for example
+/// `2023:07:14 18:30:02`, which orders like the time it stands for
+fn exif_date_time(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = std::io::BufReader::new(file);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    let field =
+        exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(values) => {
+            let value = values.first()?;
+            Some(String::from_utf8_lossy(value).into_owned())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn items(paths: &[PathBuf]) -> Vec<DirItem> {
+        paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| DirItem {
+                path: path.clone(),
+                request_id: i as u32,
+            })
+            .collect()
+    }
+
+    /// Orders all of the files with `sort_images`, and once more by inserting
+    /// them one by one
+    fn sorted(files: &[DirItem], order: SortOrder) -> Vec<usize> {
+        let mut images: Vec<usize> = (0..files.len()).collect();
+        sort_images(files, &mut images, order);
+        let mut inserted = Vec::new();
+        for new in 0..files.len() {
+            insert_image(files, &mut inserted, new, order);
+        }
+        assert_eq!(inserted, images, "{order:?}");
+        images
+    }
+
+    #[test]
+    fn missing_keys_are_last() {
+        let key = |n| Some(KeyValue::Number(n));
+        assert_eq!(cmp_keys(&key(1), &key(2), false), Ordering::Less);
+        assert_eq!(cmp_keys(&key(1), &key(2), true), Ordering::Greater);
+        assert_eq!(cmp_keys(&key(1), &None, false), Ordering::Less);
+        assert_eq!(cmp_keys(&key(1), &None, true), Ordering::Less);
+        assert_eq!(cmp_keys(&None, &None, true), Ordering::Equal);
+    }
+
+    #[test]
+    fn name_and_extension_orders() {
+        let paths: Vec<PathBuf> = ["a.png", "b.JPG", "c.gif", "d.jpg"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let files = items(&paths);
+        let order = |key, descending| SortOrder {
+            key,
+            descending,
+            seed: 0,
+        };
+        assert_eq!(sorted(&files, order(SortKey::Name, false)), [0, 1, 2, 3]);
+        assert_eq!(sorted(&files, order(SortKey::Name, true)), [3, 2, 1, 0]);
+        // Equal extensions stay in name order, also when descending
+        assert_eq!(
+            sorted(&files, order(SortKey::Extension, false)),
+            [2, 1, 3, 0]
+        );
+        assert_eq!(
+            sorted(&files, order(SortKey::Extension, true)),
+            [0, 1, 3, 2]
+        );
+    }
+
+    #[test]
+    fn size_order_puts_missing_files_last() {
+        let folder = TempDir::new("sort");
+        let mut paths = Vec::new();
+        for (name, size) in [("a.png", 30), ("b.png", 10), ("c.png", 20)] {
+            fs::write(folder.join(name), vec![0; size]).unwrap();
+            paths.push(folder.join(name));
+        }
+        paths.insert(1, folder.join("missing.png"));
+        let files = items(&paths);
+        let ascending = SortOrder {
+            key: SortKey::Size,
+            ..SortOrder::default()
+        };
+        let descending = SortOrder {
+            descending: true,
+            ..ascending
+        };
+        assert_eq!(sorted(&files, ascending), [2, 3, 0, 1]);
+        assert_eq!(sorted(&files, descending), [0, 3, 2, 1]);
+    }
+
+    #[test]
+    fn random_order_depends_on_the_seed() {
+        let paths: Vec<PathBuf> =
+            (0..20).map(|i| PathBuf::from(format!("{i}.png"))).collect();
+        let files = items(&paths);
+        let shuffle = |seed| {
+            let mut images: Vec<usize> = (0..files.len()).collect();
+            let order = SortOrder {
+                key: SortKey::Random,
+                descending: false,
+                seed,
+            };
+            sort_images(&files, &mut images, order);
+            images
+        };
+        assert_eq!(shuffle(1), shuffle(1));
+        assert_ne!(shuffle(1), shuffle(2));
+        let mut images = shuffle(1);
+        images.sort_unstable();
+        assert_eq!(images, (0..files.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(SortOrder::default().description(), None);
+        let order = SortOrder {
+            key: SortKey::Size,
+            descending: true,
+            seed: 0,
+        };
+        assert!(order.description().unwrap().ends_with(", descending"));
+    }
+}
@@ -0,0 +1,266 @@
+//! Reads and writes thumbnails in the shared cache described by the
+//! freedesktop.org Thumbnail Managing Standard, so that the thumbnails are
+//! shared with file managers and other viewers.
+//!
+//! The thumbnail of a file is a PNG named after the MD5 hash of the file's
+//! URI. Its `Thumb::URI` and `Thumb::MTime` text chunks tell whether it still
+//! belongs to the file.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use directories::BaseDirs;
+use image::{ImageFormat, RgbaImage};
+use md5::{Digest, Md5};
+
+use super::image_loader::errors::*;
+
+/// The subfolders of the cache with the size that their thumbnails fit into
+const FLAVORS: [(&str, u32); 4] = [
+    ("normal", 128),
+    ("large", 256),
+    ("x-large", 512),
+    ("xx-large", 1024),
+];
+
+/// The folder of the thumbnails of one size
+pub struct ThumbnailStore {
+    root: PathBuf,
+    dir: PathBuf,
+    size: u32,
+}
+
+impl ThumbnailStore {
+    /// The store of the smallest thumbnails that are at least `size` pixels
+    /// large. Returns `None` for sizes that the standard doesn't define or if
+    /// there's no cache folder.
+    pub fn for_size(size: u32) -> Option<ThumbnailStore> {
+        let &(name, size) = FLAVORS.iter().find(|(_, s)| *s >= size)?;
+        let root = BaseDirs::new()?.cache_dir().join("thumbnails");
+        let dir = root.join(name);
+        Some(ThumbnailStore { root, dir, size })
+    }
+
+    /// The width and height of the square that the thumbnails fit into
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the stored thumbnail of the image at `path` unless it's
+    /// missing or older than the image.
+    pub fn load(&self, path: &Path) -> Option<RgbaImage> {
+        let source = Source::new(path)?;
+        let bytes = fs::read(self.thumbnail_path(&source.uri)).ok()?;
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let reader = decoder.read_info().ok()?;
+        let info = reader.info();
+        let text = |keyword: &str| {
+            let latin1 = info
+                .uncompressed_latin1_text
+                .iter()
+                .find(|chunk| chunk.keyword == keyword)
+                .map(|chunk| chunk.text.clone());
+            latin1.or_else(|| {
+                info.utf8_text
+                    .iter()
+                    .find(|chunk| chunk.keyword == keyword)
+                    .and_then(|chunk| chunk.get_text().ok())
+            })
+        };
+        if text("Thumb::URI")? != source.uri {
+            return None;
+        }
+        if text("Thumb::MTime")?.parse::<u64>().ok()? != source.mtime {
+            return None;
+        }
+        // The size is optional, but the thumbnail is outdated if it differs
+        if let Some(size) = text("Thumb::Size") {
+            if size.parse::<u64>().ok()? != source.size {
+                return None;
+            }
+        }
+        let image =
+            image::load_from_memory_with_format(&bytes, ImageFormat::Png);
+        image.ok().map(|image| image.into_rgba8())
+    }
+
+    /// Writes the thumbnail of the image at `path` into the store. The
+    /// thumbnail is written to a temporary file first so that other programs
+    /// never read an incomplete one.
+    pub fn save(&self, path: &Path, thumbnail: &RgbaImage) -> Result<()> {
+        let Some(source) = Source::new(path) else {
+            return Err(Error::Msg(format!(
+                "Could not get the URI of {path:?}"
+            )));
+        };
+        if source.path.starts_with(&self.root) {
+            // The standard forbids thumbnails of thumbnails
+            return Ok(());
+        }
+        create_private_dir(&self.dir)?;
+        let mut png_bytes = Vec::new();
+        {
+            let (w, h) = thumbnail.dimensions();
+            let mut encoder = png::Encoder::new(&mut png_bytes, w, h);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let texts = [
+                ("Thumb::URI", source.uri.clone()),
+                ("Thumb::MTime", source.mtime.to_string()),
+                ("Thumb::Size", source.size.to_string()),
+                ("Software", env!("CARGO_PKG_NAME").to_string()),
+            ];
+            for (keyword, text) in texts {
+                encoder
+                    .add_text_chunk(keyword.to_string(), text)
+                    .map_err(|e| Error::Msg(e.to_string()))?;
+            }
+            let mut writer = encoder
+                .write_header()
+                .map_err(|e| Error::Msg(e.to_string()))?;
+            writer
+                .write_image_data(thumbnail.as_raw())
+                .map_err(|e| Error::Msg(e.to_string()))?;
+        }
+        let final_path = self.thumbnail_path(&source.uri);
+        let temp_path = final_path
+            .with_extension(format!("png.{}.tmp", std::process::id()));
+        let result = write_private_file(&temp_path, &png_bytes)
+            .and_then(|()| fs::rename(&temp_path, &final_path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        Ok(result?)
+    }
+
+    fn thumbnail_path(&self, uri: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", md5_hex(uri.as_bytes())))
+    }
+}
+
+/// The properties of the original image that a thumbnail is checked against
+struct Source {
+    path: PathBuf,
+    uri: String,
+    /// The modification time in seconds since the Unix epoch
+    mtime: u64,
+    size: u64,
+}
+
+impl Source {
+    fn new(path: &Path) -> Option<Source> {
+        let path = fs::canonicalize(path).ok()?;
+        let metadata = fs::metadata(&path).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        Some(Source {
+            uri: file_uri(&path),
+            path,
+            mtime,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// The `file://` URI of an absolute path, with the characters that aren't
+/// allowed in URIs percent encoded
+fn file_uri(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = {
+        // Windows paths are written with forward slashes in URIs
+        let path = path.to_string_lossy().replace('\\', "/");
+        let path = path.trim_start_matches("//?/").to_string();
+        format!("/{path}").into_bytes()
+    };
+    let mut uri = String::from("file://");
+    for byte in bytes {
+        let unreserved = byte.is_ascii_alphanumeric()
+            || b"-_.~!$&'()*+,;=:@/".contains(&byte);
+        if unreserved {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// Creates the folder readable by the user only, as the standard requires
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn write_private_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(bytes)
+}
+
+/// The MD5 hash of `data` as lowercase hexadecimal digits. Thumbnails are
+/// named by it, so it's not used for anything security related.
+fn md5_hex(data: &[u8]) -> String {
+    Md5::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_test_suite() {
+        // From appendix A.5 of RFC 1321
+        let cases = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+        ];
+        for (data, hash) in cases {
+            assert_eq!(md5_hex(data.as_bytes()), hash);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn thumbnail_names() {
+        // The example of the Thumbnail Managing Standard
+        let uri = file_uri(Path::new("/home/jens/photos/me.png"));
+        assert_eq!(uri, "file:///home/jens/photos/me.png");
+        assert_eq!(md5_hex(uri.as_bytes()), "c6ee772d9e49320e97ec29a7eb5b1697");
+
+        let uri = file_uri(Path::new("/photos/a b/ü#1.png"));
+        assert_eq!(uri, "file:///photos/a%20b/%C3%BC%231.png");
+    }
+}
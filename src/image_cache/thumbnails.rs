@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::TryRecvError,
+};
+
+use glium::texture::{MipmapsOption, RawImage2d, SrgbTexture2d};
+
+use super::{
+    directory::next_request_id,
+    errors::*,
+    image_loader::{ImageLoader, LoadRequest, LoadResult},
+};
+
+/// The state of the thumbnail of a file
+pub enum Thumbnail {
+    Loading,
+    Failed,
+    Loaded(Rc<SrgbTexture2d>),
+}
+
+struct CachedThumbnail {
+    /// `None` if the image failed to load
+    texture: Option<Rc<SrgbTexture2d>>,
+    size_estimate: isize,
+    /// The frame in which the thumbnail was last used
+    last_used: u64,
+}
+
+/// Loads scaled down versions of images on its own loader threads and keeps
+/// them on the GPU. The thumbnails have a memory budget that is separate from
+/// the one of the `ImageCache`.
+pub struct ThumbnailCache {
+    loader: ImageLoader,
+    /// The thumbnails are scaled to fit into a square of this many pixels
+    size: u32,
+    capacity: isize,
+    used: isize,
+    thumbnails: HashMap<PathBuf, CachedThumbnail>,
+    /// The paths of the requests that haven't finished yet
+    pending: HashMap<u32, PathBuf>,
+    frame: u64,
+}
+
+impl ThumbnailCache {
+    /// Limits the number of requests in the queue of the loader, so that
+    /// scrolling away doesn't leave a lot of thumbnails to be loaded that
+    /// aren't needed anymore.
+    const MAX_PENDING_REQUESTS: usize = 8;
+
+    /// # Arguments
+    /// * `capacity` - Number of bytes. The least recently used thumbnails are
+    ///   released when the thumbnails take up more than this.
+    pub fn new(capacity: isize, threads: u32) -> ThumbnailCache {
+        ThumbnailCache {
+            loader: ImageLoader::new(threads),
+            size: 0,
+            capacity,
+            used: 0,
+            thumbnails: HashMap::new(),
+            pending: HashMap::new(),
+            frame: 0,
+        }
+    }
+
+    /// Sets the size of the square that the thumbnails fit into in pixels.
+    /// Thumbnails of a different size are loaded again.
+    pub fn set_size(&mut self, size: u32) {
+        if size != self.size {
+            self.size = size;
+            self.thumbnails.clear();
+            // The results of these requests are ignored
+            self.pending.clear();
+            self.used = 0;
+        }
+    }
+
+    /// Marks the start of a new frame. The thumbnails that are used in the
+    /// current frame are never released to make room for new ones.
+    pub fn start_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn get(&mut self, path: &Path) -> Thumbnail {
+        match self.thumbnails.get_mut(path) {
+            Some(cached) => {
+                cached.last_used = self.frame;
+                match &cached.texture {
+                    Some(texture) => Thumbnail::Loaded(texture.clone()),
+                    None => Thumbnail::Failed,
+                }
+            }
+            None => Thumbnail::Loading,
+        }
+    }
+
+    /// Sends a load request for the thumbnail unless it's already loaded or
+    /// requested. Returns false if no more requests can be sent at the moment.
+    pub fn request(&mut self, path: &Path) -> bool {
+        if self.thumbnails.contains_key(path)
+            || self.pending.values().any(|pending| pending == path)
+        {
+            return true;
+        }
+        if self.pending.len() >= Self::MAX_PENDING_REQUESTS {
+            return false;
+        }
+        let req_id = next_request_id();
+        self.pending.insert(req_id, path.to_owned());
+        self.loader.send_load_request(LoadRequest {
+            req_id,
+            path: path.to_owned(),
+            thumbnail_size: Some(self.size),
+        });
+        true
+    }
+
+    pub fn loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Uploads the thumbnails that the loader has finished. Returns true if
+    /// any thumbnail changed.
+    pub fn receive(&mut self, display: &glium::Display) -> Result<bool> {
+        let mut changed = false;
+        loop {
+            let load_result = match self.loader.try_recv_prefetched() {
+                Ok(load_result) => load_result,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    panic!("Channel disconnected unexpectidly.")
+                }
+            };
+            match load_result {
+                LoadResult::Start { .. } => (),
+                LoadResult::Frame { req_id, image, .. } => {
+                    let Some(path) = self.pending.get(&req_id).cloned() else {
+                        continue;
+                    };
+                    let (w, h) = image.dimensions();
+                    let raw_image =
+                        RawImage2d::from_raw_rgba(image.into_raw(), (w, h));
+                    let texture = SrgbTexture2d::with_mipmaps(
+                        display,
+                        raw_image,
+                        MipmapsOption::NoMipmap,
+                    )?;
+                    self.insert(
+                        path,
+                        Some(Rc::new(texture)),
+                        (w * h * 4) as isize,
+                    );
+                    changed = true;
+                }
+                LoadResult::Done { req_id } => {
+                    self.pending.remove(&req_id);
+                }
+                LoadResult::Failed { req_id } => {
+                    if let Some(path) = self.pending.remove(&req_id) {
+                        self.insert(path, None, 0);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn insert(
+        &mut self,
+        path: PathBuf,
+        texture: Option<Rc<SrgbTexture2d>>,
+        size_estimate: isize,
+    ) {
+        let cached = CachedThumbnail {
+            texture,
+            size_estimate,
+            last_used: self.frame,
+        };
+        if let Some(prev) = self.thumbnails.insert(path, cached) {
+            self.used -= prev.size_estimate;
+        }
+        self.used += size_estimate;
+        while self.used > self.capacity {
+            let least_recent = self
+                .thumbnails
+                .iter()
+                .filter(|(_, cached)| cached.last_used < self.frame)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(path, _)| path.clone());
+            match least_recent.and_then(|p| self.thumbnails.remove(&p)) {
+                Some(removed) => self.used -= removed.size_estimate,
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Watches the opened folder for files that are created, renamed or removed,
+//! so that the list of files can be updated without listing the folder again.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+use log::debug;
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+/// The paths in a watched folder that changed since the last check
+#[derive(Debug, Default)]
+pub struct FolderChanges {
+    /// Files and folders that were created, renamed or removed. Whether a
+    /// path exists now tells which one happened.
+    pub paths: Vec<PathBuf>,
+    /// Files whose contents were written or replaced
+    pub written: Vec<PathBuf>,
+    /// Set if some changes were missed, so the folder has to be listed again
+    pub rescan: bool,
+}
+
+impl FolderChanges {
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.written.is_empty() && !self.rescan
+    }
+}
+
+pub struct FolderWatcher {
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    path: PathBuf,
+    recursive: bool,
+}
+
+impl FolderWatcher {
+    /// Starts watching `path`, and all of its subfolders if `recursive` is
+    /// set
+    pub fn new(path: &Path, recursive: bool) -> notify::Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher.watch(path, mode)?;
+        Ok(FolderWatcher {
+            _watcher: watcher,
+            events,
+            path: path.to_owned(),
+            recursive,
+        })
+    }
+
+    /// Returns whether this watches the same files as a new watcher for
+    /// `path` would
+    pub fn watches(&self, path: &Path, recursive: bool) -> bool {
+        self.path == path && self.recursive == recursive
+    }
+
+    /// Collects the changes that happened since the last call
+    pub fn changes(&self) -> FolderChanges {
+        let mut changes = FolderChanges::default();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    debug!("Error while watching {:?}: {}", self.path, e);
+                    changes.rescan = true;
+                    continue;
+                }
+            };
+            if event.need_rescan() {
+                changes.rescan = true;
+            }
+            let (listing_changed, written) = match event.kind {
+                EventKind::Remove(_) => (true, false),
+                // A file can be replaced by renaming another one over it
+                EventKind::Create(_)
+                | EventKind::Modify(ModifyKind::Name(_))
+                | EventKind::Any => (true, true),
+                EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+                | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                    (false, true)
+                }
+                _ => (false, false),
+            };
+            for path in event.paths {
+                if listing_changed {
+                    push_unique(&mut changes.paths, &path);
+                }
+                if written {
+                    push_unique(&mut changes.written, &path);
+                }
+            }
+        }
+        changes
+    }
+}
+
+fn push_unique(paths: &mut Vec<PathBuf>, path: &Path) {
+    if !paths.iter().any(|p| p == path) {
+        paths.push(path.to_owned());
+    }
+}
@@ -13,12 +13,39 @@ pub static TOGGLE_FULLSCREEN_NAME: &str = "toggle_fullscreen";
 pub static ESCAPE_NAME: &str = "escape";
 pub static IMG_NEXT_NAME: &str = "img_next";
 pub static IMG_PREV_NAME: &str = "img_prev";
+pub static IMG_FIRST_NAME: &str = "img_first";
+pub static IMG_LAST_NAME: &str = "img_last";
+pub static IMG_GOTO_NAME: &str = "img_goto";
 pub static IMG_ORIG_NAME: &str = "img_orig";
 pub static IMG_FIT_NAME: &str = "img_fit";
 pub static IMG_FIT_BEST_NAME: &str = "img_fit_best";
+pub static IMG_FIT_WIDTH_NAME: &str = "img_fit_width";
+pub static IMG_FIT_HEIGHT_NAME: &str = "img_fit_height";
 pub static IMG_DEL_NAME: &str = "img_del";
 pub static IMG_COPY_NAME: &str = "img_copy";
 pub static PAN_NAME: &str = "pan";
+pub static TOGGLE_LOCK_VIEW_NAME: &str = "toggle_lock_view";
+pub static IMG_ROTATE_CW_NAME: &str = "img_rotate_cw";
+pub static IMG_ROTATE_CCW_NAME: &str = "img_rotate_ccw";
+pub static IMG_FLIP_HOR_NAME: &str = "img_flip_hor";
+pub static IMG_FLIP_VER_NAME: &str = "img_flip_ver";
+pub static IMG_SAVE_ORIENTATION_NAME: &str = "img_save_orientation";
+pub static TOGGLE_CROP_NAME: &str = "toggle_crop";
+pub static CROP_ASPECT_NAME: &str = "crop_aspect";
+pub static CROP_SAVE_NAME: &str = "crop_save";
+pub static SAVE_AS_NAME: &str = "save_as";
+pub static TOGGLE_COMPARE_NAME: &str = "toggle_compare";
+pub static COMPARE_PIN_NAME: &str = "compare_pin";
+pub static COMPARE_TARGET_NAME: &str = "compare_target";
+pub static COMPARE_LAYOUT_NAME: &str = "compare_layout";
+pub static COMPARE_ANCHOR_NAME: &str = "compare_anchor";
+pub static TOGGLE_GALLERY_NAME: &str = "toggle_gallery";
+pub static TOGGLE_RECURSIVE_NAME: &str = "toggle_recursive";
+pub static TOGGLE_FOLLOW_NAME: &str = "toggle_follow";
+pub static FOLDER_NEXT_NAME: &str = "folder_next";
+pub static FOLDER_PREV_NAME: &str = "folder_prev";
+pub static SORT_NEXT_NAME: &str = "sort_next";
+pub static SORT_REVERSE_NAME: &str = "sort_reverse";
 pub static PLAY_ANIM_NAME: &str = "play_anim";
 pub static PLAY_PRESENT_NAME: &str = "play_present";
 pub static PLAY_PRESENT_RND_NAME: &str = "play_present_rnd";
@@ -38,12 +65,39 @@ lazy_static! {
         m.insert(ESCAPE_NAME, vec!["Escape"]);
         m.insert(IMG_NEXT_NAME, vec!["D", "Right", "PageDown"]);
         m.insert(IMG_PREV_NAME, vec!["A", "Left", "PageUp"]);
-        m.insert(IMG_ORIG_NAME, vec!["Q", "1"]);
+        m.insert(IMG_FIRST_NAME, vec!["Home"]);
+        m.insert(IMG_LAST_NAME, vec!["End"]);
+        m.insert(IMG_GOTO_NAME, vec!["CmdCtrl+G"]);
+        m.insert(IMG_ORIG_NAME, vec!["Q"]);
         m.insert(IMG_FIT_NAME, vec!["F"]);
         m.insert(IMG_FIT_BEST_NAME, vec!["E"]);
+        m.insert(IMG_FIT_WIDTH_NAME, vec!["W"]);
+        m.insert(IMG_FIT_HEIGHT_NAME, vec!["H"]);
         m.insert(IMG_DEL_NAME, vec!["Delete"]);
         m.insert(IMG_COPY_NAME, vec!["CmdCtrl+C"]);
         m.insert(PAN_NAME, vec!["Space"]);
+        m.insert(TOGGLE_LOCK_VIEW_NAME, vec!["L"]);
+        m.insert(IMG_ROTATE_CW_NAME, vec!["R"]);
+        m.insert(IMG_ROTATE_CCW_NAME, vec!["Alt+R"]);
+        m.insert(IMG_FLIP_HOR_NAME, vec!["X"]);
+        m.insert(IMG_FLIP_VER_NAME, vec!["Y"]);
+        m.insert(IMG_SAVE_ORIENTATION_NAME, vec!["CmdCtrl+R"]);
+        m.insert(TOGGLE_CROP_NAME, vec!["C"]);
+        m.insert(CROP_ASPECT_NAME, vec!["Alt+C"]);
+        m.insert(CROP_SAVE_NAME, vec!["CmdCtrl+S"]);
+        m.insert(SAVE_AS_NAME, vec!["CmdCtrl+E"]);
+        m.insert(TOGGLE_COMPARE_NAME, vec!["V"]);
+        m.insert(COMPARE_PIN_NAME, vec!["B"]);
+        m.insert(COMPARE_TARGET_NAME, vec!["T"]);
+        m.insert(COMPARE_LAYOUT_NAME, vec!["M"]);
+        m.insert(COMPARE_ANCHOR_NAME, vec!["Alt+M"]);
+        m.insert(TOGGLE_GALLERY_NAME, vec!["G"]);
+        m.insert(TOGGLE_RECURSIVE_NAME, vec!["Alt+D"]);
+        m.insert(TOGGLE_FOLLOW_NAME, vec!["N"]);
+        m.insert(FOLDER_NEXT_NAME, vec!["Alt+Right", "Alt+PageDown"]);
+        m.insert(FOLDER_PREV_NAME, vec!["Alt+Left", "Alt+PageUp"]);
+        m.insert(SORT_NEXT_NAME, vec!["O"]);
+        m.insert(SORT_REVERSE_NAME, vec!["Alt+O"]);
         m.insert(PLAY_ANIM_NAME, vec!["Alt+A", "Alt+V"]);
         m.insert(PLAY_PRESENT_NAME, vec!["P"]);
         m.insert(PLAY_PRESENT_RND_NAME, vec!["Alt+P"]);
@@ -67,6 +121,34 @@ pub fn char_to_input_key(ch: char) -> String {
     input_key
 }
 
+/// Returns the digit of the input key if it types one without a modifier
+/// other than shift
+pub fn count_digit(input_key: &str, modifiers: ModifiersState) -> Option<u32> {
+    if modifiers.alt() || modifiers.ctrl() || modifiers.logo() {
+        return None;
+    }
+    let mut chars = input_key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => ch.to_digit(10),
+        _ => None,
+    }
+}
+
+/// Returns true for the input keys of the modifier keys themselves
+pub fn is_modifier_key(input_key: &str) -> bool {
+    matches!(
+        input_key,
+        "lshift"
+            | "rshift"
+            | "lcontrol"
+            | "rcontrol"
+            | "lalt"
+            | "ralt"
+            | "lwin"
+            | "rwin"
+    )
+}
+
 fn substitute_command_parameters(
     string: &str,
     var_map: &HashMap<&str, &str>,
@@ -177,3 +259,25 @@ pub fn action_triggered(
         keys_triggered(keys.as_slice(), input_key, modifiers)
     }
 }
+
+/// Returns true if the input key with the modifiers triggers an action or a
+/// custom command
+pub fn key_is_bound(
+    config: &Arc<Mutex<Configuration>>,
+    input_key: &str,
+    modifiers: ModifiersState,
+) -> bool {
+    let config = config.lock().unwrap();
+    let bindings = config.bindings.as_ref();
+    let action_bound = DEFAULT_BINDINGS.iter().any(|(name, default_keys)| {
+        match bindings.and_then(|b| b.get(*name)) {
+            Some(keys) => keys_triggered(keys, input_key, modifiers),
+            None => keys_triggered(default_keys, input_key, modifiers),
+        }
+    });
+    let command_bound =
+        config.commands.iter().flatten().any(|command| {
+            keys_triggered(&command.input, input_key, modifiers)
+        });
+    action_bound || command_bound
+}
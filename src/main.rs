@@ -10,7 +10,7 @@ use std::{
 use log::trace;
 
 use crate::{
-    configuration::{Configuration, Theme, WindowMode},
+    configuration::{Configuration, ScalingMode, Theme, WindowMode},
     gelatin::{
         application::*,
         glium::glutin::window::Icon,
@@ -21,24 +21,37 @@ use crate::{
         picture::*,
         window::{Window, WindowDescriptor},
     },
+    image_cache::{
+        filter::{normalize_extension, ImageFilter, NamePattern},
+        image_loader::{self, STDIN_PATH},
+    },
     widgets::{
         bottom_bar::BottomBar, copy_notification::CopyNotifications,
-        help_screen::*, picture_widget::*,
+        gallery::Gallery, help_screen::*, notification::Notification,
+        picture_widget::*, prompt::Prompt, slider_preview::SliderPreview,
     },
 };
 
 mod clipboard_handler;
 mod cmd_line;
 mod configuration;
+mod convert;
+mod diff;
+mod export;
 mod gelatin;
 mod handle_panic;
 mod image_cache;
 mod input_handling;
+mod orientation_saver;
 mod parallel_action;
 mod playback_manager;
+mod playlist;
 mod shaders;
+#[cfg(test)]
+mod temp_dir;
 mod utils;
 mod version;
+mod view_memory;
 mod widgets;
 
 static USAGE: &[u8] = include_bytes!("../resource/usage.png");
@@ -55,6 +68,22 @@ fn main() {
     let args = cmd_line::parse_args();
 
     let config = Configuration::load();
+    if let Some(convert_args) = args.convert {
+        let config = config.unwrap_or_default();
+        std::process::exit(convert::run(convert_args, &config));
+    }
+    if let Some(diff_args) = args.diff {
+        std::process::exit(diff::run(diff_args));
+    }
+    // The standard input is read before the window opens, since the program
+    // that writes into it may still be running
+    if args.paths.iter().any(|path| path == STDIN_PATH) {
+        if let Err(e) = image_loader::read_stdin_image() {
+            eprintln!("Could not read the image from the standard input: {e}");
+            std::process::exit(1);
+        }
+    }
+    let list_entries = playlist::command_line_entries(&args);
     let first_launch = config.is_err();
     let config = Arc::new(Mutex::new(config.unwrap_or_default()));
 
@@ -81,21 +110,57 @@ fn main() {
     let copy_notifications_widget = Rc::new(Label::new());
     let copy_notifications = CopyNotifications::new(&copy_notifications_widget);
 
-    let bottom_bar = Rc::new(BottomBar::new());
+    let filmstrip_position = config.lock().unwrap().filmstrip_position();
+    let bottom_bar = Rc::new(BottomBar::new(filmstrip_position));
+    let gallery = make_gallery(&window, config.clone());
+    let slider_preview = Rc::new(SliderPreview::new());
+    let notification = Rc::new(Notification::new());
+    let prompt = Rc::new(Prompt::new());
     let picture_widget = make_picture_widget(
         &window,
         bottom_bar.clone(),
+        gallery.clone(),
         left_to_pan_hint.clone(),
         copy_notifications,
+        notification.clone(),
+        prompt.clone(),
         config.clone(),
     );
 
-    if let Some(file_path) = args.file_path {
-        picture_widget.jump_to_path(file_path);
+    {
+        let config = config.lock().unwrap();
+        let recursive = args.recursive || config.recursive();
+        let max_depth = args.max_depth.unwrap_or_else(|| config.max_depth());
+        picture_widget.set_recursive(recursive, max_depth);
+
+        let filter = ImageFilter {
+            name: args.name_filter.or_else(|| config_name_filter(&config)),
+            extensions: args.extensions.unwrap_or_else(|| {
+                let extensions = config.extensions().iter();
+                extensions.map(|e| normalize_extension(e)).collect()
+            }),
+            orientation: args.orientation.or(config.orientation()),
+        };
+        if !filter.is_empty() {
+            picture_widget.set_filter(filter);
+        }
+    }
+    match (list_entries, args.paths.into_iter().next()) {
+        (Some(entries), _) => picture_widget.open_list(entries),
+        (None, Some(path)) => picture_widget.jump_to_path(path),
+        (None, None) => (),
+    }
+    // After opening the image, since opening another image stops following
+    if args.follow {
+        picture_widget.set_follow_newest(true);
     }
 
     let picture_area_container = make_picture_area_container();
     picture_area_container.add_child(picture_widget.clone());
+    picture_area_container.add_child(gallery.clone());
+    picture_area_container.add_child(slider_preview.clone());
+    picture_area_container.add_child(notification);
+    picture_area_container.add_child(prompt);
     picture_area_container.add_child(copy_notifications_widget);
     picture_area_container.add_child(left_to_pan_hint);
     picture_area_container.add_child(help_screen.clone());
@@ -104,8 +169,17 @@ fn main() {
     root_container.add_child(picture_area_container);
     root_container.add_child(bottom_bar.widget.clone());
 
+    {
+        let picture_widget = picture_widget.clone();
+        gallery.set_on_close(move |path| {
+            picture_widget.show_from_gallery(path);
+        });
+    }
+
     let set_theme = {
         let picture_widget = picture_widget.clone();
+        let gallery = gallery.clone();
+        let slider_preview = slider_preview.clone();
         let window = window.clone();
         let bottom_bar = bottom_bar.clone();
         let config = config.clone();
@@ -115,10 +189,14 @@ fn main() {
             match theme {
                 Theme::Light => {
                     picture_widget.set_bright_shade(0.96);
+                    gallery.set_bright_shade(0.96);
+                    slider_preview.set_bright_shade(0.96);
                     window.set_bg_color([0.85, 0.85, 0.85, 1.0]);
                 }
                 Theme::Dark => {
                     picture_widget.set_bright_shade(0.11);
+                    gallery.set_bright_shade(0.11);
+                    slider_preview.set_bright_shade(0.11);
                     window.set_bg_color([0.03, 0.03, 0.03, 1.0]);
                 }
             }
@@ -142,6 +220,26 @@ fn main() {
             picture_widget.jump_to_index(slider.value());
         });
     }
+    {
+        let slider = bottom_bar.slider.clone();
+        let picture_widget = picture_widget.clone();
+        bottom_bar.slider.set_on_preview_change(move || {
+            let preview = slider.preview().and_then(|index| {
+                let path = picture_widget.image_path_at_index(index)?;
+                Some((path, slider.step_position(index)))
+            });
+            match preview {
+                Some((path, position)) => slider_preview.show(&path, position),
+                None => slider_preview.hide(),
+            }
+        });
+    }
+    {
+        let picture_widget = picture_widget.clone();
+        bottom_bar.filmstrip.set_on_click(move |index| {
+            picture_widget.jump_to_index(index as u32);
+        });
+    }
     {
         let picture_widget = picture_widget.clone();
         bottom_bar.orig_scale_button.set_on_click(move || {
@@ -151,12 +249,31 @@ fn main() {
     {
         let picture_widget = picture_widget.clone();
         bottom_bar.fit_best_button.set_on_click(move || {
-            picture_widget.set_img_size_to_fit(false);
+            picture_widget.set_img_size_to_fit(ScalingMode::FitMin);
         });
     }
     {
+        let picture_widget = picture_widget.clone();
         bottom_bar.fit_stretch_button.set_on_click(move || {
-            picture_widget.set_img_size_to_fit(true);
+            picture_widget.set_img_size_to_fit(ScalingMode::FitStretch);
+        });
+    }
+    {
+        let picture_widget = picture_widget.clone();
+        bottom_bar.fit_width_button.set_on_click(move || {
+            picture_widget.set_img_size_to_fit(ScalingMode::FitWidth);
+        });
+    }
+    {
+        let picture_widget = picture_widget.clone();
+        bottom_bar.fit_height_button.set_on_click(move || {
+            picture_widget.set_img_size_to_fit(ScalingMode::FitHeight);
+        });
+    }
+    {
+        let picture_widget = picture_widget.clone();
+        bottom_bar.lock_view_button.set_on_click(move || {
+            picture_widget.toggle_view_lock();
         });
     }
     let help_visible = Cell::new(first_launch);
@@ -176,6 +293,7 @@ fn main() {
     window.set_root(root_container);
 
     application.set_at_exit(Some(move || {
+        picture_widget.save_view_memory();
         config.lock().unwrap().save().unwrap();
     }));
     application.start_event_loop();
@@ -219,19 +337,54 @@ fn make_picture_area_container() -> Rc<VerticalLayoutContainer> {
     picture_area_container
 }
 
+fn make_gallery(
+    window: &Rc<Window>,
+    config: Arc<Mutex<Configuration>>,
+) -> Rc<Gallery> {
+    let gallery = Rc::new(Gallery::new(&window.display_mut(), config));
+    gallery.set_height(Length::Stretch {
+        min: 0.0,
+        max: f32::INFINITY,
+    });
+    gallery.set_width(Length::Stretch {
+        min: 0.0,
+        max: f32::INFINITY,
+    });
+    gallery
+}
+
+/// The file name pattern of the `[folder]` section. The regular expression
+/// is used if both are given.
+fn config_name_filter(config: &Configuration) -> Option<NamePattern> {
+    if let Some(regex) = config.filter_regex() {
+        match NamePattern::regex(regex) {
+            Ok(pattern) => return Some(pattern),
+            Err(e) => eprintln!("Ignoring the filter_regex setting, {e}"),
+        }
+    }
+    config.filter().map(NamePattern::wildcard)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn make_picture_widget(
     window: &Rc<Window>,
     bottom_bar: Rc<BottomBar>,
+    gallery: Rc<Gallery>,
     left_to_pan_hint: Rc<HelpScreen>,
     copy_notifications: CopyNotifications,
+    notification: Rc<Notification>,
+    prompt: Rc<Prompt>,
     config: Arc<Mutex<Configuration>>,
 ) -> Rc<PictureWidget> {
     let picture_widget = Rc::new(PictureWidget::new(
         &window.display_mut(),
         window,
         bottom_bar,
+        gallery,
         left_to_pan_hint,
         copy_notifications,
+        notification,
+        prompt,
         config,
     ));
     picture_widget.set_height(Length::Stretch {
@@ -0,0 +1,556 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use exif::{experimental::Writer, Field, In, Tag, Value};
+use image::{codecs::png::PngDecoder, DynamicImage, ImageFormat};
+
+use crate::image_cache::image_loader::{
+    apply_orientation, detect_format, errors::*, is_stdin_image, ImgFormat,
+    Orientation,
+};
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_TYPE_SHORT: u16 = 3;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Makes the image file at `path` appear with `orientation` when opened,
+/// without losing any image quality or metadata.
+///
+/// The Exif Orientation tag is updated when the file already has one. JPEG
+/// files without the tag get a new Exif segment. PNG files are re-encoded
+/// with their pixels transformed, keeping their other chunks. A few formats
+/// without metadata are re-encoded as well, other files are refused.
+///
+/// The new contents are written into a temporary file next to the original,
+/// which then replaces it, so the original is kept if anything fails.
+pub fn save_orientation(path: &Path, orientation: Orientation) -> Result<()> {
+    if is_stdin_image(path) {
+        return Err(Error::Msg(
+            "The image from the standard input is not stored in a file".into(),
+        ));
+    }
+    let format = match detect_format(path)? {
+        ImgFormat::Image(format) => format,
+        ImgFormat::Svg => {
+            return Err(Error::Msg(
+                "Saving the orientation of SVG files is not supported".into(),
+            ))
+        }
+    };
+    let mut data = fs::read(path)?;
+    let data = match format {
+        ImageFormat::Jpeg | ImageFormat::Tiff | ImageFormat::WebP
+            if patch_exif_orientation(&mut data, orientation) =>
+        {
+            data
+        }
+        ImageFormat::Jpeg => {
+            let exif = exif::Reader::new()
+                .read_from_container(&mut Cursor::new(&data))
+                .ok();
+            write_jpeg_exif(&data, exif.as_ref(), orientation, false)?
+        }
+        ImageFormat::Png => reencode_png(&data, orientation)?,
+        ImageFormat::Bmp | ImageFormat::Qoi | ImageFormat::Farbfeld => {
+            if let Some(lost) = lost_metadata(&data, format) {
+                return Err(Error::Msg(format!(
+                    "Saving the orientation would lose the {lost} of the file"
+                )));
+            }
+            let image = image::load_from_memory_with_format(&data, format)?;
+            let mut encoded = Cursor::new(Vec::new());
+            orient_dynamic_image(image, orientation)?
+                .write_to(&mut encoded, format)?;
+            encoded.into_inner()
+        }
+        ImageFormat::Tiff | ImageFormat::WebP => {
+            return Err(Error::Msg(format!(
+                "Saving the orientation of {format:?} files without an Exif \
+                Orientation tag is not supported"
+            )));
+        }
+        format => {
+            return Err(Error::Msg(format!(
+                "Saving the orientation is not supported for {format:?} files"
+            )));
+        }
+    };
+    replace_file(path, &data)
+}
+
+/// Replaces the contents of the file at `path` with `data`. The data is
+/// written into a temporary file in the same folder first, which is then
+/// renamed over the original, so the original stays intact if writing fails.
+pub fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    // Replace the file that a symlink points to rather than the link
+    let path = fs::canonicalize(path)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::Msg(format!("Invalid file path {path:?}")))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    let permissions = fs::metadata(&path)?.permissions();
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::set_permissions(&temp_path, permissions))
+        .and_then(|()| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+/// Returns a copy of the PNG file `data` with its pixels transformed by
+/// `orientation`. The ancillary chunks are carried across: the pixel density
+/// is swapped along with the dimensions and the orientation in the Exif
+/// chunk is reset, since it's applied to the pixels. Fails if the file has a
+/// chunk that isn't valid anymore once the pixels are re-encoded.
+fn reencode_png(data: &[u8], orientation: Orientation) -> Result<Vec<u8>> {
+    if PngDecoder::new(Cursor::new(data))?.is_apng() {
+        return Err(Error::Msg(
+            "Saving the orientation of animations is not supported".into(),
+        ));
+    }
+    let mut carried = Vec::new();
+    for (kind, body) in png_chunks(data)? {
+        match &kind {
+            // Written by the encoder, the transparency becomes an alpha
+            // channel when decoded. The modification time isn't kept since
+            // the image is modified.
+            b"IHDR" | b"PLTE" | b"tRNS" | b"IDAT" | b"IEND" | b"tIME" => (),
+            b"pHYs" if orientation.swaps_dimensions() && body.len() == 9 => {
+                let mut body = body.to_vec();
+                body[..8].rotate_left(4);
+                carried.push((kind, body));
+            }
+            b"eXIf" => {
+                let mut body = body.to_vec();
+                patch_tiff_orientation(&mut body, Orientation::Deg0);
+                carried.push((kind, body));
+            }
+            // The color space chunks only depend on the sample values, which
+            // stay the same
+            b"gAMA" | b"cHRM" | b"sRGB" | b"iCCP" | b"cICP" => {
+                carried.push((kind, body.to_vec()));
+            }
+            // Ancillary chunks that are marked as safe to copy by the PNG
+            // specification
+            _ if kind[0].is_ascii_lowercase()
+                && kind[3].is_ascii_lowercase() =>
+            {
+                carried.push((kind, body.to_vec()));
+            }
+            _ => {
+                return Err(Error::Msg(format!(
+                    "Saving the orientation would lose the {} chunk of the \
+                    PNG file",
+                    String::from_utf8_lossy(&kind)
+                )));
+            }
+        }
+    }
+    let image = image::load_from_memory_with_format(data, ImageFormat::Png)?;
+    let mut encoded = Cursor::new(Vec::new());
+    orient_dynamic_image(image, orientation)?
+        .write_to(&mut encoded, ImageFormat::Png)?;
+    let encoded = encoded.into_inner();
+    // The chunks are placed right after the header chunk, which the encoder
+    // writes first
+    let header_end = PNG_SIGNATURE.len() + 12 + 13;
+    let mut result = encoded[..header_end].to_vec();
+    for (kind, body) in carried {
+        let len = u32::try_from(body.len())
+            .map_err(|_| Error::Msg("Invalid PNG chunk".into()))?;
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&kind);
+        crc.update(&body);
+        result.extend_from_slice(&len.to_be_bytes());
+        result.extend_from_slice(&kind);
+        result.extend_from_slice(&body);
+        result.extend_from_slice(&crc.finalize().to_be_bytes());
+    }
+    result.extend_from_slice(&encoded[header_end..]);
+    Ok(result)
+}
+
+/// Splits the PNG file `data` into the types and the contents of its chunks
+fn png_chunks(data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let invalid = || Error::Msg("Invalid PNG file".into());
+    let mut rest = data.strip_prefix(PNG_SIGNATURE).ok_or_else(invalid)?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let (len, kind) = match rest.get(..8) {
+            Some(&[a, b, c, d, e, f, g, h]) => {
+                (u32::from_be_bytes([a, b, c, d]) as usize, [e, f, g, h])
+            }
+            _ => return Err(invalid()),
+        };
+        let body = rest.get(8..8 + len).ok_or_else(invalid)?;
+        chunks.push((kind, body));
+        // Skip the checksum
+        rest = rest.get(12 + len..).ok_or_else(invalid)?;
+    }
+    Ok(chunks)
+}
+
+/// Describes the information in a BMP, QOI or Farbfeld file that the
+/// encoder of the format wouldn't write, or returns `None` if there's none
+fn lost_metadata(data: &[u8], format: ImageFormat) -> Option<&'static str> {
+    let u32_at = |at: usize| {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    match format {
+        ImageFormat::Bmp => {
+            let header_size = u32_at(14)?;
+            // Newer headers have a color space and may have an ICC profile
+            if header_size > 40 {
+                return Some("color space");
+            }
+            let has_resolution = header_size == 40
+                && (u32_at(38) != Some(0) || u32_at(42) != Some(0));
+            has_resolution.then_some("resolution")
+        }
+        // The encoder always writes sRGB
+        ImageFormat::Qoi => (data.get(13) != Some(&0)).then_some("color space"),
+        _ => None,
+    }
+}
+
+/// Overwrites the value of the existing Exif Orientation tag in `data`.
+/// Returns false if there is no such tag.
+fn patch_exif_orientation(data: &mut [u8], orientation: Orientation) -> bool {
+    let exif = match exif::Reader::new()
+        .read_from_container(&mut Cursor::new(&data[..]))
+    {
+        Ok(exif) => exif,
+        Err(_) => return false,
+    };
+    // The reader only gives us a copy of the Exif data, so find it in the file.
+    let tiff = exif.buf();
+    let start = match data.windows(tiff.len()).position(|w| w == tiff) {
+        Some(start) => start,
+        None => return false,
+    };
+    patch_tiff_orientation(&mut data[start..start + tiff.len()], orientation)
+}
+
+/// Overwrites the value of the Orientation tag in the Exif data `tiff`, which
+/// is in the TIFF format. Returns false if there is no such tag.
+fn patch_tiff_orientation(tiff: &mut [u8], orientation: Orientation) -> bool {
+    let le = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let read_u16 = |buf: &[u8], at: usize| {
+        let bytes = [*buf.get(at)?, *buf.get(at + 1)?];
+        Some(if le {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let ifd_offset = match tiff.get(4..8) {
+        Some(b) if le => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        Some(b) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        None => return false,
+    } as usize;
+    let entry_count = match read_u16(tiff, ifd_offset) {
+        Some(count) => count as usize,
+        None => return false,
+    };
+    for i in 0..entry_count {
+        let entry = ifd_offset + 2 + i * 12;
+        if read_u16(tiff, entry) != Some(ORIENTATION_TAG) {
+            continue;
+        }
+        if read_u16(tiff, entry + 2) != Some(EXIF_TYPE_SHORT) {
+            return false;
+        }
+        let value = orientation.to_exif();
+        let bytes = if le {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        return match tiff.get_mut(entry + 8..entry + 10) {
+            Some(target) => {
+                target.copy_from_slice(&bytes);
+                true
+            }
+            None => false,
+        };
+    }
+    false
+}
+
+/// Returns a copy of the JPEG file `data` with its Exif segment replaced by
+/// the fields of `exif` and the Orientation tag set to `orientation`.
+///
+/// When `pixels_changed` is set, the fields that describe the pixels, like
+/// the thumbnail and the dimensions, are left out.
+pub fn write_jpeg_exif(
+    data: &[u8],
+    exif: Option<&exif::Exif>,
+    orientation: Orientation,
+    pixels_changed: bool,
+) -> Result<Vec<u8>> {
+    let invalid = || Error::Msg("Invalid JPEG file".into());
+    if data.get(0..2) != Some(&[0xFF, 0xD8]) {
+        return Err(invalid());
+    }
+    // The new segment replaces the existing Exif segment or it's placed after
+    // the JFIF segment if there is one.
+    let mut replaced = (2, 2);
+    let mut pos = 2;
+    while let Some(&[0xFF, marker, len_hi, len_lo]) = data.get(pos..pos + 4) {
+        // Only the application segments at the start are of interest
+        if !(0xE0..=0xEF).contains(&marker) {
+            break;
+        }
+        let end = pos + 2 + u16::from_be_bytes([len_hi, len_lo]) as usize;
+        let payload = data.get(pos + 4..end).ok_or_else(invalid)?;
+        if marker == 0xE1 && payload.starts_with(EXIF_HEADER) {
+            replaced = (pos, end);
+            break;
+        }
+        if marker == 0xE0 && replaced.0 == pos {
+            replaced = (end, end);
+        }
+        pos = end;
+    }
+
+    let orientation_field = Field {
+        tag: Tag::Orientation,
+        ifd_num: In::PRIMARY,
+        value: Value::Short(vec![orientation.to_exif()]),
+    };
+    let mut writer = Writer::new();
+    writer.push_field(&orientation_field);
+    let mut little_endian = false;
+    let mut thumbnail = None;
+    if let Some(exif) = exif {
+        little_endian = exif.little_endian();
+        for field in exif.fields() {
+            let describes_pixels = matches!(
+                field.tag,
+                Tag::PixelXDimension | Tag::PixelYDimension
+            ) || field.ifd_num == In::THUMBNAIL;
+            if pixels_changed && describes_pixels {
+                continue;
+            }
+            if field.tag != Tag::Orientation || field.ifd_num != In::PRIMARY {
+                writer.push_field(field);
+            }
+        }
+        let thumbnail_field = |tag| {
+            exif.get_field(tag, In::THUMBNAIL)
+                .and_then(|f| f.value.get_uint(0))
+                .map(|v| v as usize)
+        };
+        if let (Some(offset), Some(len)) = (
+            thumbnail_field(Tag::JPEGInterchangeFormat),
+            thumbnail_field(Tag::JPEGInterchangeFormatLength),
+        ) {
+            thumbnail = exif.buf().get(offset..offset + len);
+        }
+    }
+    if let (Some(thumbnail), false) = (thumbnail, pixels_changed) {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer.write(&mut tiff, little_endian)?;
+    let tiff = tiff.into_inner();
+
+    let segment_len = 2 + EXIF_HEADER.len() + tiff.len();
+    let segment_len = u16::try_from(segment_len).map_err(|_| {
+        Error::Msg("The Exif data doesn't fit into a JPEG segment".into())
+    })?;
+    let mut result = Vec::with_capacity(data.len() + tiff.len() + 10);
+    result.extend_from_slice(&data[..replaced.0]);
+    result.extend_from_slice(&[0xFF, 0xE1]);
+    result.extend_from_slice(&segment_len.to_be_bytes());
+    result.extend_from_slice(EXIF_HEADER);
+    result.extend_from_slice(&tiff);
+    result.extend_from_slice(&data[replaced.1..]);
+    Ok(result)
+}
+
+fn orient_dynamic_image(
+    image: DynamicImage,
+    orientation: Orientation,
+) -> Result<DynamicImage> {
+    use DynamicImage::*;
+    let o = orientation;
+    Ok(match image {
+        ImageLuma8(image) => ImageLuma8(apply_orientation(image, o)),
+        ImageLumaA8(image) => ImageLumaA8(apply_orientation(image, o)),
+        ImageRgb8(image) => ImageRgb8(apply_orientation(image, o)),
+        ImageRgba8(image) => ImageRgba8(apply_orientation(image, o)),
+        ImageLuma16(image) => ImageLuma16(apply_orientation(image, o)),
+        ImageLumaA16(image) => ImageLumaA16(apply_orientation(image, o)),
+        ImageRgb16(image) => ImageRgb16(apply_orientation(image, o)),
+        ImageRgba16(image) => ImageRgba16(apply_orientation(image, o)),
+        ImageRgb32F(image) => ImageRgb32F(apply_orientation(image, o)),
+        ImageRgba32F(image) => ImageRgba32F(apply_orientation(image, o)),
+        _ => {
+            return Err(Error::Msg("Unsupported pixel format".into()));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, RgbImage};
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, format).unwrap();
+        encoded.into_inner()
+    }
+
+    /// A 2 by 1 PNG file with `chunks` after its header chunk
+    fn png_with_chunks(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let image = GrayImage::from_raw(2, 1, vec![10, 20]).unwrap();
+        let encoded = encode(image.into(), ImageFormat::Png);
+        let header_end = PNG_SIGNATURE.len() + 12 + 13;
+        let mut result = encoded[..header_end].to_vec();
+        for (kind, body) in chunks {
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(&kind[..]);
+            crc.update(body);
+            result.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            result.extend_from_slice(&kind[..]);
+            result.extend_from_slice(body);
+            result.extend_from_slice(&crc.finalize().to_be_bytes());
+        }
+        result.extend_from_slice(&encoded[header_end..]);
+        result
+    }
+
+    /// Exif data with only an Orientation tag
+    fn tiff_with_orientation(little_endian: bool, value: u16) -> Vec<u8> {
+        let field = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![value]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, little_endian).unwrap();
+        tiff.into_inner()
+    }
+
+    fn exif_orientation(tiff: &[u8]) -> Option<u32> {
+        let exif = exif::Reader::new().read_raw(tiff.to_vec()).ok()?;
+        exif.get_field(Tag::Orientation, In::PRIMARY)?
+            .value
+            .get_uint(0)
+    }
+
+    #[test]
+    fn patches_the_orientation_of_both_byte_orders() {
+        for little_endian in [true, false] {
+            let mut tiff = tiff_with_orientation(little_endian, 1);
+            assert!(patch_tiff_orientation(&mut tiff, Orientation::Deg90));
+            assert_eq!(exif_orientation(&tiff), Some(8));
+        }
+        let field = Field {
+            tag: Tag::ImageDescription,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Test".to_vec()]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        let mut tiff = tiff.into_inner();
+        assert!(!patch_tiff_orientation(&mut tiff, Orientation::Deg90));
+    }
+
+    #[test]
+    fn png_keeps_its_chunks() {
+        let mut phys = Vec::new();
+        phys.extend_from_slice(&1000u32.to_be_bytes());
+        phys.extend_from_slice(&2000u32.to_be_bytes());
+        phys.push(1);
+        let exif = tiff_with_orientation(false, 6);
+        let data = png_with_chunks(&[
+            (b"pHYs", &phys),
+            (b"eXIf", &exif),
+            (b"tEXt", b"Title\0Test"),
+            (b"tIME", &[7, 234, 1, 1, 0, 0, 0]),
+        ]);
+
+        let result = reencode_png(&data, Orientation::Deg90).unwrap();
+        let chunks = png_chunks(&result).unwrap();
+        let find = |kind: &[u8; 4]| {
+            chunks
+                .iter()
+                .find(|(k, _)| k == kind)
+                .map(|(_, body)| *body)
+        };
+        let phys = find(b"pHYs").unwrap();
+        assert_eq!(phys[..4], 2000u32.to_be_bytes());
+        assert_eq!(phys[4..8], 1000u32.to_be_bytes());
+        assert_eq!(exif_orientation(find(b"eXIf").unwrap()), Some(1));
+        assert_eq!(find(b"tEXt"), Some(&b"Title\0Test"[..]));
+        assert_eq!(find(b"tIME"), None);
+
+        // The chunk checksums are verified by the decoder
+        let image = image::load_from_memory(&result).unwrap().into_luma8();
+        assert_eq!(image.dimensions(), (1, 2));
+        // Turning counter-clockwise moves the right pixel to the top
+        assert_eq!(image.as_raw(), &[20, 10]);
+    }
+
+    #[test]
+    fn png_refuses_unsafe_chunks() {
+        let data = png_with_chunks(&[(b"bKGD", &[0, 0])]);
+        assert!(reencode_png(&data, Orientation::Deg90).is_err());
+    }
+
+    #[test]
+    fn bmp_resolution_is_metadata() {
+        let image = RgbImage::from_pixel(2, 2, image::Rgb([1, 2, 3]));
+        let mut data = encode(image.into(), ImageFormat::Bmp);
+        let header_size = u32::from_le_bytes(data[14..18].try_into().unwrap());
+        if header_size == 40 {
+            assert_eq!(lost_metadata(&data, ImageFormat::Bmp), None);
+        }
+        data[14..18].copy_from_slice(&40u32.to_le_bytes());
+        data[38..42].copy_from_slice(&2835u32.to_le_bytes());
+        assert_eq!(lost_metadata(&data, ImageFormat::Bmp), Some("resolution"));
+        data[14..18].copy_from_slice(&124u32.to_le_bytes());
+        assert_eq!(lost_metadata(&data, ImageFormat::Bmp), Some("color space"));
+    }
+
+    #[test]
+    fn replaces_the_file() {
+        let folder = TempDir::new("orientation");
+        let path = folder.join("image.png");
+        let image = GrayImage::from_fn(3, 1, |x, _| Luma([x as u8]));
+        fs::write(&path, encode(image.into(), ImageFormat::Png)).unwrap();
+
+        save_orientation(&path, Orientation::Deg270).unwrap();
+        let image = image::open(&path).unwrap().into_luma8();
+        assert_eq!(image.dimensions(), (1, 3));
+        assert_eq!(image.as_raw(), &[0, 1, 2]);
+        assert_eq!(fs::read_dir(&*folder).unwrap().count(), 1);
+    }
+}
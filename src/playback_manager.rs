@@ -11,13 +11,22 @@ use log::{debug, trace};
 use rand::{seq::SliceRandom, thread_rng};
 
 use crate::{
+    configuration::EndOfFolder,
     gelatin::{glium::Display, window::Window, NextUpdate},
-    image_cache::{self, AnimationFrameTexture, ImageCache},
+    image_cache::{
+        self, filter::ImageFilter, sort::SortOrder, AnimationFrameTexture,
+        FolderEnd, ImageCache,
+    },
 };
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// How often the watched folder is checked for created and removed files
+const FOLDER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a file has to stay untouched after it was written before it's
+/// loaded, so that partially written files are skipped
+const WRITE_SETTLE_TIME: Duration = Duration::from_millis(200);
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum LoadRequest {
     None,
     LoadNext,
@@ -67,6 +76,9 @@ trait Playback: Sized {
         index: usize,
     ) -> FrameLoadResult;
 
+    /// Returns true if playing forward would have to start over
+    fn is_at_end(image_cache: &mut ImageCache) -> bool;
+
     fn delay_nanos(player: &ImgSequencePlayer<Self>) -> u64;
 }
 
@@ -103,6 +115,10 @@ impl Playback for FolderPlayback {
         image_cache.load_at_index(display, index, None)
     }
 
+    fn is_at_end(image_cache: &mut ImageCache) -> bool {
+        image_cache.is_at_sequence_end()
+    }
+
     fn delay_nanos(_player: &ImgSequencePlayer<Self>) -> u64 {
         const FRAMERATE: u64 = 25;
         NANOS_PER_SEC / FRAMERATE
@@ -146,6 +162,11 @@ impl Playback for AnimPlayback {
         }
     }
 
+    fn is_at_end(_image_cache: &mut ImageCache) -> bool {
+        // Animations always loop
+        false
+    }
+
     fn delay_nanos(player: &ImgSequencePlayer<Self>) -> u64 {
         if let Some(ref frame) = player.image_texture {
             frame.delay_nano
@@ -163,6 +184,17 @@ pub struct PlaybackManager {
     // filename: Option<OsString>,
     folder_player: ImgSequencePlayer<FolderPlayback>,
     image_player: ImgSequencePlayer<AnimPlayback>,
+
+    /// The time of the last write to the file of the current image. The image
+    /// is reloaded once the writes have stopped.
+    last_write: Option<Instant>,
+
+    /// Show every image that is added to the folder
+    follow_newest: bool,
+    /// The last image that was added to the folder while following, and the
+    /// time of the last write to it. It's prefetched right away and shown
+    /// once the writes have stopped.
+    newest_image: Option<(PathBuf, Instant)>,
 }
 
 impl PlaybackManager {
@@ -185,12 +217,20 @@ impl PlaybackManager {
             Ok(value) => value.max(2).min(4),
             _ => 4,
         };
+        Self::with_cache(cache_capaxity, thread_count)
+    }
 
+    /// Creates a playback manager whose cache holds about `capacity` bytes
+    /// of images and loads them with `thread_count` threads
+    pub fn with_cache(capacity: isize, thread_count: u32) -> Self {
         PlaybackManager {
             //playback_state: PlaybackState::Paused,
-            image_cache: ImageCache::new(cache_capaxity, thread_count),
+            image_cache: ImageCache::new(capacity, thread_count),
             folder_player: ImgSequencePlayer::new(),
             image_player: ImgSequencePlayer::new(),
+            last_write: None,
+            follow_newest: false,
+            newest_image: None,
         }
     }
 
@@ -236,7 +276,121 @@ impl PlaybackManager {
         self.image_cache.current_dir_len()
     }
 
+    /// Returns the paths of the images in the folder, or `None` when the
+    /// folder hasn't finished filtering.
+    pub fn image_paths(&mut self) -> Option<Vec<PathBuf>> {
+        self.image_cache.image_paths()
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.image_cache.sort_order()
+    }
+
+    /// Orders the images of the folder anew. The current image stays shown.
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.image_cache.set_sort_order(sort_order);
+    }
+
+    /// Only shows the images of the folder that match `filter`
+    pub fn set_filter(&mut self, filter: ImageFilter) {
+        self.image_cache.set_filter(filter);
+    }
+
+    /// Sets what stepping past the last or before the first image does
+    pub fn set_end_of_folder(&mut self, end_of_folder: EndOfFolder) {
+        self.image_cache.set_end_of_folder(end_of_folder);
+    }
+
+    /// Sets whether the slideshows start over after the last image or stop
+    pub fn set_loop_slideshow(&mut self, loop_slideshow: bool) {
+        self.folder_player.loop_playback = loop_slideshow;
+    }
+
+    /// Returns the end of the folder once after a step past it was refused
+    pub fn take_reached_end(&mut self) -> Option<FolderEnd> {
+        self.folder_player.reached_end.take()
+    }
+
+    /// Opens the first image of the next folder in the parent folder, or of
+    /// the previous one if `forward` is false. Folders without images are
+    /// skipped.
+    pub fn open_sibling_folder(&mut self, forward: bool) {
+        match self.image_cache.change_to_sibling_folder(forward) {
+            Ok(true) => {
+                self.set_follow_newest(false);
+                self.load(LoadRequest::Jump(0));
+            }
+            Ok(false) => (),
+            Err(e) => eprintln!("Error while opening the folder: {e}"),
+        }
+    }
+
+    /// Shows the images of `entries`, which are files and folders, in place
+    /// of the images of a folder and opens the first one
+    pub fn open_list(&mut self, entries: Vec<PathBuf>) {
+        match self.image_cache.change_to_list(entries) {
+            Ok(()) => {
+                self.set_follow_newest(false);
+                self.load(LoadRequest::Jump(0));
+            }
+            Err(e) => eprintln!("Error while opening the list: {e}"),
+        }
+    }
+
+    /// The folder whose subfolders are listed too, or `None` when only the
+    /// images in the folder of the current image are listed.
+    pub fn recursive_root(&self) -> Option<&Path> {
+        self.image_cache.recursive_root()
+    }
+
+    /// Lists the images in `max_depth` levels of subfolders too, or only the
+    /// ones in the folder of the current image when it's `None`.
+    pub fn set_max_depth(
+        &mut self,
+        max_depth: Option<u32>,
+    ) -> image_cache::Result<()> {
+        self.image_cache.set_max_depth(max_depth)?;
+        // The index of the current image has changed
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.load(LoadRequest::FilePath(path));
+        }
+        Ok(())
+    }
+
+    /// Changes whenever the images of the folder or the cached textures change
+    pub fn image_cache_version(&self) -> (u64, u64) {
+        self.image_cache.version()
+    }
+
+    /// Returns the path of the image at `index` in the folder and its texture
+    /// if it's already in the cache.
+    pub fn image_at_index(
+        &mut self,
+        index: usize,
+    ) -> Option<(PathBuf, Option<AnimationFrameTexture>)> {
+        self.image_cache.image_at_index(index)
+    }
+
+    /// Returns the path of the image `offset` places after the current one
+    /// in the folder, or `None` when the folder hasn't finished filtering.
+    pub fn file_path_at_offset(&mut self, offset: isize) -> Option<PathBuf> {
+        self.image_cache.file_path_at_offset(offset)
+    }
+
     pub fn update_directory(&mut self) -> image_cache::Result<()> {
+        self.update_directory_with(ImageCache::update_directory)
+    }
+
+    /// Checks the images for modifications, and lists the folder again if it
+    /// isn't watched for changes anyway
+    pub fn refresh_directory(&mut self) -> image_cache::Result<()> {
+        self.update_directory_with(ImageCache::refresh_directory)
+    }
+
+    fn update_directory_with(
+        &mut self,
+        update: fn(&mut ImageCache) -> image_cache::Result<()>,
+    ) -> image_cache::Result<()> {
         debug!("In `update_directory`");
         if let LoadRequest::None = self.folder_player.load_request {
             let curr_path = self.image_cache.current_file_path();
@@ -245,7 +399,7 @@ impl PlaybackManager {
                 curr_path
             );
             if curr_path.is_some() {
-                self.image_cache.update_directory()?;
+                update(&mut self.image_cache)?;
 
                 // The there's no file to open, just request to open the empty path.
                 // This will hide the previously loaded image.
@@ -254,17 +408,124 @@ impl PlaybackManager {
                     .image_cache
                     .current_file_path()
                     .unwrap_or_else(PathBuf::new);
-                self.request_load(LoadRequest::FilePath(path));
+                self.load(LoadRequest::FilePath(path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the files that were created, renamed or removed in the folder.
+    /// Shows the next image if the current one was removed.
+    fn apply_folder_changes(&mut self) -> image_cache::Result<()> {
+        let prev_path = self.image_cache.current_file_path();
+        let update = self.image_cache.apply_folder_changes()?;
+        let now = Instant::now();
+        if update.current_written {
+            self.last_write = Some(now);
+        }
+        if self.follow_newest {
+            if let Some(path) = update.created.last() {
+                // Files that are written in one go, or moved into the
+                // folder, are often complete already
+                self.image_cache.prefetch_file(path);
+                self.newest_image = Some((path.clone(), now));
+            } else if let Some((path, last_write)) = &mut self.newest_image {
+                if update.written.contains(path) {
+                    *last_write = now;
+                }
             }
         }
+        if !update.listing_changed {
+            return Ok(());
+        }
+        let path = self.image_cache.current_file_path();
+        let no_request =
+            matches!(self.folder_player.load_request, LoadRequest::None);
+        if path != prev_path && no_request {
+            self.load(LoadRequest::FilePath(path.unwrap_or_default()));
+        }
         Ok(())
     }
 
+    /// Loads the image of the request. Requests that go to another image
+    /// stop following the newest image.
     pub fn request_load(&mut self, request: LoadRequest) {
+        let stays = match &request {
+            LoadRequest::None | LoadRequest::Jump(0) => true,
+            LoadRequest::FilePath(path) => {
+                Some(path) == self.shown_file_path().as_ref()
+            }
+            _ => false,
+        };
+        if !stays {
+            self.set_follow_newest(false);
+        }
+        self.load(request);
+    }
+
+    fn load(&mut self, request: LoadRequest) {
         self.folder_player.request_load(request);
         self.image_player.request_load(LoadRequest::Jump(0));
     }
 
+    pub fn follow_newest(&self) -> bool {
+        self.follow_newest
+    }
+
+    /// Turns on or off switching to every image that is added to the folder
+    pub fn set_follow_newest(&mut self, follow: bool) {
+        self.follow_newest = follow;
+        self.newest_image = None;
+    }
+
+    /// Switches to the newest image once its file hasn't been written to for
+    /// a moment. Returns when to check again.
+    fn show_newest_image(&mut self) -> NextUpdate {
+        let Some((_, last_write)) = &self.newest_image else {
+            return NextUpdate::Latest;
+        };
+        let show_time = *last_write + WRITE_SETTLE_TIME;
+        if Instant::now() < show_time {
+            return NextUpdate::WaitUntil(show_time);
+        }
+        if let Some((path, _)) = self.newest_image.take() {
+            // The current image stays shown until the new one is loaded
+            self.load(LoadRequest::FilePath(path));
+        }
+        NextUpdate::Latest
+    }
+
+    /// Loads the shown image again once its file hasn't been written to for
+    /// a moment. Returns when to check again.
+    fn reload_written_image(&mut self) -> NextUpdate {
+        let Some(last_write) = self.last_write else {
+            return NextUpdate::Latest;
+        };
+        let reload_time = last_write + WRITE_SETTLE_TIME;
+        if Instant::now() < reload_time {
+            return NextUpdate::WaitUntil(reload_time);
+        }
+        self.last_write = None;
+        let path = self.image_cache.current_file_path();
+        let no_request =
+            matches!(self.folder_player.load_request, LoadRequest::None);
+        // Another image that is being loaded replaces this one anyway
+        if let (Some(path), true) = (path, no_request) {
+            if Some(&path) == self.shown_file_path().as_ref() {
+                self.folder_player
+                    .request_reload(LoadRequest::FilePath(path));
+                self.image_player.request_reload(LoadRequest::Jump(0));
+            }
+        }
+        NextUpdate::Latest
+    }
+
+    /// Returns true once after the shown image was reloaded because its file
+    /// was written to
+    pub fn take_reloaded(&mut self) -> bool {
+        mem::take(&mut self.folder_player.reloaded)
+    }
+
     pub fn image_texture(&self) -> Option<AnimationFrameTexture> {
         self.image_player.image_texture()
     }
@@ -275,6 +536,12 @@ impl PlaybackManager {
     }
 
     pub fn update_image(&mut self, window: &Window) -> NextUpdate {
+        if let Err(e) = self.apply_folder_changes() {
+            eprintln!("Error while updating the folder: {e}");
+        }
+        let reload_update = self
+            .reload_written_image()
+            .aggregate(self.show_newest_image());
         let display = window.display_mut();
         let prev_file = self.folder_player.image_texture();
         let next_update = self
@@ -297,7 +564,15 @@ impl PlaybackManager {
             .image_player
             .update_image(&display, &mut self.image_cache);
         trace!("Image player next update: {:?}", img_player_next_update);
-        next_update.aggregate(img_player_next_update)
+        let next_update = next_update
+            .aggregate(img_player_next_update)
+            .aggregate(reload_update);
+        if self.image_cache.is_folder_watched() {
+            let poll_time = Instant::now() + FOLDER_POLL_INTERVAL;
+            next_update.aggregate(NextUpdate::WaitUntil(poll_time))
+        } else {
+            next_update
+        }
     }
 }
 
@@ -315,6 +590,17 @@ struct ImgSequencePlayer<P: Playback> {
     image_texture: Option<AnimationFrameTexture>,
     file_path: Option<PathBuf>,
 
+    /// Set while the shown image is reloaded. The image stays shown if it
+    /// can't be loaded, since the file may be in the middle of a write.
+    keep_on_error: bool,
+    /// Set when a reload has finished
+    reloaded: bool,
+
+    /// Start over after the last image instead of pausing
+    loop_playback: bool,
+    /// Set when a step past an end of the folder was refused
+    reached_end: Option<FolderEnd>,
+
     _playback: PhantomData<P>,
 }
 
@@ -330,6 +616,10 @@ impl<P: Playback> ImgSequencePlayer<P> {
             //should_sleep: true,
             image_texture: None,
             file_path: None,
+            keep_on_error: false,
+            reloaded: false,
+            loop_playback: true,
+            reached_end: None,
 
             _playback: PhantomData,
         }
@@ -371,6 +661,14 @@ impl<P: Playback> ImgSequencePlayer<P> {
 
     pub fn request_load(&mut self, request: LoadRequest) {
         self.load_request = request;
+        self.keep_on_error = false;
+    }
+
+    /// Like `request_load`, but keeps the shown image if the new one can't be
+    /// loaded
+    pub fn request_reload(&mut self, request: LoadRequest) {
+        self.load_request = request;
+        self.keep_on_error = true;
     }
 
     pub fn image_texture(&self) -> Option<AnimationFrameTexture> {
@@ -449,6 +747,12 @@ impl<P: Playback> ImgSequencePlayer<P> {
             };
             if frame_step > 0 {
                 load_request = match self.playback_state {
+                    PlaybackState::Forward | PlaybackState::Present
+                        if !self.loop_playback && P::is_at_end(image_cache) =>
+                    {
+                        self.playback_state = PlaybackState::Paused;
+                        LoadRequest::None
+                    }
                     PlaybackState::Forward | PlaybackState::Present => {
                         // if we can't load the frames quickly enough,
                         // we won't jump over frames, but instead play the animation slower.
@@ -458,6 +762,11 @@ impl<P: Playback> ImgSequencePlayer<P> {
                         let mut target = None;
                         for _ in 0..frame_step {
                             target = self.present_remaining.pop();
+                            if target.is_none() && !self.loop_playback {
+                                // Every image has been shown
+                                self.playback_state = PlaybackState::Paused;
+                                break;
+                            }
                             if target.is_none() {
                                 // Restart
                                 // WARNING we silently assume that the folder is fully
@@ -533,6 +842,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
                 Ok((frame, file_path)) => {
                     self.image_texture = Some(frame);
                     self.file_path = Some(file_path);
+                    self.reloaded |= mem::take(&mut self.keep_on_error);
                 }
                 Err(image_cache::errors::Error::WaitingOnLoader) => {
                     // Set the load request to jump in place so that
@@ -540,6 +850,15 @@ impl<P: Playback> ImgSequencePlayer<P> {
                     self.load_request = LoadRequest::Jump(0);
                     next_update = NextUpdate::WaitUntil(few_millisecs_from_now);
                 }
+                Err(image_cache::errors::Error::ReachedEnd(end)) => {
+                    // The image stays, and so does a slideshow that can't go
+                    // on
+                    self.reached_end = Some(end);
+                    self.playback_state = PlaybackState::Paused;
+                }
+                Err(err) if mem::take(&mut self.keep_on_error) => {
+                    eprintln!("Could not reload the image: {err}");
+                }
                 Err(err) => {
                     self.image_texture = None;
                     self.file_path = None;
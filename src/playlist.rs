@@ -0,0 +1,215 @@
+//! Reads the lists of files that are opened from the command line: playlists
+//! with a path on each line, like M3U files, and lists of paths that are
+//! separated by null characters, like the output of `find -print0`.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{cmd_line::Args, image_cache::image_loader::STDIN_PATH};
+
+/// Returns the files and folders of the command line that are shown one
+/// after the other, with the playlists replaced by their files. Returns
+/// `None` if at most a single image or folder is given, since it's shown with
+/// the other images of its folder.
+pub fn command_line_entries(args: &Args) -> Option<Vec<PathBuf>> {
+    // The image from the standard input has no folder
+    let single_path = args.paths.len() <= 1
+        && !args
+            .paths
+            .iter()
+            .any(|path| is_playlist(path) || path == Path::new(STDIN_PATH))
+        && args.playlists.is_empty()
+        && args.files0_from.is_none();
+    if single_path {
+        return None;
+    }
+    let mut entries = Vec::new();
+    for path in &args.paths {
+        if is_playlist(path) {
+            entries.extend(playlist_entries(path));
+        } else {
+            entries.push(path.clone());
+        }
+    }
+    for path in &args.playlists {
+        entries.extend(playlist_entries(path));
+    }
+    if let Some(source) = &args.files0_from {
+        match read_files0(source) {
+            Ok(paths) => entries.extend(paths),
+            Err(e) => eprintln!("Could not read the list {source:?}: {e}"),
+        }
+    }
+    Some(entries)
+}
+
+/// The paths of the playlist at `path`, or none if it can't be read
+fn playlist_entries(path: &Path) -> Vec<PathBuf> {
+    read_playlist(path).unwrap_or_else(|e| {
+        eprintln!("Could not read the playlist {path:?}: {e}");
+        Vec::new()
+    })
+}
+
+/// Returns true if the file at `path` is opened as a playlist instead of an
+/// image
+pub fn is_playlist(path: &Path) -> bool {
+    let extension = path.extension().unwrap_or_default();
+    let extension = extension.to_string_lossy().to_lowercase();
+    extension == "m3u" || extension == "m3u8"
+}
+
+/// Reads the paths of the playlist at `path`, which has a path or a `file://`
+/// URI on each line. Empty lines and the lines starting with `#`, like the
+/// extended M3U tags, are skipped. Relative paths are relative to the folder
+/// of the playlist.
+pub fn read_playlist(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read(path)?;
+    let contents = contents.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&contents);
+    let folder = path.parent().unwrap_or(Path::new(""));
+    let mut paths = Vec::new();
+    for line in contents.split(|&byte| byte == b'\n') {
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let entry = if let Some(uri_path) = line.strip_prefix(b"file://") {
+            path_from_uri(uri_path)
+        } else if line.windows(3).any(|w| w == b"://") {
+            eprintln!(
+                "Skipping {}, only local files can be opened",
+                String::from_utf8_lossy(line)
+            );
+            continue;
+        } else {
+            path_from_bytes(line)
+        };
+        paths.push(folder.join(entry));
+    }
+    Ok(paths)
+}
+
+/// Reads the paths that are separated by null characters from the file at
+/// `source`, or from the standard input if `source` is `-`
+pub fn read_files0(source: &str) -> io::Result<Vec<PathBuf>> {
+    let contents = if source == STDIN_PATH {
+        let mut contents = Vec::new();
+        io::stdin().lock().read_to_end(&mut contents)?;
+        contents
+    } else {
+        fs::read(source)?
+    };
+    let paths = contents
+        .split(|&byte| byte == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect();
+    Ok(paths)
+}
+
+/// The path of a `file://` URI without the scheme, where the host is either
+/// empty or `localhost`
+fn path_from_uri(uri_path: &[u8]) -> PathBuf {
+    let uri_path = uri_path.strip_prefix(b"localhost").unwrap_or(uri_path);
+    let mut bytes = Vec::with_capacity(uri_path.len());
+    let mut rest = uri_path;
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|_| byte == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    // URIs of Windows paths look like `file:///C:/folder`
+    #[cfg(not(unix))]
+    if bytes.get(2) == Some(&b':') {
+        bytes.remove(0);
+    }
+    path_from_bytes(&bytes)
+}
+
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn uris() {
+        assert_eq!(path_from_uri(b"/a%20b"), Path::new("/a b"));
+        assert_eq!(path_from_uri(b"localhost/a/b.png"), Path::new("/a/b.png"));
+        assert_eq!(path_from_uri(b"/f%C3%B6%c3%b6"), Path::new("/föö"));
+        // Invalid escapes are kept as they are
+        assert_eq!(path_from_uri(b"/100%/a%2"), Path::new("/100%/a%2"));
+    }
+
+    #[test]
+    fn playlists() {
+        let contents = b"\xEF\xBB\xBF#EXTM3U\r\n\
+            #EXTINF:-1,First\r\n\
+            a.png\r\n\
+            \r\n\
+            sub/b c.png  \r\n\
+            /abs/d.png\r\n\
+            file:///abs/e%20f.png\r\n\
+            https://example.com/g.png\r\n\
+            h.png";
+        let folder = TempDir::new("playlist");
+        let path = folder.join("list.m3u8");
+        fs::write(&path, contents).unwrap();
+        let entries = read_playlist(&path).unwrap();
+        assert_eq!(
+            entries,
+            [
+                folder.join("a.png"),
+                folder.join("sub/b c.png"),
+                PathBuf::from("/abs/d.png"),
+                PathBuf::from("/abs/e f.png"),
+                folder.join("h.png"),
+            ]
+        );
+        assert!(is_playlist(Path::new("list.M3U")));
+        assert!(!is_playlist(Path::new("list.png")));
+    }
+
+    #[test]
+    fn null_separated_lists() {
+        let folder = TempDir::new("files0");
+        let path = folder.join("files0");
+        fs::write(&path, b"a.png\0/b c.png\0\0sub/d.png\0").unwrap();
+        let entries = read_files0(path.to_str().unwrap()).unwrap();
+        // Unlike playlists, the paths are relative to the working directory
+        assert_eq!(
+            entries,
+            [
+                PathBuf::from("a.png"),
+                PathBuf::from("/b c.png"),
+                PathBuf::from("sub/d.png"),
+            ]
+        );
+    }
+}
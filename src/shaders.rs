@@ -3,3 +3,13 @@ pub static VERTEX_140: &str = include_str!("shaders/vertex_140.glsl");
 
 pub static FRAGMENT_110: &str = include_str!("shaders/fragment_110.glsl");
 pub static FRAGMENT_140: &str = include_str!("shaders/fragment_140.glsl");
+
+pub static OVERLAY_VERTEX_110: &str =
+    include_str!("shaders/overlay_vertex_110.glsl");
+pub static OVERLAY_VERTEX_140: &str =
+    include_str!("shaders/overlay_vertex_140.glsl");
+
+pub static OVERLAY_FRAGMENT_110: &str =
+    include_str!("shaders/overlay_fragment_110.glsl");
+pub static OVERLAY_FRAGMENT_140: &str =
+    include_str!("shaders/overlay_fragment_140.glsl");
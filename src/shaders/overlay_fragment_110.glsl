@@ -0,0 +1,59 @@
+#version 110
+uniform sampler2D tex;
+uniform sampler2D b_tex;
+// -1: only the part outside the second image, 0: onion skin,
+// 1: difference, 2: changed pixels
+uniform int mode;
+uniform float opacity;
+uniform float threshold;
+uniform float bright_shade;
+uniform float lod_level; // textureLod is not available in 1.10
+varying vec2 v_tex_coords;
+varying vec2 v_b_tex_coords;
+varying vec2 v_b_image_coords;
+
+// Approximately converts to sRGB so that differences are perceptual
+vec4 to_srgb(vec4 color) {
+    return vec4(pow(color.rgb, vec3(1.0 / 2.2)) * color.a, color.a);
+}
+
+bool outside(vec2 coords) {
+    return any(lessThan(coords, vec2(0.0)))
+        || any(greaterThan(coords, vec2(1.0)));
+}
+
+void main() {
+    vec4 a = texture2D(tex, v_tex_coords);
+    const float grid_size = 12.0;
+    vec4 grid_color;
+    if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
+        ^^ (mod(gl_FragCoord.y, grid_size * 2.0) < grid_size)
+    ) {
+        grid_color = vec4(bright_shade);
+    } else {
+        grid_color = vec4(bright_shade * 0.55);
+    }
+    vec4 a_over_grid = mix(grid_color, a, a.a);
+    if (outside(v_b_image_coords)) {
+        // The second image doesn't cover this fragment
+        gl_FragColor = a_over_grid;
+        return;
+    }
+    if (mode < 0 || outside(v_b_tex_coords)) {
+        // Another draw call covers this fragment, either with the overlap
+        // of the images or with another cell of the second image
+        discard;
+    }
+    vec4 b = texture2D(b_tex, v_b_tex_coords);
+    vec4 diff = abs(to_srgb(a) - to_srgb(b));
+    if (mode == 0) {
+        gl_FragColor = mix(a_over_grid, mix(grid_color, b, b.a), opacity);
+    } else if (mode == 1) {
+        gl_FragColor = vec4(pow(diff.rgb, vec3(2.2)), 1.0);
+    } else if (max(max(diff.r, diff.g), max(diff.b, diff.a)) > threshold) {
+        gl_FragColor = vec4(1.0, 0.05, 0.05, 1.0);
+    } else {
+        float luma = dot(a_over_grid.rgb, vec3(0.2126, 0.7152, 0.0722));
+        gl_FragColor = vec4(vec3(luma * 0.4), 1.0);
+    }
+}
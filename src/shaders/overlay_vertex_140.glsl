@@ -0,0 +1,15 @@
+#version 140
+uniform mat4 matrix;
+uniform mat4 b_matrix;
+uniform mat4 b_image_matrix;
+in vec2 position;
+in vec2 tex_coords;
+out vec2 v_tex_coords;
+out vec2 v_b_tex_coords;
+out vec2 v_b_image_coords;
+void main() {
+    gl_Position = matrix * vec4(position, 0.0, 1.0);
+    v_tex_coords = tex_coords;
+    v_b_tex_coords = (b_matrix * vec4(tex_coords, 0.0, 1.0)).xy;
+    v_b_image_coords = (b_image_matrix * vec4(tex_coords, 0.0, 1.0)).xy;
+}
@@ -0,0 +1,39 @@
+//! A folder for the files of a test. It's removed when the test ends, also
+//! when an assertion fails.
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty folder in the temporary folder of the system. The
+    /// `name` has to be different for each test, since they run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("alloy-{name}-{}", std::process::id()));
+        // Left over from a run that was killed
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{configuration::data_dir, image_cache::image_loader::Orientation};
+
+/// The number of images for which the view is remembered
+const CAPACITY: usize = 512;
+const FILE_NAME: &str = "views.toml";
+
+/// Describes how an image was shown, independently of the panel size.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    /// Size of an image texel in physical display pixels
+    pub img_texel_size: f32,
+    /// The point of the image at the center of the panel relative to the
+    /// center of the image, in units of the (oriented) image size.
+    pub center: [f32; 2],
+    /// Rotation and flip applied by the user
+    #[serde(default)]
+    pub orientation: Orientation,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    view: ViewState,
+}
+
+/// A least recently used map from image paths to the view they were last shown with.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ViewMemory {
+    /// The most recently used entry is at the front.
+    entries: VecDeque<Entry>,
+}
+
+impl ViewMemory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Loads the views stored by a previous session or returns an empty
+    /// memory if there are none.
+    pub fn load() -> Self {
+        let file_path = views_file();
+        let views_str = match fs::read_to_string(&file_path) {
+            Ok(views_str) => views_str,
+            Err(_) => return Self::new(),
+        };
+        toml::from_str(&views_str).unwrap_or_else(|e| {
+            eprintln!("Could not parse the views from {file_path:?}: {e}");
+            Self::new()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let file_path = views_file();
+        let views_str = toml::to_string(self).map_err(|e| format!("{e}"))?;
+        fs::write(&file_path, views_str)
+            .map_err(|_| format!("Could not write the views to {file_path:?}"))
+    }
+
+    /// Returns the view of the image at `path` and marks it as the most
+    /// recently used one.
+    pub fn get(&mut self, path: &Path) -> Option<ViewState> {
+        let index = self.entries.iter().position(|e| e.path == path)?;
+        let entry = self.entries.remove(index)?;
+        let view = entry.view;
+        self.entries.push_front(entry);
+        Some(view)
+    }
+
+    pub fn insert(&mut self, path: PathBuf, view: ViewState) {
+        self.remove(&path);
+        self.entries.push_front(Entry { path, view });
+        self.entries.truncate(CAPACITY);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.retain(|e| e.path != path);
+    }
+}
+
+fn views_file() -> PathBuf {
+    data_dir().join(FILE_NAME)
+}
@@ -1,10 +1,13 @@
 use std::{f32, rc::Rc};
 
+use super::filmstrip::Filmstrip;
 use crate::{
-    configuration::{ScalingMode, Theme},
+    configuration::{FilmstripPosition, ScalingMode, Theme},
     gelatin::{
         button::Button,
-        line_layout_container::HorizontalLayoutContainer,
+        line_layout_container::{
+            HorizontalLayoutContainer, VerticalLayoutContainer,
+        },
         misc::{Alignment, Length},
         picture::Picture,
         slider::Slider,
@@ -25,6 +28,14 @@ static FIT_STRETCH_LIGHT: &[u8] =
 static FIT_BEST: &[u8] = include_bytes!("../../resource/fit-min.png");
 static FIT_BEST_LIGHT: &[u8] =
     include_bytes!("../../resource/fit-min-light.png");
+static FIT_WIDTH: &[u8] = include_bytes!("../../resource/fit-width.png");
+static FIT_WIDTH_LIGHT: &[u8] =
+    include_bytes!("../../resource/fit-width-light.png");
+static FIT_HEIGHT: &[u8] = include_bytes!("../../resource/fit-height.png");
+static FIT_HEIGHT_LIGHT: &[u8] =
+    include_bytes!("../../resource/fit-height-light.png");
+static LOCK: &[u8] = include_bytes!("../../resource/lock.png");
+static LOCK_LIGHT: &[u8] = include_bytes!("../../resource/lock-light.png");
 
 const NO_BG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
 const ACTIVE_BG_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.5];
@@ -32,13 +43,19 @@ const ACTIVE_BG_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.5];
 const SMALL_BUTTON_GAP: f32 = 4.0;
 const BIG_BUTTON_GAP: f32 = 32.0;
 const BUTTON_SIZE: f32 = 24.0;
+const ROW_HEIGHT: f32 = 32.0;
+const FILMSTRIP_HEIGHT: f32 = 56.0;
 
 pub struct BottomBar {
-    pub widget: Rc<HorizontalLayoutContainer>,
+    pub widget: Rc<VerticalLayoutContainer>,
     pub orig_scale_button: Rc<Button>,
     pub fit_stretch_button: Rc<Button>,
     pub fit_best_button: Rc<Button>,
+    pub fit_width_button: Rc<Button>,
+    pub fit_height_button: Rc<Button>,
+    pub lock_view_button: Rc<Button>,
     pub slider: Rc<Slider>,
+    pub filmstrip: Rc<Filmstrip>,
     pub theme_button: Rc<Button>,
     pub help_button: Rc<Button>,
 
@@ -52,10 +69,16 @@ pub struct BottomBar {
     fit_stretch_light: Rc<Picture>,
     fit_best: Rc<Picture>,
     fit_best_light: Rc<Picture>,
+    fit_width: Rc<Picture>,
+    fit_width_light: Rc<Picture>,
+    fit_height: Rc<Picture>,
+    fit_height_light: Rc<Picture>,
+    lock: Rc<Picture>,
+    lock_light: Rc<Picture>,
 }
 
 impl BottomBar {
-    pub fn new() -> Self {
+    pub fn new(filmstrip_position: FilmstripPosition) -> Self {
         let question = Rc::new(Picture::from_encoded_bytes(QUESTION_BUTTON));
         let question_light =
             Rc::new(Picture::from_encoded_bytes(QUESTION_BUTTON_LIGHT));
@@ -69,42 +92,86 @@ impl BottomBar {
         let fit_best = Rc::new(Picture::from_encoded_bytes(FIT_BEST));
         let fit_best_light =
             Rc::new(Picture::from_encoded_bytes(FIT_BEST_LIGHT));
+        let fit_width = Rc::new(Picture::from_encoded_bytes(FIT_WIDTH));
+        let fit_width_light =
+            Rc::new(Picture::from_encoded_bytes(FIT_WIDTH_LIGHT));
+        let fit_height = Rc::new(Picture::from_encoded_bytes(FIT_HEIGHT));
+        let fit_height_light =
+            Rc::new(Picture::from_encoded_bytes(FIT_HEIGHT_LIGHT));
+        let lock = Rc::new(Picture::from_encoded_bytes(LOCK));
+        let lock_light = Rc::new(Picture::from_encoded_bytes(LOCK_LIGHT));
 
-        let widget = Rc::new(HorizontalLayoutContainer::new());
-        widget.set_margin_left(0.0);
-        widget.set_margin_right(0.0);
-        widget.set_height(Length::Fixed(32.0));
+        let height = match filmstrip_position {
+            FilmstripPosition::AboveSlider => ROW_HEIGHT + FILMSTRIP_HEIGHT,
+            _ => ROW_HEIGHT,
+        };
+        let widget = Rc::new(VerticalLayoutContainer::new());
+        widget.set_margin_all(0.0);
+        widget.set_height(Length::Fixed(height));
         widget.set_width(Length::Stretch {
             min: 0.0,
             max: f32::INFINITY,
         });
+        let row = Rc::new(HorizontalLayoutContainer::new());
+        row.set_margin_left(0.0);
+        row.set_margin_right(0.0);
+        row.set_height(Length::Fixed(ROW_HEIGHT));
+        row.set_width(Length::Stretch {
+            min: 0.0,
+            max: f32::INFINITY,
+        });
 
         let orig_scale_button = make_icon_button(Alignment::Start);
         let fit_best_button = make_icon_button(Alignment::Start);
         let fit_stretch_button = make_icon_button(Alignment::Start);
+        let fit_width_button = make_icon_button(Alignment::Start);
+        let fit_height_button = make_icon_button(Alignment::Start);
+        let lock_view_button = make_icon_button(Alignment::Start);
         let slider = make_slider();
+        let filmstrip = make_filmstrip(filmstrip_position);
         let theme_button = make_icon_button(Alignment::End);
         let help_button = make_icon_button(Alignment::End);
 
         orig_scale_button.set_margin_left(SMALL_BUTTON_GAP);
-        fit_stretch_button.set_margin_right(SMALL_BUTTON_GAP);
+        lock_view_button.set_margin_left(SMALL_BUTTON_GAP);
+        lock_view_button.set_margin_right(SMALL_BUTTON_GAP);
         theme_button.set_margin_left(SMALL_BUTTON_GAP);
         help_button.set_margin_left(SMALL_BUTTON_GAP);
         help_button.set_margin_right(SMALL_BUTTON_GAP);
 
-        widget.add_child(orig_scale_button.clone());
-        widget.add_child(fit_best_button.clone());
-        widget.add_child(fit_stretch_button.clone());
-        widget.add_child(slider.clone());
-        widget.add_child(theme_button.clone());
-        widget.add_child(help_button.clone());
+        row.add_child(orig_scale_button.clone());
+        row.add_child(fit_best_button.clone());
+        row.add_child(fit_stretch_button.clone());
+        row.add_child(fit_width_button.clone());
+        row.add_child(fit_height_button.clone());
+        row.add_child(lock_view_button.clone());
+        match filmstrip_position {
+            FilmstripPosition::Hidden => {
+                filmstrip.set_visible(false);
+                row.add_child(slider.clone());
+            }
+            FilmstripPosition::AboveSlider => {
+                widget.add_child(filmstrip.clone());
+                row.add_child(slider.clone());
+            }
+            FilmstripPosition::InsteadOfSlider => {
+                row.add_child(filmstrip.clone());
+            }
+        }
+        row.add_child(theme_button.clone());
+        row.add_child(help_button.clone());
+        widget.add_child(row);
 
         Self {
             widget,
             orig_scale_button,
             fit_stretch_button,
             fit_best_button,
+            fit_width_button,
+            fit_height_button,
+            lock_view_button,
             slider,
+            filmstrip,
             theme_button,
             help_button,
 
@@ -118,6 +185,12 @@ impl BottomBar {
             fit_stretch_light,
             fit_best,
             fit_best_light,
+            fit_width,
+            fit_width_light,
+            fit_height,
+            fit_height_light,
+            lock,
+            lock_light,
         }
     }
 
@@ -128,9 +201,14 @@ impl BottomBar {
                 self.fit_best_button.set_icon(Some(self.fit_best.clone()));
                 self.fit_stretch_button
                     .set_icon(Some(self.fit_stretch.clone()));
+                self.fit_width_button.set_icon(Some(self.fit_width.clone()));
+                self.fit_height_button
+                    .set_icon(Some(self.fit_height.clone()));
+                self.lock_view_button.set_icon(Some(self.lock.clone()));
                 self.theme_button.set_icon(Some(self.moon_img.clone()));
                 self.widget.set_bg_color([1.0, 1.0, 1.0, 1.0]);
                 self.slider.set_shadow_color([0.0, 0.0, 0.0]);
+                self.filmstrip.set_bright_shade(0.96);
                 self.help_button.set_icon(Some(self.question.clone()));
             }
             Theme::Dark => {
@@ -140,9 +218,16 @@ impl BottomBar {
                     .set_icon(Some(self.fit_best_light.clone()));
                 self.fit_stretch_button
                     .set_icon(Some(self.fit_stretch_light.clone()));
+                self.fit_width_button
+                    .set_icon(Some(self.fit_width_light.clone()));
+                self.fit_height_button
+                    .set_icon(Some(self.fit_height_light.clone()));
+                self.lock_view_button
+                    .set_icon(Some(self.lock_light.clone()));
                 self.theme_button.set_icon(Some(self.light_img.clone()));
                 self.widget.set_bg_color([0.08, 0.08, 0.08, 1.0]);
                 self.slider.set_shadow_color([0.0, 0.0, 0.0]);
+                self.filmstrip.set_bright_shade(0.11);
                 self.help_button.set_icon(Some(self.question_light.clone()));
             }
         }
@@ -160,32 +245,35 @@ impl BottomBar {
         })
     }
 
+    pub fn set_view_locked(&self, locked: bool) {
+        self.lock_view_button.set_bg_color(if locked {
+            ACTIVE_BG_COLOR
+        } else {
+            NO_BG_COLOR
+        })
+    }
+
     pub fn update_scaling_buttons(
         &self,
         scaling: ScalingMode,
         img_texel_size: f32,
     ) {
-        match scaling {
-            #[allow(clippy::float_cmp)]
-            ScalingMode::Fixed => {
-                if img_texel_size == 1.0 {
-                    self.orig_scale_button.set_bg_color(ACTIVE_BG_COLOR);
-                } else {
-                    self.orig_scale_button.set_bg_color(NO_BG_COLOR);
-                }
-                self.fit_best_button.set_bg_color(NO_BG_COLOR);
-                self.fit_stretch_button.set_bg_color(NO_BG_COLOR);
-            }
-            ScalingMode::FitMin => {
-                self.orig_scale_button.set_bg_color(NO_BG_COLOR);
-                self.fit_best_button.set_bg_color(ACTIVE_BG_COLOR);
-                self.fit_stretch_button.set_bg_color(NO_BG_COLOR);
-            }
-            ScalingMode::FitStretch => {
-                self.orig_scale_button.set_bg_color(NO_BG_COLOR);
-                self.fit_best_button.set_bg_color(NO_BG_COLOR);
-                self.fit_stretch_button.set_bg_color(ACTIVE_BG_COLOR);
-            }
+        #[allow(clippy::float_cmp)]
+        let orig_active =
+            scaling == ScalingMode::Fixed && img_texel_size == 1.0;
+        let buttons = [
+            (&self.orig_scale_button, orig_active),
+            (&self.fit_best_button, scaling == ScalingMode::FitMin),
+            (&self.fit_stretch_button, scaling == ScalingMode::FitStretch),
+            (&self.fit_width_button, scaling == ScalingMode::FitWidth),
+            (&self.fit_height_button, scaling == ScalingMode::FitHeight),
+        ];
+        for (button, active) in buttons {
+            button.set_bg_color(if active {
+                ACTIVE_BG_COLOR
+            } else {
+                NO_BG_COLOR
+            });
         }
     }
 }
@@ -213,3 +301,22 @@ fn make_slider() -> Rc<Slider> {
     slider.set_steps(6, 1);
     slider
 }
+
+fn make_filmstrip(position: FilmstripPosition) -> Rc<Filmstrip> {
+    let filmstrip = Rc::new(Filmstrip::new());
+    filmstrip.set_width(Length::Stretch {
+        min: 0.0,
+        max: f32::INFINITY,
+    });
+    if let FilmstripPosition::InsteadOfSlider = position {
+        // Takes the place of the slider
+        filmstrip.set_margin_top(SMALL_BUTTON_GAP);
+        filmstrip.set_margin_left(BIG_BUTTON_GAP);
+        filmstrip.set_margin_right(BIG_BUTTON_GAP);
+        filmstrip.set_height(Length::Fixed(BUTTON_SIZE));
+        filmstrip.set_horizontal_align(Alignment::Center);
+    } else {
+        filmstrip.set_height(Length::Fixed(FILMSTRIP_HEIGHT));
+    }
+    filmstrip
+}
@@ -0,0 +1,194 @@
+use std::path::PathBuf;
+
+use glium::Frame;
+
+use crate::{
+    configuration::CompareAnchor,
+    gelatin::{
+        misc::{LogicalRect, LogicalVector},
+        DrawContext,
+    },
+    playback_manager::{LoadRequest, PlaybackManager},
+};
+
+/// The distance in logical pixels from the split line within which it can be
+/// grabbed with the mouse.
+const SPLIT_GRAB_DISTANCE: f32 = 6.0;
+/// The right image only needs its neighbors prefetched, so its cache is kept
+/// small next to the one of the left image.
+const CACHE_CAPACITY: isize = 256_000_000;
+const LOADER_THREADS: u32 = 2;
+
+/// How the two images of the compare mode are arranged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareLayout {
+    /// Each image is shown in its own half of the panel.
+    SideBySide,
+    /// The images are on top of each other, the left image is shown on the
+    /// left side of a movable split line and the right image on the other.
+    Split,
+    /// The right image is blended over the left one.
+    Onion,
+    /// The absolute difference of the images is shown.
+    Difference,
+    /// The pixels that differ by more than a threshold are highlighted.
+    Mask,
+}
+
+impl CompareLayout {
+    pub fn next(self) -> Self {
+        match self {
+            CompareLayout::SideBySide => CompareLayout::Split,
+            CompareLayout::Split => CompareLayout::Onion,
+            CompareLayout::Onion => CompareLayout::Difference,
+            CompareLayout::Difference => CompareLayout::Mask,
+            CompareLayout::Mask => CompareLayout::SideBySide,
+        }
+    }
+
+    /// The mode of the overlay shader, if the images are combined by it
+    pub fn overlay_mode(self) -> Option<i32> {
+        match self {
+            CompareLayout::SideBySide | CompareLayout::Split => None,
+            CompareLayout::Onion => Some(0),
+            CompareLayout::Difference => Some(1),
+            CompareLayout::Mask => Some(2),
+        }
+    }
+}
+
+/// The images that are moved when navigating in the folder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareTarget {
+    Left,
+    Right,
+    Both,
+}
+
+impl CompareTarget {
+    pub fn next(self) -> Self {
+        match self {
+            CompareTarget::Left => CompareTarget::Right,
+            CompareTarget::Right => CompareTarget::Both,
+            CompareTarget::Both => CompareTarget::Left,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareTarget::Left => "Left",
+            CompareTarget::Right => "Right",
+            CompareTarget::Both => "Both",
+        }
+    }
+
+    pub fn moves_left(self) -> bool {
+        self != CompareTarget::Right
+    }
+
+    pub fn moves_right(self) -> bool {
+        self != CompareTarget::Left
+    }
+}
+
+/// The state of the compare mode. The image of the picture widget is the
+/// left image, the right image is loaded by a separate playback manager with
+/// a smaller cache so that it can be navigated independently.
+pub struct Compare {
+    pub playback_manager: PlaybackManager,
+    pub layout: CompareLayout,
+    pub target: CompareTarget,
+    /// How images of different sizes are aligned
+    pub anchor: CompareAnchor,
+    /// The position of the split line relative to the width of the panel
+    split: f32,
+    pub dragging_split: bool,
+}
+
+impl Compare {
+    pub fn new(
+        right_path: PathBuf,
+        target: CompareTarget,
+        anchor: CompareAnchor,
+    ) -> Self {
+        let mut playback_manager =
+            PlaybackManager::with_cache(CACHE_CAPACITY, LOADER_THREADS);
+        playback_manager.request_load(LoadRequest::FilePath(right_path));
+        Compare {
+            playback_manager,
+            layout: CompareLayout::SideBySide,
+            target,
+            anchor,
+            split: 0.5,
+            dragging_split: false,
+        }
+    }
+
+    /// The areas of `bounds` that the left and the right image are placed
+    /// in. Both images are shown with the same position relative to their
+    /// area.
+    pub fn views(&self, bounds: LogicalRect) -> (LogicalRect, LogicalRect) {
+        match self.layout {
+            CompareLayout::SideBySide => self.clips(bounds),
+            _ => (bounds, bounds),
+        }
+    }
+
+    /// The areas of `bounds` that the left and the right image are visible in
+    pub fn clips(&self, bounds: LogicalRect) -> (LogicalRect, LogicalRect) {
+        if self.layout.overlay_mode().is_some() {
+            return (bounds, bounds);
+        }
+        let x = self.divider_x(bounds);
+        let left = LogicalRect {
+            pos: bounds.pos,
+            size: LogicalVector::new(x - bounds.left(), bounds.size.vec.y),
+        };
+        let right = LogicalRect {
+            pos: LogicalVector::new(x, bounds.top()),
+            size: LogicalVector::new(bounds.right() - x, bounds.size.vec.y),
+        };
+        (left, right)
+    }
+
+    /// The horizontal position of the line between the images
+    pub fn divider_x(&self, bounds: LogicalRect) -> f32 {
+        let split = match self.layout {
+            CompareLayout::SideBySide => 0.5,
+            _ => self.split,
+        };
+        bounds.left() + (bounds.size.vec.x * split).round()
+    }
+
+    /// Returns true if the split line can be dragged from `point`.
+    pub fn can_grab_split(
+        &self,
+        bounds: LogicalRect,
+        point: LogicalVector,
+    ) -> bool {
+        self.layout == CompareLayout::Split
+            && bounds.contains(point)
+            && (point.vec.x - self.divider_x(bounds)).abs()
+                <= SPLIT_GRAB_DISTANCE
+    }
+
+    pub fn drag_split(&mut self, bounds: LogicalRect, x: f32) {
+        let width = bounds.size.vec.x.max(1.0);
+        self.split = ((x - bounds.left()) / width).clamp(0.0, 1.0);
+    }
+}
+
+/// Draws the line between the two images.
+pub fn draw_divider(
+    target: &mut Frame,
+    context: &DrawContext,
+    bounds: LogicalRect,
+    x: f32,
+) {
+    let width = 1.0 / context.dpi_scale_factor;
+    let line = LogicalRect {
+        pos: LogicalVector::new(x - width * 0.5, bounds.top()),
+        size: LogicalVector::new(width, bounds.size.vec.y),
+    };
+    context.clear_color(target, [1.0, 1.0, 1.0, 0.8], Some(line));
+}
@@ -0,0 +1,122 @@
+use cgmath::Vector2;
+use glium::Frame;
+
+use crate::{
+    gelatin::{
+        misc::{LogicalRect, LogicalVector},
+        DrawContext,
+    },
+    image_cache::image_loader::CropRect,
+};
+
+/// The aspect ratios that the selection can be constrained to. `None` allows
+/// any shape. The ratios are swapped when the selection is taller than wide.
+pub const ASPECT_RATIOS: &[Option<(u32, u32)>] = &[
+    None,
+    Some((1, 1)),
+    Some((4, 3)),
+    Some((3, 2)),
+    Some((16, 9)),
+];
+
+/// A rectangle selected by dragging the mouse over the image. The corners
+/// are in texel coordinates of the image as it's shown, that is, with its
+/// orientation applied.
+#[derive(Clone, Copy, Debug)]
+pub struct CropSelection {
+    start: Vector2<f32>,
+    end: Vector2<f32>,
+    /// The size of the oriented image in texels
+    bounds: Vector2<f32>,
+}
+
+impl CropSelection {
+    pub fn new(point: Vector2<f32>, img_w: u32, img_h: u32) -> Self {
+        let bounds = Vector2::new(img_w as f32, img_h as f32);
+        let start = clamp_to(point, bounds);
+        CropSelection {
+            start,
+            end: start,
+            bounds,
+        }
+    }
+
+    pub fn drag_to(&mut self, point: Vector2<f32>) {
+        self.end = clamp_to(point, self.bounds);
+    }
+
+    /// Returns the selected pixels or `None` if the selection is empty.
+    pub fn rect(&self, aspect: Option<(u32, u32)>) -> Option<CropRect> {
+        let mut delta = self.end - self.start;
+        if let Some((w, h)) = aspect {
+            let (w, h) = if delta.x.abs() >= delta.y.abs() {
+                (w as f32, h as f32)
+            } else {
+                (h as f32, w as f32)
+            };
+            // Shrink towards the start so that the selection stays within
+            // the dragged area and therefore within the image.
+            let sel_w = delta.x.abs().min(delta.y.abs() * w / h);
+            let sel_h = sel_w * h / w;
+            delta =
+                Vector2::new(sel_w.copysign(delta.x), sel_h.copysign(delta.y));
+        }
+        let end = self.start + delta;
+        let x0 = self.start.x.min(end.x).round() as u32;
+        let y0 = self.start.y.min(end.y).round() as u32;
+        let x1 = self.start.x.max(end.x).round() as u32;
+        let y1 = self.start.y.max(end.y).round() as u32;
+        if x1 > x0 && y1 > y0 {
+            Some(CropRect {
+                x: x0,
+                y: y0,
+                w: x1 - x0,
+                h: y1 - y0,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn clamp_to(point: Vector2<f32>, bounds: Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(point.x.clamp(0.0, bounds.x), point.y.clamp(0.0, bounds.y))
+}
+
+/// Darkens the area of `bounds` outside of `selected` and outlines the selection.
+pub fn draw(
+    target: &mut Frame,
+    context: &DrawContext,
+    bounds: LogicalRect,
+    selected: LogicalRect,
+) {
+    let shade = [0.0, 0.0, 0.0, 0.5];
+    let line = [1.0, 1.0, 1.0, 0.8];
+    let left = selected.left().max(bounds.left());
+    let right = selected.right().min(bounds.right());
+    let top = selected.top().max(bounds.top());
+    let bottom = selected.bottom().min(bounds.bottom());
+    let rect = |x0: f32, y0: f32, x1: f32, y1: f32| LogicalRect {
+        pos: LogicalVector::new(x0, y0),
+        size: LogicalVector::new((x1 - x0).max(0.0), (y1 - y0).max(0.0)),
+    };
+    let shaded = [
+        rect(bounds.left(), bounds.top(), bounds.right(), top),
+        rect(bounds.left(), bottom, bounds.right(), bounds.bottom()),
+        rect(bounds.left(), top, left, bottom),
+        rect(right, top, bounds.right(), bottom),
+    ];
+    for area in shaded {
+        context.clear_color(target, shade, Some(area));
+    }
+    let width = 1.0 / context.dpi_scale_factor;
+    let outline = [
+        rect(left, top, right, top + width),
+        rect(left, bottom - width, right, bottom),
+        rect(left, top, left + width, bottom),
+        rect(right - width, top, right, bottom),
+    ];
+    for edge in outline {
+        context.clear_color(target, line, Some(edge));
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Matrix4, Vector3};
+use glium::{
+    glutin::event::{ElementState, MouseButton},
+    program,
+    texture::SrgbTexture2d,
+    uniform,
+    uniforms::{MinifySamplerFilter, SamplerWrapFunction},
+    Frame, Program, Surface,
+};
+
+use super::picture_widget::orientation_to_matrix;
+use crate::{
+    add_common_widget_functions,
+    gelatin::{
+        misc::{
+            Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
+        },
+        window::{RenderValidity, Window},
+        DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData,
+        WidgetError,
+    },
+    image_cache::{
+        image_loader::Orientation,
+        thumbnails::{Thumbnail, ThumbnailCache},
+        AnimationFrameTexture,
+    },
+    shaders,
+};
+
+/// The space around each thumbnail in logical pixels
+const THUMBNAIL_PADDING: f32 = 2.0;
+/// The memory that the thumbnails of the images which aren't in the image
+/// cache may use, in bytes
+const THUMBNAIL_CACHE_SIZE: isize = 32 * 1024 * 1024;
+/// How often the finished thumbnails are checked while some are loading
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const CURRENT_COLOR: [f32; 4] = [0.25, 0.5, 0.95, 0.8];
+const HOVER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.35];
+const PLACEHOLDER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.2];
+
+/// An image of the folder shown in the filmstrip
+pub struct FilmstripItem {
+    /// The index of the image in the folder
+    pub index: usize,
+    pub path: PathBuf,
+    /// The first frame of the image if it's in the image cache
+    pub texture: Option<AnimationFrameTexture>,
+}
+
+impl FilmstripItem {
+    /// The cached texture if it can be drawn as a single texture. Images
+    /// that are split into a grid use a thumbnail instead.
+    fn single_texture(&self) -> Option<&AnimationFrameTexture> {
+        self.texture
+            .as_ref()
+            .filter(|texture| texture.tex_grid.len() == 1)
+    }
+
+    fn same_as(&self, other: &FilmstripItem) -> bool {
+        let same_texture = match (&self.texture, &other.texture) {
+            (Some(a), Some(b)) => Rc::ptr_eq(&a.tex_grid, &b.tex_grid),
+            (None, None) => true,
+            _ => false,
+        };
+        self.index == other.index && self.path == other.path && same_texture
+    }
+}
+
+struct FilmstripData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    /// Created on the first draw, when the display is available
+    program: Option<Program>,
+    bright_shade: f32,
+    /// Only created once the filmstrip is shown
+    thumbnails: Option<ThumbnailCache>,
+    dpi_scale: f32,
+
+    items: Vec<FilmstripItem>,
+    current: usize,
+    hover: Option<usize>,
+    last_mouse_pos: LogicalVector,
+    on_click: Option<Rc<dyn Fn(usize)>>,
+}
+
+impl WidgetData for FilmstripData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+impl FilmstripData {
+    /// The cells are squares as high as the widget
+    fn cell_size(&self) -> f32 {
+        self.drawn_bounds.size.vec.y.max(1.0)
+    }
+
+    fn thumbnail_size(&self) -> f32 {
+        (self.cell_size() - THUMBNAIL_PADDING * 2.0).max(1.0)
+    }
+
+    /// The area of the image at `index` of the folder. The current image is
+    /// in the middle of the widget.
+    fn cell_rect(&self, index: usize) -> LogicalRect {
+        let cell_size = self.cell_size();
+        let offset = index as f32 - self.current as f32;
+        let center = self.drawn_bounds.center();
+        LogicalRect {
+            pos: LogicalVector::new(
+                center.vec.x + (offset - 0.5) * cell_size,
+                self.drawn_bounds.top(),
+            ),
+            size: LogicalVector::new(cell_size, cell_size),
+        }
+    }
+
+    fn index_at(&self, point: LogicalVector) -> Option<usize> {
+        if !self.drawn_bounds.contains(point) {
+            return None;
+        }
+        self.items
+            .iter()
+            .map(|item| item.index)
+            .find(|&index| self.cell_rect(index).contains(point))
+    }
+
+    fn update_hover(&mut self) {
+        let hover = self.index_at(self.last_mouse_pos);
+        if hover != self.hover {
+            self.hover = hover;
+            self.render_validity.invalidate();
+        }
+    }
+}
+
+/// A row of small thumbnails of the images around the current one. Clicking
+/// a thumbnail jumps to its image.
+///
+/// The images that the `ImageCache` has prefetched are drawn from their
+/// textures. Only the ones that aren't in the cache are loaded as thumbnails.
+pub struct Filmstrip {
+    data: RefCell<FilmstripData>,
+}
+
+impl Filmstrip {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> Self {
+        Filmstrip {
+            data: RefCell::new(FilmstripData {
+                placement: Default::default(),
+                drawn_bounds: Default::default(),
+                visible: true,
+                render_validity: Default::default(),
+                program: None,
+                bright_shade: 0.95,
+                thumbnails: None,
+                dpi_scale: 1.0,
+                items: Vec::new(),
+                current: 0,
+                hover: None,
+                last_mouse_pos: Default::default(),
+                on_click: None,
+            }),
+        }
+    }
+
+    /// The number of images on each side of the current one that fit into
+    /// the widget
+    pub fn radius(&self) -> usize {
+        let borrowed = self.data.borrow();
+        let cells = borrowed.drawn_bounds.size.vec.x / borrowed.cell_size();
+        ((cells - 1.0) * 0.5).ceil().max(0.0) as usize
+    }
+
+    /// Sets the images to show around the image at `current`
+    pub fn set_items(&self, current: usize, items: Vec<FilmstripItem>) {
+        let mut borrowed = self.data.borrow_mut();
+        let unchanged = borrowed.current == current
+            && borrowed.items.len() == items.len()
+            && borrowed.items.iter().zip(&items).all(|(a, b)| a.same_as(b));
+        if unchanged {
+            return;
+        }
+        borrowed.current = current;
+        borrowed.items = items;
+        borrowed.update_hover();
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Sets the function that is called with the index of the image whose
+    /// thumbnail was clicked.
+    pub fn set_on_click<F: Fn(usize) + 'static>(&self, callback: F) {
+        self.data.borrow_mut().on_click = Some(Rc::new(callback));
+    }
+
+    pub fn set_bright_shade(&self, shade: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.bright_shade = shade;
+        borrowed.render_validity.invalidate();
+    }
+}
+
+impl Widget for Filmstrip {
+    fn before_draw(&self, window: &Window) -> NextUpdate {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return NextUpdate::Latest;
+        }
+        let size = (data.thumbnail_size() * data.dpi_scale).round() as u32;
+        let thumbnails = data.thumbnails.get_or_insert_with(|| {
+            ThumbnailCache::new(THUMBNAIL_CACHE_SIZE, 1)
+        });
+        thumbnails.set_size(size);
+        match thumbnails.receive(&window.display_mut()) {
+            Ok(true) => data.render_validity.invalidate(),
+            Ok(false) => (),
+            Err(e) => eprintln!("Error while uploading a thumbnail: {e}"),
+        }
+        // The closest images are requested first
+        let mut missing: Vec<&FilmstripItem> = data
+            .items
+            .iter()
+            .filter(|item| item.single_texture().is_none())
+            .collect();
+        missing.sort_by_key(|item| item.index.abs_diff(data.current));
+        for item in missing {
+            if !thumbnails.request(&item.path) {
+                break;
+            }
+        }
+        if thumbnails.loading() {
+            NextUpdate::WaitUntil(Instant::now() + LOADING_POLL_INTERVAL)
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        // The thumbnails are loaded again at the new size in `before_draw`
+        #[allow(clippy::float_cmp)]
+        let dpi_changed = data.dpi_scale != context.dpi_scale_factor;
+        data.dpi_scale = context.dpi_scale_factor;
+        let bounds = data.drawn_bounds;
+        let thumbnail_size = data.thumbnail_size();
+        let cells: Vec<LogicalRect> = data
+            .items
+            .iter()
+            .map(|item| data.cell_rect(item.index))
+            .collect();
+        let program = data.program.get_or_insert_with(|| {
+            program!(context.display,
+                140 => {
+                    vertex: shaders::VERTEX_140,
+                    fragment: shaders::FRAGMENT_140
+                },
+                110 => {
+                    vertex: shaders::VERTEX_110,
+                    fragment: shaders::FRAGMENT_110
+                },
+            )
+            .unwrap()
+        });
+        let Some(thumbnails) = &mut data.thumbnails else {
+            return Ok(NextUpdate::Soonest);
+        };
+        thumbnails.start_frame();
+        let draw_params = glium::DrawParameters {
+            viewport: Some(*context.viewport),
+            scissor: Some(context.logical_rect_to_viewport(&bounds)),
+            ..Default::default()
+        };
+        for (item, &cell) in data.items.iter().zip(&cells) {
+            if cell.right() < bounds.left() || cell.left() > bounds.right() {
+                continue;
+            }
+            if item.index == data.current {
+                context.clear_color(target, CURRENT_COLOR, Some(cell));
+            } else if data.hover == Some(item.index) {
+                context.clear_color(target, HOVER_COLOR, Some(cell));
+            }
+            let area = LogicalRect {
+                pos: cell.pos
+                    + LogicalVector::new(THUMBNAIL_PADDING, THUMBNAIL_PADDING),
+                size: LogicalVector::new(thumbnail_size, thumbnail_size),
+            };
+            let loaded;
+            let thumbnail = match item.single_texture() {
+                Some(texture) => Some((
+                    &texture.tex_grid[0].tex,
+                    texture.orientation,
+                    MinifySamplerFilter::LinearMipmapLinear,
+                )),
+                None => match thumbnails.get(&item.path) {
+                    Thumbnail::Loaded(texture) => {
+                        loaded = texture;
+                        // Thumbnails are oriented by the loader
+                        Some((
+                            &*loaded,
+                            Orientation::Deg0,
+                            MinifySamplerFilter::Linear,
+                        ))
+                    }
+                    Thumbnail::Loading | Thumbnail::Failed => None,
+                },
+            };
+            match thumbnail {
+                Some(thumbnail) => draw_texture(
+                    target,
+                    context,
+                    program,
+                    data.bright_shade,
+                    &draw_params,
+                    thumbnail,
+                    area,
+                ),
+                None => {
+                    context.clear_color(target, PLACEHOLDER_COLOR, Some(area))
+                }
+            }
+        }
+        if dpi_changed {
+            Ok(NextUpdate::Soonest)
+        } else {
+            Ok(NextUpdate::Latest)
+        }
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.default_layout(available_space);
+        borrowed.update_hover();
+    }
+
+    fn handle_event(&self, event: &Event) {
+        let mut borrowed = self.data.borrow_mut();
+        if !borrowed.visible {
+            return;
+        }
+        match event.kind {
+            EventKind::MouseMove => {
+                borrowed.last_mouse_pos = event.cursor_pos;
+                borrowed.update_hover();
+            }
+            EventKind::MouseButton {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+            } => {
+                let clicked = borrowed.index_at(event.cursor_pos);
+                let on_click = borrowed.on_click.clone();
+                drop(borrowed);
+                if let (Some(index), Some(on_click)) = (clicked, on_click) {
+                    on_click(index);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
+
+/// Draws `texture` shown with `orientation` to fit into `area`. Images
+/// smaller than the area aren't scaled up.
+pub fn draw_texture(
+    target: &mut Frame,
+    context: &DrawContext,
+    program: &Program,
+    bright_shade: f32,
+    draw_params: &glium::DrawParameters,
+    (texture, orientation, minify_filter): (
+        &SrgbTexture2d,
+        Orientation,
+        MinifySamplerFilter,
+    ),
+    area: LogicalRect,
+) {
+    let dpi_scale = context.dpi_scale_factor;
+    let (w, h) = texture.dimensions();
+    let (shown_w, shown_h) = if orientation.swaps_dimensions() {
+        (h as f32, w as f32)
+    } else {
+        (w as f32, h as f32)
+    };
+    let scale = (area.size.vec.x * dpi_scale / shown_w)
+        .min(area.size.vec.y * dpi_scale / shown_h)
+        .min(1.0);
+    // The size of the unrotated image in logical pixels
+    let size = LogicalVector::new(w as f32, h as f32) * scale / dpi_scale;
+    let center = area.center();
+    let to_center = Matrix4::from_translation(Vector3::new(-0.5, -0.5, 0.0));
+    let transform = context.projection_transform
+        * Matrix4::from_translation(center.vec.extend(0.0))
+        * orientation_to_matrix(orientation)
+        * Matrix4::from_nonuniform_scale(size.vec.x, size.vec.y, 1.0)
+        * to_center;
+    let lod_level = (1.0 / scale).log2().max(0.0).floor();
+    let sampler = texture
+        .sampled()
+        .wrap_function(SamplerWrapFunction::Clamp)
+        .minify_filter(minify_filter)
+        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
+    let uniforms = uniform! {
+        matrix: Into::<[[f32; 4]; 4]>::into(transform),
+        bright_shade: bright_shade,
+        tex: sampler,
+        lod_level: lod_level,
+    };
+    target
+        .draw(
+            context.unit_quad_vertices,
+            context.unit_quad_indices,
+            program,
+            &uniforms,
+            draw_params,
+        )
+        .unwrap();
+}
@@ -0,0 +1,593 @@
+use std::{
+    cell::RefCell,
+    ops::Range,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cgmath::Matrix4;
+use glium::{
+    glutin::event::{
+        ElementState, ModifiersState, MouseButton, VirtualKeyCode,
+    },
+    program, uniform, Display, Frame, Program, Surface,
+};
+
+use crate::{
+    add_common_widget_functions,
+    configuration::Configuration,
+    gelatin::{
+        misc::{
+            Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
+        },
+        window::{RenderValidity, Window},
+        DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData,
+        WidgetError,
+    },
+    image_cache::thumbnails::{Thumbnail, ThumbnailCache},
+    input_handling::*,
+    shaders,
+    utils::{virtual_keycode_is_char, virtual_keycode_to_string},
+};
+
+/// The space between the thumbnails in logical pixels
+const THUMBNAIL_PADDING: f32 = 8.0;
+/// The distance that a line of mouse wheel scrolling moves in logical pixels
+const SCROLL_LINE: f32 = 60.0;
+/// Two clicks on the same thumbnail within this time open the image
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How often the finished thumbnails are checked while some are loading
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const SELECTION_COLOR: [f32; 4] = [0.25, 0.5, 0.95, 0.6];
+const HOVER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.25];
+const PLACEHOLDER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.15];
+const SCROLLBAR_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.6];
+
+struct GalleryData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    config: Arc<Mutex<Configuration>>,
+    program: Program,
+    bright_shade: f32,
+    thumbnails: ThumbnailCache,
+    /// The size of the thumbnails in logical pixels
+    thumbnail_size: f32,
+    /// The dpi scale factor of the last draw
+    dpi_scale: f32,
+
+    paths: Vec<PathBuf>,
+    selected: usize,
+    hover: Option<usize>,
+    /// The distance that the grid is scrolled down in logical pixels
+    scroll: f32,
+    /// Set when the selected thumbnail should be scrolled into view
+    scroll_to_selected: bool,
+    last_click: Option<(usize, Instant)>,
+    last_mouse_pos: LogicalVector,
+    title: String,
+
+    /// Set when the gallery should be closed, to the image that should be
+    /// shown if one was opened
+    closing: Option<Option<PathBuf>>,
+    on_close: Option<Rc<dyn Fn(Option<PathBuf>)>>,
+}
+
+impl WidgetData for GalleryData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+impl GalleryData {
+    fn cell_size(&self) -> f32 {
+        self.thumbnail_size + THUMBNAIL_PADDING * 2.0
+    }
+
+    fn columns(&self) -> usize {
+        let columns = self.drawn_bounds.size.vec.x / self.cell_size();
+        (columns.floor() as usize).max(1)
+    }
+
+    fn rows(&self) -> usize {
+        self.paths.len().div_ceil(self.columns())
+    }
+
+    /// The number of rows that fit into the widget
+    fn page_rows(&self) -> usize {
+        let rows = self.drawn_bounds.size.vec.y / self.cell_size();
+        (rows.floor() as usize).max(1)
+    }
+
+    fn max_scroll(&self) -> f32 {
+        let content_height = self.rows() as f32 * self.cell_size();
+        (content_height - self.drawn_bounds.size.vec.y).max(0.0)
+    }
+
+    /// The area of the thumbnail at `index` in window coordinates
+    fn cell_rect(&self, index: usize) -> LogicalRect {
+        let cell_size = self.cell_size();
+        let columns = self.columns();
+        let bounds = self.drawn_bounds;
+        // The grid is centered horizontally
+        let left = bounds.left()
+            + ((bounds.size.vec.x - columns as f32 * cell_size) * 0.5).max(0.0);
+        let col = (index % columns) as f32;
+        let row = (index / columns) as f32;
+        LogicalRect {
+            pos: LogicalVector::new(
+                left + col * cell_size,
+                bounds.top() + row * cell_size - self.scroll,
+            ),
+            size: LogicalVector::new(cell_size, cell_size),
+        }
+    }
+
+    fn index_at(&self, point: LogicalVector) -> Option<usize> {
+        if !self.drawn_bounds.contains(point) {
+            return None;
+        }
+        let first = self.cell_rect(0);
+        let cell_size = self.cell_size();
+        let col = ((point.vec.x - first.left()) / cell_size).floor();
+        let row = ((point.vec.y - first.top()) / cell_size).floor();
+        if col < 0.0 || col >= self.columns() as f32 || row < 0.0 {
+            return None;
+        }
+        let index = row as usize * self.columns() + col as usize;
+        (index < self.paths.len()).then_some(index)
+    }
+
+    /// The indices of the thumbnails that are at least partially visible
+    fn visible_range(&self) -> Range<usize> {
+        let cell_size = self.cell_size();
+        let columns = self.columns();
+        let first_row = (self.scroll / cell_size).floor() as usize;
+        let last_row = ((self.scroll + self.drawn_bounds.size.vec.y)
+            / cell_size)
+            .ceil() as usize;
+        let end = (last_row * columns).min(self.paths.len());
+        (first_row * columns).min(end)..end
+    }
+
+    fn scroll_by(&mut self, delta: f32) {
+        self.scroll = (self.scroll + delta).clamp(0.0, self.max_scroll());
+        self.update_hover();
+        self.render_validity.invalidate();
+    }
+
+    fn scroll_selected_into_view(&mut self) {
+        let cell_size = self.cell_size();
+        let row_top = (self.selected / self.columns()) as f32 * cell_size;
+        let height = self.drawn_bounds.size.vec.y;
+        if row_top < self.scroll {
+            self.scroll = row_top;
+        } else if row_top + cell_size > self.scroll + height {
+            self.scroll = row_top + cell_size - height;
+        }
+        self.scroll = self.scroll.clamp(0.0, self.max_scroll());
+        self.update_hover();
+    }
+
+    fn update_hover(&mut self) {
+        let hover = self.index_at(self.last_mouse_pos);
+        if hover != self.hover {
+            self.hover = hover;
+            self.render_validity.invalidate();
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        if self.paths.is_empty() {
+            return;
+        }
+        self.selected = index.min(self.paths.len() - 1);
+        self.scroll_to_selected = true;
+        self.render_validity.invalidate();
+    }
+
+    /// Moves the selection by `rows` and `cols`, stopping at the ends
+    fn move_selection(&mut self, rows: isize, cols: isize) {
+        let offset = rows * self.columns() as isize + cols;
+        let index = (self.selected as isize + offset).max(0) as usize;
+        // Moving down from the last full row goes to the last image
+        self.select(index);
+    }
+
+    fn close(&mut self, open: Option<PathBuf>) {
+        self.closing = Some(open);
+        self.render_validity.invalidate();
+    }
+
+    fn open_selected(&mut self) {
+        let path = self.paths.get(self.selected).cloned();
+        self.close(path);
+    }
+
+    /// Requests the visible thumbnails first and then the ones on the next
+    /// page.
+    fn request_thumbnails(&mut self) {
+        let visible = self.visible_range();
+        let ahead = self.page_rows() * self.columns();
+        let end = (visible.end + ahead).min(self.paths.len());
+        for index in visible.start..end {
+            if !self.thumbnails.request(&self.paths[index]) {
+                break;
+            }
+        }
+    }
+
+    fn update_title(&mut self, window: &Window) {
+        let title = match self.paths.get(self.selected) {
+            Some(path) => {
+                let name = path.file_name().unwrap_or_default();
+                format!(
+                    "{} : Gallery ({} / {})",
+                    name.to_string_lossy(),
+                    self.selected + 1,
+                    self.paths.len()
+                )
+            }
+            None => "Gallery".to_string(),
+        };
+        if title != self.title {
+            self.title = title.clone();
+            window.set_title(title);
+        }
+    }
+
+    fn handle_key_input(&mut self, input_key: &str, modifiers: ModifiersState) {
+        if action_triggered(
+            &self.config,
+            TOGGLE_GALLERY_NAME,
+            input_key,
+            modifiers,
+        ) || action_triggered(
+            &self.config,
+            ESCAPE_NAME,
+            input_key,
+            modifiers,
+        ) {
+            self.close(None);
+        }
+    }
+
+    fn handle_navigation_key(&mut self, key: VirtualKeyCode) {
+        let page = self.page_rows() as isize;
+        match key {
+            VirtualKeyCode::Left => self.move_selection(0, -1),
+            VirtualKeyCode::Right => self.move_selection(0, 1),
+            VirtualKeyCode::Up => self.move_selection(-1, 0),
+            VirtualKeyCode::Down => self.move_selection(1, 0),
+            VirtualKeyCode::PageUp => self.move_selection(-page, 0),
+            VirtualKeyCode::PageDown => self.move_selection(page, 0),
+            VirtualKeyCode::Home => self.select(0),
+            VirtualKeyCode::End => self.select(usize::MAX),
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                self.open_selected()
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Shows the images of a folder as a scrollable grid of thumbnails
+pub struct Gallery {
+    data: RefCell<GalleryData>,
+}
+
+impl Gallery {
+    add_common_widget_functions!(data);
+
+    pub fn new(display: &Display, config: Arc<Mutex<Configuration>>) -> Self {
+        let program = program!(display,
+            140 => {
+                vertex: shaders::VERTEX_140,
+                fragment: shaders::FRAGMENT_140
+            },
+            110 => {
+                vertex: shaders::VERTEX_110,
+                fragment: shaders::FRAGMENT_110
+            },
+        )
+        .unwrap();
+        let (thumbnail_size, cache_size) = {
+            let config = config.lock().unwrap();
+            (config.thumbnail_size(), config.thumbnail_cache_size())
+        };
+        Gallery {
+            data: RefCell::new(GalleryData {
+                placement: Default::default(),
+                drawn_bounds: Default::default(),
+                visible: false,
+                render_validity: Default::default(),
+                config,
+                program,
+                bright_shade: 0.95,
+                thumbnails: ThumbnailCache::new(cache_size, 2),
+                thumbnail_size: thumbnail_size as f32,
+                dpi_scale: 1.0,
+                paths: Vec::new(),
+                selected: 0,
+                hover: None,
+                scroll: 0.0,
+                scroll_to_selected: false,
+                last_click: None,
+                last_mouse_pos: Default::default(),
+                title: String::new(),
+                closing: None,
+                on_close: None,
+            }),
+        }
+    }
+
+    /// Shows the thumbnails of `paths` with the image at `selected` selected
+    pub fn open(&self, paths: Vec<PathBuf>, selected: usize) {
+        let mut borrowed = self.data.borrow_mut();
+        let thumbnail_size = borrowed.config.lock().unwrap().thumbnail_size();
+        borrowed.thumbnail_size = thumbnail_size as f32;
+        borrowed.paths = paths;
+        borrowed.hover = None;
+        borrowed.last_click = None;
+        borrowed.title.clear();
+        borrowed.closing = None;
+        borrowed.visible = true;
+        borrowed.select(selected);
+    }
+
+    /// Sets the function that is called when the gallery is closed, with the
+    /// path of the image that was opened from it, if any.
+    pub fn set_on_close<F: Fn(Option<PathBuf>) + 'static>(&self, callback: F) {
+        self.data.borrow_mut().on_close = Some(Rc::new(callback));
+    }
+
+    pub fn set_bright_shade(&self, shade: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.bright_shade = shade;
+        borrowed.render_validity.invalidate();
+    }
+}
+
+impl Widget for Gallery {
+    fn before_draw(&self, window: &Window) -> NextUpdate {
+        let mut data = self.data.borrow_mut();
+        if let Some(open) = data.closing.take() {
+            data.visible = false;
+            data.paths.clear();
+            let on_close = data.on_close.clone();
+            drop(data);
+            if let Some(on_close) = on_close {
+                on_close(open);
+            }
+            return NextUpdate::Latest;
+        }
+        if !data.visible {
+            return NextUpdate::Latest;
+        }
+        data.update_title(window);
+
+        let physical_size = (data.thumbnail_size * data.dpi_scale).round();
+        data.thumbnails.set_size(physical_size as u32);
+        let received = data.thumbnails.receive(&window.display_mut());
+        match received {
+            Ok(true) => data.render_validity.invalidate(),
+            Ok(false) => (),
+            Err(e) => eprintln!("Error while uploading a thumbnail: {e}"),
+        }
+        data.request_thumbnails();
+        if data.thumbnails.loading() {
+            NextUpdate::WaitUntil(Instant::now() + LOADING_POLL_INTERVAL)
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        // Only done here because the layout is needed for it
+        if std::mem::take(&mut data.scroll_to_selected) {
+            data.scroll_selected_into_view();
+        }
+        data.dpi_scale = context.dpi_scale_factor;
+        data.thumbnails.start_frame();
+        let mut waiting = false;
+        let bounds = data.drawn_bounds;
+        let draw_params = glium::DrawParameters {
+            viewport: Some(*context.viewport),
+            scissor: Some(context.logical_rect_to_viewport(&bounds)),
+            ..Default::default()
+        };
+        for index in data.visible_range() {
+            let cell = data.cell_rect(index);
+            let highlight = LogicalRect {
+                pos: cell.pos + LogicalVector::new(2.0, 2.0),
+                size: cell.size - LogicalVector::new(4.0, 4.0),
+            };
+            if index == data.selected {
+                context.clear_color(target, SELECTION_COLOR, Some(highlight));
+            } else if data.hover == Some(index) {
+                context.clear_color(target, HOVER_COLOR, Some(highlight));
+            }
+            let texture = match data.thumbnails.get(&data.paths[index]) {
+                Thumbnail::Loaded(texture) => texture,
+                thumbnail => {
+                    waiting |= matches!(thumbnail, Thumbnail::Loading);
+                    let placeholder = LogicalRect {
+                        pos: cell.pos
+                            + LogicalVector::new(
+                                THUMBNAIL_PADDING,
+                                THUMBNAIL_PADDING,
+                            ),
+                        size: LogicalVector::new(
+                            data.thumbnail_size,
+                            data.thumbnail_size,
+                        ),
+                    };
+                    context.clear_color(
+                        target,
+                        PLACEHOLDER_COLOR,
+                        Some(placeholder),
+                    );
+                    continue;
+                }
+            };
+            // Small images aren't scaled up
+            let (w, h) = texture.dimensions();
+            let max_side = w.max(h) as f32 / context.dpi_scale_factor;
+            let scale = (data.thumbnail_size / max_side).min(1.0);
+            let size = LogicalVector::new(w as f32, h as f32) * scale
+                / context.dpi_scale_factor;
+            let center = cell.center().vec * context.dpi_scale_factor;
+            let corner = (center - size.vec * context.dpi_scale_factor * 0.5)
+                .map(f32::round)
+                / context.dpi_scale_factor;
+            let transform = Matrix4::from_translation(corner.extend(0.0))
+                * Matrix4::from_nonuniform_scale(size.vec.x, size.vec.y, 1.0);
+            let transform = context.projection_transform * transform;
+            let sampler = texture
+                .sampled()
+                .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear);
+            let uniforms = uniform! {
+                matrix: Into::<[[f32; 4]; 4]>::into(transform),
+                bright_shade: data.bright_shade,
+                tex: sampler,
+                lod_level: 0.0f32,
+            };
+            target
+                .draw(
+                    context.unit_quad_vertices,
+                    context.unit_quad_indices,
+                    &data.program,
+                    &uniforms,
+                    &draw_params,
+                )
+                .unwrap();
+        }
+
+        let max_scroll = data.max_scroll();
+        if max_scroll > 0.0 {
+            let height = bounds.size.vec.y;
+            let bar_height = height * height / (height + max_scroll);
+            let bar_top = (height - bar_height) * data.scroll / max_scroll;
+            let bar = LogicalRect {
+                pos: LogicalVector::new(
+                    bounds.right() - 6.0,
+                    bounds.top() + bar_top,
+                ),
+                size: LogicalVector::new(4.0, bar_height),
+            };
+            context.clear_color(target, SCROLLBAR_COLOR, Some(bar));
+        }
+        if waiting {
+            // The thumbnails are requested in `before_draw`
+            let next_update = Instant::now() + LOADING_POLL_INTERVAL;
+            Ok(NextUpdate::WaitUntil(next_update))
+        } else {
+            Ok(NextUpdate::Latest)
+        }
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.default_layout(available_space);
+        // The number of columns may have changed
+        borrowed.scroll = borrowed.scroll.clamp(0.0, borrowed.max_scroll());
+        borrowed.update_hover();
+    }
+
+    fn handle_event(&self, event: &Event) {
+        let mut borrowed = self.data.borrow_mut();
+        if !borrowed.visible || borrowed.closing.is_some() {
+            return;
+        }
+        match event.kind {
+            EventKind::MouseMove => {
+                borrowed.last_mouse_pos = event.cursor_pos;
+                borrowed.update_hover();
+            }
+            EventKind::MouseButton {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+            } => {
+                if let Some(index) = borrowed.index_at(event.cursor_pos) {
+                    let now = Instant::now();
+                    let double_click = match borrowed.last_click {
+                        Some((last_index, time)) => {
+                            last_index == index
+                                && now - time < DOUBLE_CLICK_TIME
+                        }
+                        None => false,
+                    };
+                    borrowed.select(index);
+                    if double_click {
+                        borrowed.last_click = None;
+                        borrowed.open_selected();
+                    } else {
+                        borrowed.last_click = Some((index, now));
+                    }
+                }
+            }
+            EventKind::MouseScroll { delta } => {
+                borrowed.scroll_by(-delta.vec.y * SCROLL_LINE);
+            }
+            EventKind::ReceivedCharacter(ch) if !event.modifiers.ctrl() => {
+                let input_key = char_to_input_key(ch);
+                borrowed.handle_key_input(&input_key, event.modifiers);
+            }
+            EventKind::KeyInput { input } => {
+                let Some(key) = input.virtual_keycode else {
+                    return;
+                };
+                if input.state != ElementState::Pressed {
+                    return;
+                }
+                let printable =
+                    !event.modifiers.ctrl() && virtual_keycode_is_char(key);
+                if !printable {
+                    let input_key =
+                        virtual_keycode_to_string(key).to_lowercase();
+                    borrowed.handle_key_input(&input_key, event.modifiers);
+                }
+                borrowed.handle_navigation_key(key);
+            }
+            _ => (),
+        }
+    }
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
@@ -1,4 +1,12 @@
 pub mod bottom_bar;
+pub mod compare;
 pub mod copy_notification;
+pub mod crop_selection;
+pub mod filmstrip;
+pub mod gallery;
 pub mod help_screen;
+pub mod notification;
 pub mod picture_widget;
+pub mod prompt;
+pub mod slider_preview;
+pub mod text;
@@ -0,0 +1,246 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use glium::{
+    program,
+    texture::{MipmapsOption, RawImage2d, SrgbTexture2d},
+    uniforms::MinifySamplerFilter,
+    Frame, Program,
+};
+
+use super::{filmstrip::draw_texture, text};
+use crate::{
+    add_common_widget_functions,
+    gelatin::{
+        misc::{
+            Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
+        },
+        window::{RenderValidity, Window},
+        DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
+    },
+    image_cache::image_loader::Orientation,
+    shaders,
+};
+
+const DISPLAY_TIME: Duration = Duration::from_millis(1500);
+/// The space between the message and the top edge of the widget
+const MARGIN: f32 = 8.0;
+const FONT_SIZE: f32 = 14.0;
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+/// The rendered message
+struct MessageLabel {
+    message: String,
+    dpi_scale: f32,
+    /// `None` if the message couldn't be rendered
+    texture: Option<SrgbTexture2d>,
+}
+
+struct NotificationData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    /// Created on the first draw, when the display is available
+    program: Option<Program>,
+    label: Option<MessageLabel>,
+
+    message: String,
+    hide_time: Instant,
+}
+
+impl WidgetData for NotificationData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+/// A short message at the top of the widgets in the same container, which
+/// disappears after a moment
+pub struct Notification {
+    data: RefCell<NotificationData>,
+}
+
+impl Notification {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> Self {
+        text::preload_fonts();
+        Notification {
+            data: RefCell::new(NotificationData {
+                placement: WidgetPlacement {
+                    width: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    height: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    ignore_layout: true,
+                    ..Default::default()
+                },
+                drawn_bounds: Default::default(),
+                visible: false,
+                render_validity: Default::default(),
+                program: None,
+                label: None,
+                message: String::new(),
+                hide_time: Instant::now(),
+            }),
+        }
+    }
+
+    /// Shows `message` in place of the previous one
+    pub fn show(&self, message: &str) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = true;
+        borrowed.message = message.to_owned();
+        borrowed.hide_time = Instant::now() + DISPLAY_TIME;
+        borrowed.render_validity.invalidate();
+    }
+}
+
+impl Default for Notification {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for Notification {
+    fn before_draw(&self, _window: &Window) -> NextUpdate {
+        let mut borrowed = self.data.borrow_mut();
+        if !borrowed.visible {
+            return NextUpdate::Latest;
+        }
+        if Instant::now() < borrowed.hide_time {
+            return NextUpdate::WaitUntil(borrowed.hide_time);
+        }
+        borrowed.visible = false;
+        borrowed.render_validity.invalidate();
+        NextUpdate::Latest
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        let dpi_scale = context.dpi_scale_factor;
+        #[allow(clippy::float_cmp)]
+        let label_outdated = match &data.label {
+            Some(label) => {
+                label.message != data.message || label.dpi_scale != dpi_scale
+            }
+            None => true,
+        };
+        if label_outdated {
+            let image = text::render_text(
+                &data.message,
+                FONT_SIZE * dpi_scale,
+                BACKGROUND,
+            );
+            let texture = image.and_then(|image| {
+                let dimensions = image.dimensions();
+                let raw_image =
+                    RawImage2d::from_raw_rgba(image.into_raw(), dimensions);
+                let texture = SrgbTexture2d::with_mipmaps(
+                    context.display,
+                    raw_image,
+                    MipmapsOption::NoMipmap,
+                );
+                texture
+                    .map_err(|e| eprintln!("Error while uploading text: {e}"))
+                    .ok()
+            });
+            data.label = Some(MessageLabel {
+                message: data.message.clone(),
+                dpi_scale,
+                texture,
+            });
+        }
+        let Some(texture) =
+            data.label.as_ref().and_then(|label| label.texture.as_ref())
+        else {
+            return Ok(NextUpdate::Latest);
+        };
+
+        let (w, h) = texture.dimensions();
+        let size = LogicalVector::new(w as f32, h as f32) / dpi_scale;
+        let bounds = data.drawn_bounds;
+        let area = LogicalRect {
+            pos: LogicalVector::new(
+                bounds.center().vec.x - size.vec.x * 0.5,
+                bounds.top() + MARGIN,
+            ),
+            size,
+        };
+        let program = data.program.get_or_insert_with(|| {
+            program!(context.display,
+                140 => {
+                    vertex: shaders::VERTEX_140,
+                    fragment: shaders::FRAGMENT_140
+                },
+                110 => {
+                    vertex: shaders::VERTEX_110,
+                    fragment: shaders::FRAGMENT_110
+                },
+            )
+            .unwrap()
+        });
+        let draw_params = glium::DrawParameters {
+            viewport: Some(*context.viewport),
+            scissor: Some(context.logical_rect_to_viewport(&bounds)),
+            ..Default::default()
+        };
+        draw_texture(
+            target,
+            context,
+            program,
+            // The text is opaque, so the shade of the checkerboard behind
+            // transparent pixels doesn't matter
+            1.0,
+            &draw_params,
+            (texture, Orientation::Deg0, MinifySamplerFilter::Linear),
+            area,
+        );
+        Ok(NextUpdate::Latest)
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        self.data.borrow_mut().default_layout(available_space);
+    }
+
+    fn handle_event(&self, _event: &Event) {}
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
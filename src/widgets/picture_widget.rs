@@ -1,27 +1,41 @@
 use std::{
-    cell::{Ref, RefCell},
+    cell::RefCell,
     path::{Path, PathBuf},
     rc::{Rc, Weak},
-    sync::{Arc, Mutex},
+    sync::{mpsc, Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use cgmath::{Matrix4, Vector2, Vector3};
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector2, Vector3};
 use glium::{
-    glutin::event::{ElementState, ModifiersState, MouseButton},
-    program, uniform,
-    uniforms::MagnifySamplerFilter,
+    glutin::event::{
+        ElementState, ModifiersState, MouseButton, VirtualKeyCode,
+    },
+    program,
+    texture::SrgbTexture2d,
+    uniform,
+    uniforms::{MagnifySamplerFilter, Sampler},
     Display, Frame, Program, Surface,
 };
 
 use super::{
-    bottom_bar::BottomBar, copy_notification::CopyNotifications,
+    bottom_bar::BottomBar,
+    compare::{self, Compare, CompareLayout, CompareTarget},
+    copy_notification::CopyNotifications,
+    crop_selection::{self, CropSelection, ASPECT_RATIOS},
+    filmstrip::FilmstripItem,
+    gallery::Gallery,
     help_screen::HelpScreen,
+    notification::Notification,
+    prompt::Prompt,
 };
 use crate::{
     add_common_widget_functions,
     clipboard_handler::ClipboardHandler,
-    configuration::{Antialias, Configuration, ScalingMode},
+    configuration::{
+        Antialias, CompareAnchor, Configuration, ScalingMode, SortKey,
+    },
+    export::{self, ExportSettings},
     gelatin::{
         application::request_exit,
         misc::{
@@ -31,16 +45,32 @@ use crate::{
         DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData,
         WidgetError,
     },
-    image_cache::{image_loader::Orientation, AnimationFrameTexture},
+    image_cache::{
+        filter::ImageFilter,
+        image_loader::{is_stdin_image, CropRect, ImageEdits, Orientation},
+        sort::SortOrder,
+        AnimationFrameTexture, FolderEnd, TextureGridItem,
+    },
     input_handling::*,
+    orientation_saver,
     playback_manager::*,
     shaders,
     utils::{virtual_keycode_is_char, virtual_keycode_to_string},
+    view_memory::{ViewMemory, ViewState},
 };
 
 const MIN_ZOOM_FACTOR: f32 = 0.0001;
 const MAX_ZOOM_FACTOR: f32 = 10000.0;
 const AA_TEXEL_SIZE_THRESHOLD: f32 = 4f32;
+/// The portion of the panel that a page scroll moves, the rest stays in view
+/// to make it easier to follow along.
+const PAGE_SCROLL_RATIO: f32 = 0.9;
+/// How long and how far the image is nudged when there's no image past the
+/// end of the folder
+const BUMP_TIME: Duration = Duration::from_millis(250);
+const BUMP_DISTANCE: f32 = 24.0;
+/// The largest count that can be typed in front of a navigation
+const MAX_COUNT: u32 = 1_000_000;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum MovementDir {
@@ -61,7 +91,7 @@ enum HoverState {
     ItemHovered { prev_path: PathBuf },
 }
 
-fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
+pub fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
     #[rustfmt::skip]
 	let result = match orientation {
 		Orientation::Deg0 => Matrix4::from_scale(1.0),
@@ -90,8 +120,8 @@ fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
 			0.0, 0.0, 0.0, 1.0
 		),
 		Orientation::Deg90VerFlip => Matrix4::new(
-			0.0, -1.0, 0.0, 0.0,
-			-1.0, 0.0, 0.0, 0.0,
+			0.0, 1.0, 0.0, 0.0,
+			1.0, 0.0, 0.0, 0.0,
 			0.0, 0.0, 1.0, 0.0,
 			0.0, 0.0, 0.0, 1.0
 		),
@@ -102,8 +132,8 @@ fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
 			0.0, 0.0, 0.0, 1.0
 		),
 		Orientation::Deg270VerFlip => Matrix4::new(
-			0.0, 1.0, 0.0, 0.0,
-			1.0, 0.0, 0.0, 0.0,
+			0.0, -1.0, 0.0, 0.0,
+			-1.0, 0.0, 0.0, 0.0,
 			0.0, 0.0, 1.0, 0.0,
 			0.0, 0.0, 0.0, 1.0
 		),
@@ -111,6 +141,24 @@ fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
     result
 }
 
+/// The state that the items of the filmstrip depend on
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FilmstripKey {
+    curr_index: usize,
+    dir_len: usize,
+    radius: usize,
+    cache_version: (u64, u64),
+}
+
+/// What a save on a background thread sends back when it finishes
+struct FinishedSave {
+    /// The message to show
+    message: String,
+    /// The file whose orientation was saved, and the orientation that the
+    /// user had given it
+    orientation: Option<(PathBuf, Orientation)>,
+}
+
 struct PictureWidgetData {
     placement: WidgetPlacement,
     drawn_bounds: LogicalRect,
@@ -128,11 +176,36 @@ struct PictureWidgetData {
     clipboard_request_was_pending: bool,
 
     program: Program,
+    /// Combines the two images of the compare mode
+    overlay_program: Program,
     bright_shade: f32,
     /// Size of an image texel in physical display pixels
     img_texel_size: f32,
     scaling: ScalingMode,
     img_pos: LogicalVector,
+    /// Set when the free axis of the fit-width and fit-height modes should
+    /// be scrolled back to the start, e.g. because a new image is shown.
+    scroll_to_start: bool,
+    /// When set, the zoom and pan are carried over to the next image
+    /// instead of applying the scaling mode again.
+    view_locked: bool,
+    view_memory: ViewMemory,
+    /// True if the current view was restored from `view_memory`
+    view_from_memory: bool,
+    /// The dpi scale factor of the last draw
+    dpi_scale: f32,
+    /// Rotation and flip applied by the user on top of the orientation of the image
+    user_orientation: Orientation,
+    /// When set, dragging with the left mouse button selects a region
+    /// instead of panning.
+    crop_mode: bool,
+    selection: Option<CropSelection>,
+    /// True while the selection is being dragged
+    selecting: bool,
+    /// Index into `ASPECT_RATIOS`
+    crop_aspect: usize,
+    /// Set while the image is compared with a second one
+    compare: Option<Compare>,
     antialiasing: Antialias,
 
     hor_pan_input: MovementDir,
@@ -154,8 +227,31 @@ struct PictureWidgetData {
     last_cam_move_time: Instant,
     next_update: NextUpdate,
     bottom_bar: Rc<BottomBar>,
+    gallery: Rc<Gallery>,
+    /// Set when the gallery should be opened before the next draw
+    gallery_requested: bool,
+    /// The number of subfolder levels listed when recursive mode is turned on
+    recursion_depth: u32,
     left_to_pan_hint: Rc<HelpScreen>,
     copy_notifications: CopyNotifications,
+    /// What the items of the filmstrip were last built from
+    filmstrip_key: Option<FilmstripKey>,
+    /// Tells when the image was reloaded because its file changed, or when
+    /// an export finished
+    notification: Rc<Notification>,
+    /// The exports and orientation saves run on their own threads, which
+    /// report back when they finish
+    export_sender: mpsc::Sender<FinishedSave>,
+    export_results: mpsc::Receiver<FinishedSave>,
+    /// The number of saves that haven't finished yet
+    pending_exports: usize,
+    /// Asks for the number of the image to go to
+    prompt: Rc<Prompt>,
+    /// The number typed in front of a navigation, which repeats it
+    count_prefix: Option<u32>,
+    /// The start of the nudge that shows that there's no image past the end
+    /// of the folder, and that end
+    bump: Option<(Instant, FolderEnd)>,
     window: Weak<Window>,
 }
 
@@ -174,38 +270,102 @@ impl WidgetData for PictureWidgetData {
 }
 
 impl PictureWidgetData {
-    fn fit_image_to_panel(&mut self, dpi_scale: f32, stretch: bool) {
-        let size = self.drawn_bounds.size.vec;
+    fn fit_image_to_panel(&mut self, dpi_scale: f32, scaling: ScalingMode) {
+        let size = self.view_bounds().size.vec;
         if let Some(texture) = self.get_texture() {
             let panel_aspect = size.x / size.y;
             let (img_phys_w, img_pyhs_h) = {
-                let (w, h) = texture.oriented_dimensions();
+                let (w, h) = self.shown_dimensions(&texture);
                 (w as f32, h as f32)
             };
             let img_aspect = img_phys_w / img_pyhs_h;
 
             let texel_size_to_fit_width = size.x / img_phys_w;
-            let img_texel_size = if img_aspect > panel_aspect {
+            let texel_size_to_fit_height = size.y / img_pyhs_h;
+            let img_texel_size = match scaling {
+                ScalingMode::FitWidth => texel_size_to_fit_width,
+                ScalingMode::FitHeight => texel_size_to_fit_height,
                 // The image is relatively wider than the panel
-                texel_size_to_fit_width
-            } else {
-                texel_size_to_fit_width * (img_aspect / panel_aspect)
+                _ if img_aspect > panel_aspect => texel_size_to_fit_width,
+                _ => texel_size_to_fit_width * (img_aspect / panel_aspect),
             };
             let widget_phys_size = size * dpi_scale;
             let fits_in_widget = widget_phys_size.x >= img_phys_w
                 && widget_phys_size.y >= img_pyhs_h;
+            let prev_pos = self.img_pos;
             self.img_pos = LogicalVector::new(size.x * 0.5, size.y * 0.5);
-            if fits_in_widget && !stretch {
+            if fits_in_widget && scaling == ScalingMode::FitMin {
                 self.img_texel_size = 1.0;
             } else {
                 self.img_texel_size = img_texel_size * dpi_scale;
             }
-            if stretch {
-                self.scaling = ScalingMode::FitStretch;
-            } else {
-                self.scaling = ScalingMode::FitMin;
+            let img_size =
+                LogicalVector::new(img_phys_w, img_pyhs_h) * img_texel_size;
+            match scaling {
+                ScalingMode::FitWidth => {
+                    self.img_pos.vec.y = self.free_axis_pos(
+                        prev_pos.vec.y,
+                        img_size.vec.y,
+                        size.y,
+                    );
+                }
+                ScalingMode::FitHeight => {
+                    self.img_pos.vec.x = self.free_axis_pos(
+                        prev_pos.vec.x,
+                        img_size.vec.x,
+                        size.x,
+                    );
+                }
+                _ => (),
+            }
+            self.scroll_to_start = false;
+            self.scaling = scaling;
+        }
+    }
+
+    /// Returns the image position along the axis that is not fitted to the panel
+    /// when using `FitWidth` or `FitHeight`. A newly shown image starts at its
+    /// top or left edge, otherwise the position is kept but the image is not
+    /// allowed to leave a gap at either end of the panel.
+    fn free_axis_pos(&self, prev: f32, img_len: f32, panel_len: f32) -> f32 {
+        if img_len <= panel_len {
+            panel_len * 0.5
+        } else if self.scroll_to_start {
+            img_len * 0.5
+        } else {
+            prev.clamp(panel_len - img_len * 0.5, img_len * 0.5)
+        }
+    }
+
+    /// Moves the image by `delta` logical pixels. In the fit-width and
+    /// fit-height modes only the free axis moves and the mode is kept.
+    fn pan_image(&mut self, delta: LogicalVector) {
+        match self.scaling {
+            ScalingMode::FitWidth => self.img_pos.vec.y += delta.vec.y,
+            ScalingMode::FitHeight => self.img_pos.vec.x += delta.vec.x,
+            _ => {
+                self.scaling = ScalingMode::Fixed;
+                self.update_scaling_buttons();
+                self.img_pos += delta;
             }
         }
+        self.render_validity.invalidate();
+    }
+
+    /// Scrolls one page along the free axis in the fit-width and fit-height
+    /// modes. A positive direction moves towards the top or left edge of the image.
+    fn scroll_page(&mut self, hor_dir: f32, ver_dir: f32) {
+        let size = self.view_bounds().size.vec;
+        let delta = match self.scaling {
+            ScalingMode::FitWidth => {
+                LogicalVector::new(0.0, ver_dir * size.y * PAGE_SCROLL_RATIO)
+            }
+            ScalingMode::FitHeight => {
+                LogicalVector::new(hor_dir * size.x * PAGE_SCROLL_RATIO, 0.0)
+            }
+            _ => return,
+        };
+        self.pan_image(delta);
     }
 
     fn zoom_image(&mut self, anchor: LogicalVector, mut delta: f32) {
@@ -232,21 +392,18 @@ impl PictureWidgetData {
     }
 
     fn update_image_transform(&mut self, dpi_scale: f32) {
+        let view_size = self.view_bounds().size;
         match self.scaling {
             ScalingMode::Fixed => {
-                let center_offset =
-                    (self.drawn_bounds.size - self.prev_draw_size) * 0.5f32;
+                let center_offset = (view_size - self.prev_draw_size) * 0.5f32;
                 self.img_pos += center_offset;
                 self.apply_img_bounds(dpi_scale);
             }
-            ScalingMode::FitStretch => {
-                self.fit_image_to_panel(dpi_scale, true);
-            }
-            ScalingMode::FitMin => {
-                self.fit_image_to_panel(dpi_scale, false);
+            scaling => {
+                self.fit_image_to_panel(dpi_scale, scaling);
             }
         }
-        self.prev_draw_size = self.drawn_bounds.size;
+        self.prev_draw_size = view_size;
     }
 
     fn apply_camera_movement(&mut self, dpi_scale: f32) {
@@ -315,7 +472,7 @@ impl PictureWidgetData {
         }
 
         if self.zoom_input.moving() {
-            let bounds_size = self.drawn_bounds.size.vec;
+            let bounds_size = self.view_bounds().size.vec;
             let anchor =
                 LogicalVector::new(bounds_size.x * 0.5, bounds_size.y * 0.5);
             self.zoom_image(anchor, self.zoom_vel * dt_sec);
@@ -341,17 +498,62 @@ impl PictureWidgetData {
         }
     }
 
+    /// Shows the images around the current one in the filmstrip. Most of
+    /// them are already in the image cache because of the prefetching.
+    fn update_filmstrip(&mut self, curr_index: usize, dir_len: usize) {
+        let radius = self.bottom_bar.filmstrip.radius();
+        let key = FilmstripKey {
+            curr_index,
+            dir_len,
+            radius,
+            cache_version: self.playback_manager.image_cache_version(),
+        };
+        if self.filmstrip_key == Some(key) {
+            return;
+        }
+        self.filmstrip_key = Some(key);
+        let first = curr_index.saturating_sub(radius);
+        let last = (curr_index + radius).min(dir_len.saturating_sub(1));
+        let items = (first..=last)
+            .filter_map(|index| {
+                let (path, texture) =
+                    self.playback_manager.image_at_index(index)?;
+                Some(FilmstripItem {
+                    index,
+                    path,
+                    texture,
+                })
+            })
+            .collect();
+        self.bottom_bar.filmstrip.set_items(curr_index, items);
+    }
+
     fn set_window_title_filename(
         &self,
         window: &Window,
         playback_state: PlaybackState,
         file_path: &Option<PathBuf>,
     ) {
-        let mut title = match file_path {
-            Some(file_path) => self.format_file_path(file_path),
-            None => self.format_file_path("[ none ]"),
+        let relative_path = file_path.as_ref().and_then(|file_path| {
+            let root = self.playback_manager.recursive_root()?;
+            file_path.strip_prefix(root).ok()
+        });
+        let mut title = match (relative_path, file_path) {
+            (_, Some(file_path)) if is_stdin_image(file_path) => {
+                self.format_file_path("[ stdin ]")
+            }
+            // In recursive mode the subfolders tell the images apart
+            (Some(relative_path), _) => {
+                relative_path.to_string_lossy().into_owned()
+            }
+            (None, Some(file_path)) => self.format_file_path(file_path),
+            (None, None) => self.format_file_path("[ none ]"),
         };
 
+        if let Some(sorting) = self.playback_manager.sort_order().description()
+        {
+            title.push_str(&format!(" : {sorting}"));
+        }
         let playback = match playback_state {
             PlaybackState::Forward => " : Playing",
             PlaybackState::Present => " : Presenting",
@@ -359,6 +561,27 @@ impl PictureWidgetData {
             PlaybackState::Paused => "",
         };
         title.push_str(playback);
+        if self.playback_manager.follow_newest() {
+            title.push_str(" : Following");
+        }
+        if self.crop_mode || self.selection.is_some() {
+            title.push_str(" : Crop");
+            if let Some(rect) = self.selected_rect() {
+                title.push_str(&format!(" {} × {}", rect.w, rect.h));
+            }
+            if let Some((w, h)) = self.crop_aspect() {
+                title.push_str(&format!(" ({w}:{h})"));
+            }
+        }
+        if let Some(compare) = &self.compare {
+            let right = match compare.playback_manager.shown_file_path() {
+                Some(path) => self.format_file_path(path),
+                None => self.format_file_path("[ none ]"),
+            };
+            let target = compare.target.name();
+            title
+                .push_str(&format!(" : Compare with {right}, moving {target}"));
+        }
         window.set_title(title);
     }
 
@@ -409,17 +632,611 @@ impl PictureWidgetData {
         self.render_validity.invalidate();
     }
 
-    pub fn set_img_size_to_fit(&mut self, stretch: bool) {
-        self.scaling = if stretch {
-            ScalingMode::FitStretch
-        } else {
-            ScalingMode::FitMin
-        };
+    pub fn set_img_size_to_fit(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+        self.scroll_to_start = true;
         self.config.lock().unwrap().set_scaling(self.scaling);
         self.update_scaling_buttons();
         self.render_validity.invalidate();
     }
 
+    pub fn toggle_view_lock(&mut self) {
+        self.view_locked = !self.view_locked;
+        self.bottom_bar.set_view_locked(self.view_locked);
+    }
+
+    /// Describes the current view of `texture` independently of the panel size.
+    fn view_state(&self, texture: &AnimationFrameTexture) -> ViewState {
+        let (w, h) = self.shown_dimensions(texture);
+        let texel_size = self.img_texel_size / self.dpi_scale;
+        let offset = (self.view_bounds().size * 0.5 - self.img_pos).vec;
+        ViewState {
+            img_texel_size: self.img_texel_size,
+            center: [
+                offset.x / (w as f32 * texel_size),
+                offset.y / (h as f32 * texel_size),
+            ],
+            orientation: self.user_orientation,
+        }
+    }
+
+    fn apply_view_state(
+        &mut self,
+        texture: &AnimationFrameTexture,
+        view: ViewState,
+    ) {
+        self.user_orientation = view.orientation;
+        let (w, h) = self.shown_dimensions(texture);
+        let texel_size = view.img_texel_size / self.dpi_scale;
+        let center = (self.view_bounds().size * 0.5).vec;
+        self.img_texel_size = view.img_texel_size;
+        self.img_pos = LogicalVector::new(
+            center.x - view.center[0] * w as f32 * texel_size,
+            center.y - view.center[1] * h as f32 * texel_size,
+        );
+        self.scaling = ScalingMode::Fixed;
+        self.update_scaling_buttons();
+    }
+
+    /// Stores the view of the image at `path` so that it can be restored when
+    /// the image is shown again. Only views that were changed by the user
+    /// are stored.
+    fn remember_view(&mut self, path: &Path, texture: &AnimationFrameTexture) {
+        if self.scaling == ScalingMode::Fixed
+            || self.user_orientation != Orientation::Deg0
+        {
+            let view = self.view_state(texture);
+            self.view_memory.insert(path.to_owned(), view);
+        } else {
+            self.view_memory.remove(path);
+        }
+    }
+
+    /// Called when the shown image changes. Carries over the view of the
+    /// previous image when the view is locked, otherwise restores the view
+    /// that the new image was last shown with.
+    fn switch_view(
+        &mut self,
+        prev: (&Option<PathBuf>, &Option<AnimationFrameTexture>),
+        new: (&Option<PathBuf>, &Option<AnimationFrameTexture>),
+    ) {
+        // The selection doesn't apply to other images
+        self.selection = None;
+        self.selecting = false;
+        let prev_view = prev.1.as_ref().map(|tex| self.view_state(tex));
+        if let (Some(path), Some(texture)) = prev {
+            self.remember_view(path, texture);
+        }
+        let new_texture = match new.1 {
+            Some(texture) => texture,
+            None => return,
+        };
+        if self.view_locked {
+            if let Some(view) = prev_view {
+                self.apply_view_state(new_texture, view);
+            }
+            self.view_from_memory = false;
+            return;
+        }
+        let remembered = new.0.as_ref().and_then(|p| self.view_memory.get(p));
+        if let Some(view) = remembered {
+            self.apply_view_state(new_texture, view);
+            self.view_from_memory = true;
+        } else {
+            self.user_orientation = Orientation::Deg0;
+            if self.view_from_memory {
+                // Don't let the remembered view leak onto the following images
+                self.scaling = self.config.lock().unwrap().scaling();
+                self.update_scaling_buttons();
+                self.view_from_memory = false;
+            }
+        }
+    }
+
+    /// The orientation that the current image is shown with
+    fn shown_orientation(
+        &self,
+        texture: &AnimationFrameTexture,
+    ) -> Orientation {
+        texture.orientation.then(self.user_orientation)
+    }
+
+    /// The dimensions of `texture` after applying the shown orientation
+    fn shown_dimensions(&self, texture: &AnimationFrameTexture) -> (u32, u32) {
+        if self.shown_orientation(texture).swaps_dimensions() {
+            (texture.h, texture.w)
+        } else {
+            (texture.w, texture.h)
+        }
+    }
+
+    /// Rotates or flips the shown image by `change` on top of its current orientation.
+    pub fn reorient(&mut self, change: Orientation) {
+        self.user_orientation = self.user_orientation.then(change);
+        self.selection = None;
+        self.selecting = false;
+        self.render_validity.invalidate();
+    }
+
+    pub fn toggle_crop_mode(&mut self) {
+        self.set_crop_mode(!self.crop_mode);
+    }
+
+    fn set_crop_mode(&mut self, crop_mode: bool) {
+        self.crop_mode = crop_mode;
+        if !crop_mode {
+            self.selection = None;
+            self.selecting = false;
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Switches to the next aspect ratio that the selection is constrained to.
+    pub fn cycle_crop_aspect(&mut self) {
+        self.crop_aspect = (self.crop_aspect + 1) % ASPECT_RATIOS.len();
+        self.render_validity.invalidate();
+    }
+
+    fn crop_aspect(&self) -> Option<(u32, u32)> {
+        ASPECT_RATIOS[self.crop_aspect]
+    }
+
+    fn selected_rect(&self) -> Option<CropRect> {
+        self.selection.and_then(|s| s.rect(self.crop_aspect()))
+    }
+
+    /// Everything that the user changed about the shown image
+    fn edits(&self) -> ImageEdits {
+        ImageEdits {
+            orientation: self.user_orientation,
+            crop: self.selected_rect(),
+        }
+    }
+
+    /// The area of the panel that the image is placed in. This is the whole
+    /// panel unless the image is shown next to another one.
+    fn view_bounds(&self) -> LogicalRect {
+        match &self.compare {
+            Some(compare) => compare.views(self.drawn_bounds).0,
+            None => self.drawn_bounds,
+        }
+    }
+
+    /// Maps a point over the right image of the side-by-side comparison to
+    /// the same point over the left image. Other points are returned as is.
+    fn to_view_point(&self, point: LogicalVector) -> LogicalVector {
+        if let Some(compare) = &self.compare {
+            let (left, right) = compare.views(self.drawn_bounds);
+            if compare.layout == CompareLayout::SideBySide
+                && point.vec.x >= right.left()
+            {
+                return point - (right.pos - left.pos);
+            }
+        }
+        point
+    }
+
+    /// Shows the next image of the folder next to the current one, with
+    /// both of them moving together when navigating. Leaves the compare
+    /// mode if it's active.
+    pub fn toggle_compare(&mut self) {
+        if self.compare.is_some() {
+            self.close_compare();
+            return;
+        }
+        let path = self
+            .playback_manager
+            .file_path_at_offset(1)
+            .or_else(|| self.playback_manager.shown_file_path().clone());
+        if let Some(path) = path {
+            self.compare = Some(Compare::new(
+                path,
+                CompareTarget::Both,
+                self.config.lock().unwrap().compare_anchor(),
+            ));
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Compares the current image with the images that are navigated to
+    /// afterwards.
+    pub fn pin_compare_image(&mut self) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        match &mut self.compare {
+            Some(compare) => {
+                compare
+                    .playback_manager
+                    .request_load(LoadRequest::FilePath(path));
+                compare.target = CompareTarget::Left;
+            }
+            None => {
+                self.compare = Some(Compare::new(
+                    path,
+                    CompareTarget::Left,
+                    self.config.lock().unwrap().compare_anchor(),
+                ));
+            }
+        }
+        self.render_validity.invalidate();
+    }
+
+    fn close_compare(&mut self) {
+        self.compare = None;
+        self.render_validity.invalidate();
+    }
+
+    pub fn cycle_compare_target(&mut self) {
+        if let Some(compare) = &mut self.compare {
+            compare.target = compare.target.next();
+        }
+    }
+
+    pub fn cycle_compare_layout(&mut self) {
+        if let Some(compare) = &mut self.compare {
+            compare.layout = compare.layout.next();
+            self.render_validity.invalidate();
+        }
+    }
+
+    pub fn toggle_compare_anchor(&mut self) {
+        if let Some(compare) = &mut self.compare {
+            compare.anchor = compare.anchor.toggle();
+            self.render_validity.invalidate();
+        }
+    }
+
+    fn dragging_split(&self) -> bool {
+        self.compare.as_ref().is_some_and(|c| c.dragging_split)
+    }
+
+    /// Sends `request` to the images that are moved by navigation, which is
+    /// the image of the widget unless two images are compared.
+    fn navigate(&mut self, request: LoadRequest) {
+        match &mut self.compare {
+            Some(compare) => {
+                if compare.target.moves_right() {
+                    compare.playback_manager.request_load(request.clone());
+                }
+                if compare.target.moves_left() {
+                    self.playback_manager.request_load(request);
+                }
+            }
+            None => self.playback_manager.request_load(request),
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Opens the image at the index that `index` returns for the number of
+    /// images in the folder, like `navigate` opens the requested image.
+    /// Nothing happens while the folder is being filtered.
+    fn navigate_to_index(&mut self, index: impl Fn(usize) -> usize) {
+        let load = |playback_manager: &mut PlaybackManager| {
+            if let Some(count @ 1..) = playback_manager.current_dir_len() {
+                let index = index(count).min(count - 1);
+                playback_manager.request_load(LoadRequest::LoadAtIndex(index));
+            }
+        };
+        match &mut self.compare {
+            Some(compare) => {
+                if compare.target.moves_right() {
+                    load(&mut compare.playback_manager);
+                }
+                if compare.target.moves_left() {
+                    load(&mut self.playback_manager);
+                }
+            }
+            None => load(&mut self.playback_manager),
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Goes to the image whose number was entered into the prompt
+    fn submit_goto(&mut self) {
+        let text = self.prompt.close();
+        if text.is_empty() {
+            return;
+        }
+        match text.parse::<usize>() {
+            Ok(number @ 1..) => self.navigate_to_index(|_| number - 1),
+            _ => self.notification.show("There's no image with that number"),
+        }
+    }
+
+    /// Opens the next or the previous folder like `navigate` opens images
+    fn navigate_folder(&mut self, forward: bool) {
+        match &mut self.compare {
+            Some(compare) => {
+                if compare.target.moves_right() {
+                    compare.playback_manager.open_sibling_folder(forward);
+                }
+                if compare.target.moves_left() {
+                    self.playback_manager.open_sibling_folder(forward);
+                }
+            }
+            None => self.playback_manager.open_sibling_folder(forward),
+        }
+        self.render_validity.invalidate();
+    }
+
+    /// Lets the playback manager of the right image of the comparison load
+    /// and play its image.
+    fn update_compare_image(&mut self, window: &Window) -> NextUpdate {
+        let compare = match &mut self.compare {
+            Some(compare) => compare,
+            None => return NextUpdate::Latest,
+        };
+        let prev_texture = compare.playback_manager.image_texture();
+        let next_update = compare.playback_manager.update_image(window);
+        let new_texture = compare.playback_manager.image_texture();
+        let changed = match (prev_texture, new_texture) {
+            (Some(prev), Some(new)) => {
+                !Rc::ptr_eq(&prev.tex_grid, &new.tex_grid)
+            }
+            (prev, new) => prev.is_none() != new.is_none(),
+        };
+        if changed {
+            self.render_validity.invalidate();
+        }
+        next_update
+    }
+
+    /// The position of the top left corner of the shown image in window
+    /// coordinates, and the size of a texel in logical pixels.
+    fn image_placement(
+        &self,
+        texture: &AnimationFrameTexture,
+    ) -> (LogicalVector, f32) {
+        let (w, h) = self.shown_dimensions(texture);
+        let texel_size = self.img_texel_size / self.dpi_scale;
+        let half_size =
+            LogicalVector::new(w as f32, h as f32) * texel_size * 0.5;
+        (
+            self.view_bounds().pos + self.img_pos - half_size,
+            texel_size,
+        )
+    }
+
+    /// The horizontal offset of the image in logical pixels while it's nudged
+    fn bump_offset(&self) -> f32 {
+        let Some((start, end)) = self.bump else {
+            return 0.0;
+        };
+        let progress = start.elapsed().as_secs_f32() / BUMP_TIME.as_secs_f32();
+        if progress >= 1.0 {
+            return 0.0;
+        }
+        // Towards the end that was reached and back
+        let direction = match end {
+            FolderEnd::First => 1.0,
+            FolderEnd::Last => -1.0,
+        };
+        direction * BUMP_DISTANCE * (progress * std::f32::consts::PI).sin()
+    }
+
+    /// Converts a point in window coordinates into texel coordinates of the shown image.
+    fn window_to_image(
+        &self,
+        texture: &AnimationFrameTexture,
+        point: LogicalVector,
+    ) -> Vector2<f32> {
+        let (corner, texel_size) = self.image_placement(texture);
+        (point - corner).vec / texel_size
+    }
+
+    fn start_selection(&mut self, point: LogicalVector) {
+        if let Some(texture) = self.get_texture() {
+            let (w, h) = self.shown_dimensions(&texture);
+            let point = self.window_to_image(&texture, point);
+            self.selection = Some(CropSelection::new(point, w, h));
+            self.selecting = true;
+            self.render_validity.invalidate();
+        }
+    }
+
+    fn drag_selection(&mut self, point: LogicalVector) {
+        if let Some(texture) = self.get_texture() {
+            let point = self.window_to_image(&texture, point);
+            if let Some(selection) = &mut self.selection {
+                selection.drag_to(point);
+            }
+            self.render_validity.invalidate();
+        }
+    }
+
+    /// Saves the selected region of the current image into a new file.
+    pub fn save_selection(&mut self) {
+        if self.selected_rect().is_some() {
+            self.export(self.edits(), Some("crop"));
+        }
+    }
+
+    /// Saves the current image as it's shown into a new file, in the format
+    /// and with the settings of the `[export]` config section.
+    pub fn save_as(&mut self) {
+        let edits = ImageEdits {
+            crop: None,
+            ..self.edits()
+        };
+        self.export(edits, None);
+    }
+
+    /// Saves the current image on a separate thread, the result is shown
+    /// by `receive_exports` when it's done.
+    fn export(&mut self, edits: ImageEdits, suffix: Option<&'static str>) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let settings =
+            ExportSettings::from_config(&self.config.lock().unwrap());
+        self.save_in_background("Saving...", move || {
+            let message =
+                match export::export_image(&path, edits, suffix, &settings) {
+                    Ok(target) => {
+                        let name = target.file_name().unwrap_or_default();
+                        format!("Saved {}", name.to_string_lossy())
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Could not save {path:?} as {:?}: {e}",
+                            settings.format
+                        );
+                        "Could not save the image".to_owned()
+                    }
+                };
+            FinishedSave {
+                message,
+                orientation: None,
+            }
+        });
+    }
+
+    /// Runs `save` on its own thread, since encoding large images would
+    /// freeze the window
+    fn save_in_background(
+        &mut self,
+        message: &str,
+        save: impl FnOnce() -> FinishedSave + Send + 'static,
+    ) {
+        let sender = self.export_sender.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(save());
+        });
+        self.pending_exports += 1;
+        self.notification.show(message);
+    }
+
+    /// Shows the results of the finished saves. Returns when to check
+    /// again.
+    fn receive_exports(&mut self, now: Instant) -> NextUpdate {
+        while let Ok(finished) = self.export_results.try_recv() {
+            self.pending_exports -= 1;
+            self.notification.show(&finished.message);
+            if let Some((path, orientation)) = finished.orientation {
+                self.orientation_saved(&path, orientation);
+            }
+        }
+        if self.pending_exports > 0 {
+            NextUpdate::WaitUntil(now + Duration::from_millis(100))
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    /// Writes the shown orientation of the current image into the file so
+    /// that it's shown the same way next time.
+    pub fn save_orientation(&mut self) {
+        let path = match self.playback_manager.shown_file_path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let texture = match self.get_texture() {
+            Some(texture) => texture,
+            None => return,
+        };
+        if self.user_orientation == Orientation::Deg0 {
+            return;
+        }
+        let orientation = self.shown_orientation(&texture);
+        let user_orientation = self.user_orientation;
+        self.save_in_background("Saving the orientation...", move || {
+            match orientation_saver::save_orientation(&path, orientation) {
+                Ok(()) => FinishedSave {
+                    message: "Saved the orientation".to_owned(),
+                    orientation: Some((path, user_orientation)),
+                },
+                Err(e) => {
+                    eprintln!(
+                        "Could not save the orientation of {path:?}: {e}"
+                    );
+                    FinishedSave {
+                        message: "Could not save the orientation".to_owned(),
+                        orientation: None,
+                    }
+                }
+            }
+        });
+    }
+
+    /// Reloads the image at `path` after `user_orientation` was written into
+    /// it. The user's rotation is dropped where it's still the saved one,
+    /// since the reloaded image already has it.
+    fn orientation_saved(
+        &mut self,
+        path: &Path,
+        user_orientation: Orientation,
+    ) {
+        let shown =
+            self.playback_manager.shown_file_path().as_deref() == Some(path);
+        if shown && self.user_orientation == user_orientation {
+            self.user_orientation = Orientation::Deg0;
+        }
+        if let Some(view) = self.view_memory.get(path) {
+            if view.orientation == user_orientation {
+                let view = ViewState {
+                    orientation: Orientation::Deg0,
+                    ..view
+                };
+                self.view_memory.insert(path.to_owned(), view);
+            }
+        }
+        if let Err(e) = self.playback_manager.update_directory() {
+            eprintln!("Error while updating directory {:?}", e);
+        }
+        if shown {
+            self.playback_manager
+                .request_load(LoadRequest::FilePath(path.to_owned()));
+        }
+        self.render_validity.invalidate();
+    }
+
+    pub fn toggle_recursive(&mut self) {
+        let max_depth = match self.playback_manager.recursive_root() {
+            Some(_) => None,
+            None => Some(self.recursion_depth),
+        };
+        if let Err(e) = self.playback_manager.set_max_depth(max_depth) {
+            eprintln!("Error while listing the folder {:?}", e);
+        }
+        self.render_validity.invalidate();
+    }
+
+    pub fn toggle_follow_newest(&mut self) {
+        let follow = !self.playback_manager.follow_newest();
+        self.playback_manager.set_follow_newest(follow);
+        self.render_validity.invalidate();
+    }
+
+    /// Orders the images by the next key of `SortKey`
+    pub fn cycle_sort_key(&mut self) {
+        let mut order = self.playback_manager.sort_order();
+        order.key = order.key.next();
+        if order.key == SortKey::Random {
+            // A new shuffle every time
+            order.seed = rand::random();
+        }
+        self.set_sort_order(order);
+    }
+
+    pub fn toggle_sort_descending(&mut self) {
+        let mut order = self.playback_manager.sort_order();
+        order.descending = !order.descending;
+        self.set_sort_order(order);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.playback_manager.set_sort_order(order);
+        self.config.lock().unwrap().set_sort(
+            order.key,
+            order.descending,
+            order.seed,
+        );
+        self.render_validity.invalidate();
+    }
+
     pub fn toggle_antialias(&mut self) {
         let aa = match self.antialiasing {
             Antialias::Auto
@@ -448,7 +1265,7 @@ impl PictureWidgetData {
     fn apply_img_bounds(&mut self, dpi_scale: f32) {
         if let Some(texture) = self.get_texture() {
             let (img_phys_w, img_phys_h) = {
-                let (w, h) = texture.oriented_dimensions();
+                let (w, h) = self.shown_dimensions(&texture);
                 (
                     w as f32 * self.img_texel_size,
                     h as f32 * self.img_texel_size,
@@ -457,7 +1274,7 @@ impl PictureWidgetData {
             let img_w = img_phys_w / dpi_scale;
             let img_h = img_phys_h / dpi_scale;
 
-            let widget_size = self.drawn_bounds.size.vec;
+            let widget_size = self.view_bounds().size.vec;
             let img_pos = self.img_pos.vec;
 
             if img_pos.x < -img_w / 2.0 {
@@ -489,12 +1306,16 @@ pub struct PictureWidget {
 impl PictureWidget {
     add_common_widget_functions!(data);
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         display: &Display,
         window: &Rc<Window>,
         bottom_bar: Rc<BottomBar>,
+        gallery: Rc<Gallery>,
         left_to_pan_hint: Rc<HelpScreen>,
         copy_notifications: CopyNotifications,
+        notification: Rc<Notification>,
+        prompt: Rc<Prompt>,
         config: Arc<Mutex<Configuration>>,
     ) -> PictureWidget {
         let program = program!(display,
@@ -508,10 +1329,37 @@ impl PictureWidget {
             },
         )
         .unwrap();
+        let overlay_program = program!(display,
+            140 => {
+                vertex: shaders::OVERLAY_VERTEX_140,
+                fragment: shaders::OVERLAY_FRAGMENT_140
+            },
+            110 => {
+                vertex: shaders::OVERLAY_VERTEX_110,
+                fragment: shaders::OVERLAY_FRAGMENT_110
+            },
+        )
+        .unwrap();
 
         let scaling = config.lock().unwrap().scaling();
         let antialiasing = config.lock().unwrap().antialiasing();
+        let end_of_folder = config.lock().unwrap().end_of_folder();
+        let loop_slideshow = config.lock().unwrap().loop_slideshow();
+        let sort_order = {
+            let config = config.lock().unwrap();
+            SortOrder {
+                key: config.sort_key(),
+                descending: config.sort_descending(),
+                seed: config.shuffle_seed(),
+            }
+        };
+        let view_memory = if config.lock().unwrap().persist_views() {
+            ViewMemory::load()
+        } else {
+            ViewMemory::new()
+        };
 
+        let (export_sender, export_results) = mpsc::channel();
         let mut data = PictureWidgetData {
             placement: Default::default(),
             drawn_bounds: Default::default(),
@@ -526,10 +1374,22 @@ impl PictureWidget {
             render_validity: Default::default(),
 
             program,
+            overlay_program,
             bright_shade: 0.95,
             img_texel_size: 1.0,
             scaling,
             img_pos: Default::default(),
+            scroll_to_start: true,
+            view_locked: false,
+            view_memory,
+            view_from_memory: false,
+            dpi_scale: 1.0,
+            user_orientation: Orientation::Deg0,
+            crop_mode: false,
+            selection: None,
+            selecting: false,
+            crop_aspect: 0,
+            compare: None,
             antialiasing,
             hor_pan_input: MovementDir::None,
             ver_pan_input: MovementDir::None,
@@ -545,10 +1405,24 @@ impl PictureWidget {
             first_draw: true,
             next_update: NextUpdate::Latest,
             bottom_bar,
+            gallery,
+            gallery_requested: false,
+            recursion_depth: 0,
             left_to_pan_hint,
             copy_notifications,
+            filmstrip_key: None,
+            notification,
+            export_sender,
+            export_results,
+            pending_exports: 0,
+            prompt,
+            count_prefix: None,
+            bump: None,
             window: Rc::downgrade(window),
         };
+        data.playback_manager.set_sort_order(sort_order);
+        data.playback_manager.set_end_of_folder(end_of_folder);
+        data.playback_manager.set_loop_slideshow(loop_slideshow);
         data.update_scaling_buttons();
         PictureWidget {
             data: RefCell::new(data),
@@ -566,9 +1440,29 @@ impl PictureWidget {
         borrowed.set_img_size_to_orig();
     }
 
-    pub fn set_img_size_to_fit(&self, stretch: bool) {
+    pub fn set_img_size_to_fit(&self, scaling: ScalingMode) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.set_img_size_to_fit(scaling);
+    }
+
+    pub fn toggle_view_lock(&self) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.toggle_view_lock();
+    }
+
+    /// Writes the remembered views to disk if that is enabled in the config.
+    pub fn save_view_memory(&self) {
         let mut borrowed = self.data.borrow_mut();
-        borrowed.set_img_size_to_fit(stretch);
+        if !borrowed.config.lock().unwrap().persist_views() {
+            return;
+        }
+        let shown = borrowed.playback_manager.shown_file_path().clone();
+        if let (Some(path), Some(texture)) = (shown, borrowed.get_texture()) {
+            borrowed.remember_view(&path, &texture);
+        }
+        if let Err(e) = borrowed.view_memory.save() {
+            eprintln!("{e}");
+        }
     }
 
     pub fn jump_to_index(&self, index: u32) {
@@ -579,6 +1473,40 @@ impl PictureWidget {
         borrowed.render_validity.invalidate();
     }
 
+    /// The path of the image at `index` in the folder, without loading it
+    pub fn image_path_at_index(&self, index: u32) -> Option<PathBuf> {
+        let mut borrowed = self.data.borrow_mut();
+        let (path, _) =
+            borrowed.playback_manager.image_at_index(index as usize)?;
+        Some(path)
+    }
+
+    /// Turns recursive mode on or off. `max_depth` is the number of subfolder
+    /// levels listed in recursive mode, also when it's turned on later.
+    pub fn set_recursive(&self, recursive: bool, max_depth: u32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.recursion_depth = max_depth;
+        let max_depth = recursive.then_some(max_depth);
+        if let Err(e) = borrowed.playback_manager.set_max_depth(max_depth) {
+            eprintln!("Error while listing the folder {:?}", e);
+        }
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn set_filter(&self, filter: ImageFilter) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.playback_manager.set_filter(filter);
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Switches to every image that is added to the folder, until another
+    /// image is opened
+    pub fn set_follow_newest(&self, follow: bool) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.playback_manager.set_follow_newest(follow);
+        borrowed.render_validity.invalidate();
+    }
+
     pub fn jump_to_path<P: Into<PathBuf>>(&self, path: P) {
         let mut borrowed = self.data.borrow_mut();
         borrowed
@@ -587,6 +1515,63 @@ impl PictureWidget {
         borrowed.render_validity.invalidate();
     }
 
+    /// Shows the images of the files and folders of `entries` one after the
+    /// other, as if they were the images of a single folder
+    pub fn open_list(&self, entries: Vec<PathBuf>) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.playback_manager.open_list(entries);
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Shows the widget again after the gallery was closed, with the image
+    /// that was opened from the gallery if any.
+    pub fn show_from_gallery(&self, path: Option<PathBuf>) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = true;
+        if let Some(path) = path {
+            borrowed
+                .playback_manager
+                .request_load(LoadRequest::FilePath(path));
+        }
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Passes the typed digits on to the go-to prompt while it's open.
+    /// Returns true if the event was used up.
+    fn handle_prompt_event(&self, event: &Event) -> bool {
+        let mut borrowed = self.data.borrow_mut();
+        if !borrowed.prompt.is_open() {
+            return false;
+        }
+        match event.kind {
+            EventKind::ReceivedCharacter(ch) => {
+                if ch.is_ascii_digit() {
+                    borrowed.prompt.push(ch);
+                }
+                true
+            }
+            EventKind::KeyInput { input } => {
+                if input.state != ElementState::Pressed {
+                    return false;
+                }
+                match input.virtual_keycode {
+                    Some(
+                        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter,
+                    ) => {
+                        borrowed.submit_goto();
+                    }
+                    Some(VirtualKeyCode::Escape) => {
+                        borrowed.prompt.close();
+                    }
+                    Some(VirtualKeyCode::Back) => borrowed.prompt.pop(),
+                    _ => (),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
         let mut borrowed = self.data.borrow_mut();
         macro_rules! triggered {
@@ -599,6 +1584,33 @@ impl PictureWidget {
                 )
             };
         }
+        if let Some(digit) = count_digit(input_key, modifiers) {
+            let pending = borrowed.count_prefix;
+            // A digit that is bound to an action only adds to a count that
+            // was already started
+            let starts_count = digit != 0
+                && !key_is_bound(&borrowed.config, input_key, modifiers);
+            if pending.is_some() || starts_count {
+                let count = pending
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit)
+                    .min(MAX_COUNT);
+                borrowed.count_prefix = Some(count);
+                borrowed.notification.show(&count.to_string());
+                return;
+            }
+        }
+        // Holding a modifier for the navigation keeps the count
+        let count = if is_modifier_key(input_key) {
+            borrowed.count_prefix
+        } else {
+            borrowed.count_prefix.take()
+        };
+        if count.is_some() && triggered!(ESCAPE_NAME) {
+            // Only cancels the count
+            return;
+        }
         if triggered!(TOGGLE_FULLSCREEN_NAME) {
             if let Some(window) = borrowed.window.upgrade() {
                 let fullscreen = !window.fullscreen();
@@ -607,7 +1619,11 @@ impl PictureWidget {
             }
         }
         if triggered!(ESCAPE_NAME) {
-            if let Some(window) = borrowed.window.upgrade() {
+            if borrowed.crop_mode || borrowed.selection.is_some() {
+                borrowed.set_crop_mode(false);
+            } else if borrowed.compare.is_some() {
+                borrowed.close_compare();
+            } else if let Some(window) = borrowed.window.upgrade() {
                 if window.fullscreen() {
                     window.set_fullscreen(false);
                     borrowed.bottom_bar.set_visible(true);
@@ -625,26 +1641,114 @@ impl PictureWidget {
             }
         }
         if triggered!(IMG_PREV_NAME) {
-            borrowed
-                .playback_manager
-                .request_load(LoadRequest::LoadPrevious);
-            borrowed.render_validity.invalidate();
+            borrowed.navigate(match count {
+                Some(count) => LoadRequest::Jump(-(count as i32)),
+                None => LoadRequest::LoadPrevious,
+            });
         }
         if triggered!(IMG_NEXT_NAME) {
-            borrowed
-                .playback_manager
-                .request_load(LoadRequest::LoadNext);
-            borrowed.render_validity.invalidate();
+            borrowed.navigate(match count {
+                Some(count) => LoadRequest::Jump(count as i32),
+                None => LoadRequest::LoadNext,
+            });
+        }
+        if triggered!(IMG_FIRST_NAME) {
+            borrowed.navigate_to_index(|_| 0);
+        }
+        if triggered!(IMG_LAST_NAME) {
+            borrowed.navigate_to_index(|count| count - 1);
+        }
+        if triggered!(IMG_GOTO_NAME) {
+            match count {
+                Some(count) => {
+                    borrowed.navigate_to_index(|_| count as usize - 1)
+                }
+                None => borrowed.prompt.open("Go to image: "),
+            }
+        }
+        if triggered!(FOLDER_PREV_NAME) {
+            borrowed.navigate_folder(false);
+        }
+        if triggered!(FOLDER_NEXT_NAME) {
+            borrowed.navigate_folder(true);
         }
         if triggered!(IMG_FIT_NAME) {
-            borrowed.set_img_size_to_fit(true);
+            borrowed.set_img_size_to_fit(ScalingMode::FitStretch);
         }
         if triggered!(IMG_FIT_BEST_NAME) {
-            borrowed.set_img_size_to_fit(false);
+            borrowed.set_img_size_to_fit(ScalingMode::FitMin);
+        }
+        if triggered!(IMG_FIT_WIDTH_NAME) {
+            borrowed.set_img_size_to_fit(ScalingMode::FitWidth);
+        }
+        if triggered!(IMG_FIT_HEIGHT_NAME) {
+            borrowed.set_img_size_to_fit(ScalingMode::FitHeight);
         }
         if triggered!(IMG_ORIG_NAME) {
             borrowed.set_img_size_to_orig();
         }
+        if triggered!(TOGGLE_LOCK_VIEW_NAME) {
+            borrowed.toggle_view_lock();
+        }
+        if triggered!(IMG_ROTATE_CW_NAME) {
+            borrowed.reorient(Orientation::Deg270);
+        }
+        if triggered!(IMG_ROTATE_CCW_NAME) {
+            borrowed.reorient(Orientation::Deg90);
+        }
+        if triggered!(IMG_FLIP_HOR_NAME) {
+            borrowed.reorient(Orientation::Deg0HorFlip);
+        }
+        if triggered!(IMG_FLIP_VER_NAME) {
+            borrowed.reorient(Orientation::Deg180HorFlip);
+        }
+        if triggered!(IMG_SAVE_ORIENTATION_NAME) {
+            borrowed.save_orientation();
+        }
+        if triggered!(TOGGLE_CROP_NAME) {
+            borrowed.toggle_crop_mode();
+        }
+        if triggered!(CROP_ASPECT_NAME) {
+            borrowed.cycle_crop_aspect();
+        }
+        if triggered!(CROP_SAVE_NAME) {
+            borrowed.save_selection();
+        }
+        if triggered!(SAVE_AS_NAME) {
+            borrowed.save_as();
+        }
+        if triggered!(TOGGLE_COMPARE_NAME) {
+            borrowed.toggle_compare();
+        }
+        if triggered!(COMPARE_PIN_NAME) {
+            borrowed.pin_compare_image();
+        }
+        if triggered!(COMPARE_TARGET_NAME) {
+            borrowed.cycle_compare_target();
+        }
+        if triggered!(COMPARE_LAYOUT_NAME) {
+            borrowed.cycle_compare_layout();
+        }
+        if triggered!(COMPARE_ANCHOR_NAME) {
+            borrowed.toggle_compare_anchor();
+        }
+        if triggered!(TOGGLE_GALLERY_NAME) {
+            // The gallery receives this event too, so it's only opened
+            // after the event has been handled
+            borrowed.gallery_requested = true;
+        }
+        if triggered!(TOGGLE_RECURSIVE_NAME) {
+            borrowed.toggle_recursive();
+        }
+        if triggered!(TOGGLE_FOLLOW_NAME) {
+            borrowed.toggle_follow_newest();
+        }
+        if triggered!(SORT_NEXT_NAME) {
+            borrowed.cycle_sort_key();
+        }
+        if triggered!(SORT_REVERSE_NAME) {
+            borrowed.toggle_sort_descending();
+        }
         if triggered!(TOGGLE_ANTIALIAS_NAME) {
             borrowed.toggle_antialias();
         }
@@ -670,7 +1774,9 @@ impl PictureWidget {
             borrowed.render_validity.invalidate();
         }
         if triggered!(IMG_DEL_NAME) {
-            if let Some(path) = borrowed.playback_manager.shown_file_path() {
+            let path = borrowed.playback_manager.shown_file_path();
+            // `-` could be the name of an unrelated file
+            if let Some(path) = path.as_ref().filter(|p| !is_stdin_image(p)) {
                 if let Err(e) = trash::delete(path) {
                     eprintln!(
                         "Error while moving file '{:?}' to trash: {:?}",
@@ -688,10 +1794,11 @@ impl PictureWidget {
                 borrowed.playback_manager.shown_file_path().clone()
             {
                 let request_started;
+                let edits = borrowed.edits();
                 if let Some(clipboard_handler) = &mut borrowed.clipboard_handler
                 {
                     request_started = true;
-                    clipboard_handler.request_copy(path);
+                    clipboard_handler.request_copy(path, edits);
                     borrowed.copy_notifications.set_started();
                 } else {
                     request_started = false;
@@ -728,9 +1835,6 @@ impl PictureWidget {
 impl Widget for PictureWidget {
     fn before_draw(&self, window: &Window) -> NextUpdate {
         let mut data = self.data.borrow_mut();
-        if !data.visible {
-            return NextUpdate::Latest;
-        }
         if data.first_draw {
             // Don't block on the main thread and
             // wait on the image to be loaded on the first draw,
@@ -741,8 +1845,41 @@ impl Widget for PictureWidget {
         }
         let now = Instant::now();
         let prev_texture = data.playback_manager.image_texture();
+        let prev_path = data.playback_manager.shown_file_path().clone();
         data.next_update = data.playback_manager.update_image(window);
+        let compare_next_update = data.update_compare_image(window);
+        data.next_update = data.next_update.aggregate(compare_next_update);
         let new_texture = data.playback_manager.image_texture();
+        let new_path = data.playback_manager.shown_file_path().clone();
+        if data.playback_manager.take_reloaded() {
+            data.notification.show("Reloaded");
+        }
+        if let Some(end) = data.playback_manager.take_reached_end() {
+            data.bump = Some((now, end));
+        }
+        if new_path != prev_path {
+            data.scroll_to_start = true;
+            data.switch_view(
+                (&prev_path, &prev_texture),
+                (&new_path, &new_texture),
+            );
+        }
+        // The images are received even while the gallery is shown, otherwise
+        // the loader threads would keep waiting for the requested image.
+        if !data.visible {
+            return data.next_update;
+        }
+        if std::mem::take(&mut data.gallery_requested) {
+            if let Some(paths) = data.playback_manager.image_paths() {
+                if !paths.is_empty() {
+                    let selected =
+                        data.playback_manager.current_file_index().unwrap_or(0);
+                    data.gallery.open(paths, selected);
+                    data.visible = false;
+                    return data.next_update;
+                }
+            }
+        }
         let curr_file_index = data.playback_manager.current_file_index();
         let curr_dir_len = data.playback_manager.current_dir_len();
         if let (Some(curr_file_index), Some(curr_dir_len)) =
@@ -751,6 +1888,15 @@ impl Widget for PictureWidget {
             data.bottom_bar
                 .slider
                 .set_steps(curr_dir_len as u32, curr_file_index as u32);
+            if data.bottom_bar.filmstrip.visible() {
+                data.update_filmstrip(curr_file_index, curr_dir_len);
+            }
+        } else if data.playback_manager.shown_file_path().is_some() {
+            // The folder is being filtered or sorted in the background
+            let next_update = now + Duration::from_millis(50);
+            data.next_update = data
+                .next_update
+                .aggregate(NextUpdate::WaitUntil(next_update));
         }
         //data.slider.set_step_bg(data.playback_manager.cached_from_dir());
         let playback_state = data.playback_manager.playback_state();
@@ -793,6 +1939,16 @@ impl Widget for PictureWidget {
             data.render_validity.invalidate();
             data.next_update = NextUpdate::Soonest;
         }
+        if let Some((start, _)) = data.bump {
+            if start.elapsed() >= BUMP_TIME {
+                data.bump = None;
+            } else {
+                data.next_update = NextUpdate::Soonest;
+            }
+            data.render_validity.invalidate();
+        }
+        let export_update = data.receive_exports(now);
+        data.next_update = data.next_update.aggregate(export_update);
         let next_copy_noti_update = data.copy_notifications.update();
         data.next_update = data.next_update.aggregate(next_copy_noti_update);
         data.next_update
@@ -809,16 +1965,95 @@ impl Widget for PictureWidget {
             if !data.visible {
                 return Ok(data.next_update);
             }
+            data.dpi_scale = context.dpi_scale_factor;
             data.update_image_transform(context.dpi_scale_factor);
             data.apply_camera_movement(context.dpi_scale_factor);
             texture = data.get_texture();
         }
-        if let Some(texture) = texture {
-            let data = self.data.borrow();
-            draw_tex_grid(data, target, context, texture);
+        let data = self.data.borrow();
+        let bounds = data.drawn_bounds;
+        let view = data.view_bounds();
+        let left_clip = match &data.compare {
+            Some(compare) => compare.clips(bounds).0,
+            None => view,
+        };
+        // The centers of the images in physical pixels relative to the view
+        let mut center = data.img_pos.vec * context.dpi_scale_factor;
+        center.x += data.bump_offset() * context.dpi_scale_factor;
+        let right = data.compare.as_ref().and_then(|compare| {
+            let right_texture = compare.playback_manager.image_texture()?;
+            let right_center = match (compare.anchor, &texture) {
+                (CompareAnchor::TopLeft, Some(texture)) => {
+                    let left_size = drawn_size(&data, context, texture);
+                    let right_size = drawn_size(&data, context, &right_texture);
+                    center + (right_size - left_size) * 0.5
+                }
+                _ => center,
+            };
+            Some((right_texture, right_center))
+        });
+        let overlay_mode =
+            data.compare.as_ref().and_then(|c| c.layout.overlay_mode());
+        match (overlay_mode, &texture, &right) {
+            // The overlay draws both images, including the parts that the
+            // other one doesn't cover
+            (
+                Some(mode),
+                Some(texture),
+                Some((right_texture, right_center)),
+            ) => {
+                draw_overlay(
+                    &data,
+                    target,
+                    context,
+                    mode,
+                    (texture, center),
+                    (right_texture, *right_center),
+                    view,
+                );
+            }
+            _ => {
+                if let Some(texture) = &texture {
+                    draw_tex_grid(
+                        &data, target, context, texture, center, view,
+                        left_clip,
+                    );
+                }
+                if let (Some(compare), Some((right_texture, right_center))) =
+                    (&data.compare, &right)
+                {
+                    let (_, right_view) = compare.views(bounds);
+                    let (_, right_clip) = compare.clips(bounds);
+                    draw_tex_grid(
+                        &data,
+                        target,
+                        context,
+                        right_texture,
+                        *right_center,
+                        right_view,
+                        right_clip,
+                    );
+                }
+            }
+        }
+        if let Some(compare) = &data.compare {
+            if compare.layout.overlay_mode().is_none() {
+                let divider_x = compare.divider_x(bounds);
+                compare::draw_divider(target, context, bounds, divider_x);
+            }
+        }
+        if let (Some(texture), Some(rect)) = (&texture, data.selected_rect()) {
+            let (corner, texel_size) = data.image_placement(texture);
+            let selected = LogicalRect {
+                pos: corner
+                    + LogicalVector::new(rect.x as f32, rect.y as f32)
+                        * texel_size,
+                size: LogicalVector::new(rect.w as f32, rect.h as f32)
+                    * texel_size,
+            };
+            crop_selection::draw(target, context, left_clip, selected);
         }
-        let borrowed = self.data.borrow();
-        Ok(borrowed.next_update)
+        Ok(data.next_update)
     }
 
     fn layout(&self, available_space: LogicalRect) {
@@ -832,17 +2067,25 @@ impl Widget for PictureWidget {
         if !self.data.borrow().visible {
             return;
         }
+        if self.handle_prompt_event(event) {
+            return;
+        }
         match event.kind {
             EventKind::MouseMove => {
                 let mut borrowed = self.data.borrow_mut();
                 borrowed.hover =
                     borrowed.drawn_bounds.contains(event.cursor_pos);
-                if borrowed.panning {
-                    let delta = event.cursor_pos - borrowed.last_mouse_pos;
-                    borrowed.scaling = ScalingMode::Fixed;
-                    borrowed.update_scaling_buttons();
-                    borrowed.img_pos += delta;
+                if borrowed.dragging_split() {
+                    let bounds = borrowed.drawn_bounds;
+                    if let Some(compare) = &mut borrowed.compare {
+                        compare.drag_split(bounds, event.cursor_pos.vec.x);
+                    }
                     borrowed.render_validity.invalidate();
+                } else if borrowed.selecting {
+                    borrowed.drag_selection(event.cursor_pos);
+                } else if borrowed.panning {
+                    let delta = event.cursor_pos - borrowed.last_mouse_pos;
+                    borrowed.pan_image(delta);
                 }
                 borrowed.last_mouse_pos = event.cursor_pos;
             }
@@ -850,10 +2093,35 @@ impl Widget for PictureWidget {
                 MouseButton::Left => {
                     let mut borrowed = self.data.borrow_mut();
                     if state == ElementState::Pressed {
-                        if borrowed.hover {
+                        let select =
+                            borrowed.crop_mode || event.modifiers.ctrl();
+                        let bounds = borrowed.drawn_bounds;
+                        let grabbed_split = match &mut borrowed.compare {
+                            Some(compare) => {
+                                compare.dragging_split = compare
+                                    .can_grab_split(bounds, event.cursor_pos);
+                                compare.dragging_split
+                            }
+                            None => false,
+                        };
+                        if grabbed_split {
+                            // The split line is moved instead of the image
+                        } else if borrowed.hover && select {
+                            borrowed.start_selection(event.cursor_pos);
+                        } else if borrowed.hover {
                             borrowed.click = true;
                             borrowed.panning = true
                         }
+                    } else if borrowed.dragging_split() {
+                        if let Some(compare) = &mut borrowed.compare {
+                            compare.dragging_split = false;
+                        }
+                    } else if borrowed.selecting {
+                        borrowed.selecting = false;
+                        if borrowed.selected_rect().is_none() {
+                            // A click without dragging clears the selection
+                            borrowed.selection = None;
+                        }
                     } else {
                         borrowed.panning = false;
                         borrowed.click = false;
@@ -890,7 +2158,8 @@ impl Widget for PictureWidget {
             EventKind::MouseScroll { delta } => {
                 let mut borrowed = self.data.borrow_mut();
                 let delta = delta.vec.y * 0.375;
-                borrowed.zoom_image(event.cursor_pos, delta);
+                let anchor = borrowed.to_view_point(event.cursor_pos);
+                borrowed.zoom_image(anchor, delta);
             }
             EventKind::ReceivedCharacter(ch) => {
                 //println!("Got char {}", ch);
@@ -963,31 +2232,58 @@ impl Widget for PictureWidget {
                         MovementDir::Negative
                     );
 
-                    movement_trigger!(
-                        borrowed.hor_pan_input,
-                        borrowed.hor_pan_vel,
-                        PAN_LEFT_NAME,
-                        MovementDir::Positive
-                    );
-                    movement_trigger!(
-                        borrowed.hor_pan_input,
-                        borrowed.hor_pan_vel,
-                        PAN_RIGHT_NAME,
-                        MovementDir::Negative
+                    let fit_to_axis = matches!(
+                        borrowed.scaling,
+                        ScalingMode::FitWidth | ScalingMode::FitHeight
                     );
+                    if fit_to_axis {
+                        // Pages are scrolled one at a time instead of
+                        // moving the image continuously.
+                        let page_scrolls = [
+                            (PAN_LEFT_NAME, 1.0, 0.0),
+                            (PAN_RIGHT_NAME, -1.0, 0.0),
+                            (PAN_UP_NAME, 0.0, 1.0),
+                            (PAN_DOWN_NAME, 0.0, -1.0),
+                        ];
+                        for (name, hor_dir, ver_dir) in page_scrolls {
+                            if pressed
+                                && action_triggered(
+                                    &borrowed.config,
+                                    name,
+                                    input_key_str.as_str(),
+                                    event.modifiers,
+                                )
+                            {
+                                borrowed.scroll_page(hor_dir, ver_dir);
+                            }
+                        }
+                    } else {
+                        movement_trigger!(
+                            borrowed.hor_pan_input,
+                            borrowed.hor_pan_vel,
+                            PAN_LEFT_NAME,
+                            MovementDir::Positive
+                        );
+                        movement_trigger!(
+                            borrowed.hor_pan_input,
+                            borrowed.hor_pan_vel,
+                            PAN_RIGHT_NAME,
+                            MovementDir::Negative
+                        );
 
-                    movement_trigger!(
-                        borrowed.ver_pan_input,
-                        borrowed.ver_pan_vel,
-                        PAN_UP_NAME,
-                        MovementDir::Positive
-                    );
-                    movement_trigger!(
-                        borrowed.ver_pan_input,
-                        borrowed.ver_pan_vel,
-                        PAN_DOWN_NAME,
-                        MovementDir::Negative
-                    );
+                        movement_trigger!(
+                            borrowed.ver_pan_input,
+                            borrowed.ver_pan_vel,
+                            PAN_UP_NAME,
+                            MovementDir::Positive
+                        );
+                        movement_trigger!(
+                            borrowed.ver_pan_input,
+                            borrowed.ver_pan_vel,
+                            PAN_DOWN_NAME,
+                            MovementDir::Negative
+                        );
+                    }
                 }
             }
             EventKind::DroppedFile(ref path) => {
@@ -1036,10 +2332,18 @@ impl Widget for PictureWidget {
             EventKind::Focused(focused) => {
                 if focused {
                     let mut borrowed = self.data.borrow_mut();
-                    if let Err(e) = borrowed.playback_manager.update_directory()
+                    if let Err(e) =
+                        borrowed.playback_manager.refresh_directory()
                     {
                         eprintln!("{e}");
                     }
+                    if let Some(compare) = &mut borrowed.compare {
+                        if let Err(e) =
+                            compare.playback_manager.refresh_directory()
+                        {
+                            eprintln!("{e}");
+                        }
+                    }
                     borrowed.render_validity.invalidate();
                 }
             }
@@ -1075,41 +2379,52 @@ impl Drop for PictureWidget {
     }
 }
 
-fn draw_tex_grid(
-    data: Ref<PictureWidgetData>,
-    target: &mut Frame,
+/// The size of the image on the screen in physical pixels
+fn drawn_size(
+    data: &PictureWidgetData,
     context: &DrawContext,
-    texture: AnimationFrameTexture,
-) {
-    let size = data.drawn_bounds.size.vec;
-    let projection_transform =
-        cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0);
-
-    let viewport_rect = context.logical_rect_to_viewport(&data.drawn_bounds);
-    let image_draw_params = glium::DrawParameters {
-        viewport: Some(viewport_rect),
-        ..Default::default()
-    };
+    texture: &AnimationFrameTexture,
+) -> Vector2<f32> {
+    let size = image_phys_size(data, context, texture).vec;
+    if data.shown_dimensions(texture) == (texture.w, texture.h) {
+        size
+    } else {
+        Vector2::new(size.y, size.x)
+    }
+}
 
+/// The size of the image in physical pixels before it's oriented
+fn image_phys_size(
+    data: &PictureWidgetData,
+    context: &DrawContext,
+    texture: &AnimationFrameTexture,
+) -> LogicalVector {
     let img_phys_w = texture.w as f32;
     let img_phys_h = texture.h as f32;
     let img_height_over_width = img_phys_h / img_phys_w;
     let image_display_width =
         data.img_texel_size * img_phys_w / context.dpi_scale_factor;
     let image_display_height = image_display_width * img_height_over_width;
+    let img_phys_w = image_display_width * context.dpi_scale_factor;
+    let img_phys_h = image_display_height * context.dpi_scale_factor;
+    LogicalVector::new(img_phys_w.ceil(), img_phys_h.ceil())
+}
+
+/// The transformation from the unit square to the image in logical pixels
+/// relative to the view, with the center of the image at `center` given in
+/// physical pixels.
+fn image_transform(
+    data: &PictureWidgetData,
+    context: &DrawContext,
+    texture: &AnimationFrameTexture,
+    center: Vector2<f32>,
+) -> Matrix4<f32> {
     // Model tranform
-    let img_pyhs_pos = data.img_pos.vec * context.dpi_scale_factor;
-    let img_phys_siz = {
-        let img_phys_w = image_display_width * context.dpi_scale_factor;
-        let img_phys_h = image_display_height * context.dpi_scale_factor;
-        LogicalVector::new(img_phys_w.ceil(), img_phys_h.ceil())
-    };
-    let img_logical_corner_x = (img_pyhs_pos.x - img_phys_siz.vec.x * 0.5)
-        .ceil()
-        / context.dpi_scale_factor;
-    let img_logical_corner_y = (img_pyhs_pos.y - img_phys_siz.vec.y * 0.5)
-        .ceil()
-        / context.dpi_scale_factor;
+    let img_phys_siz = image_phys_size(data, context, texture);
+    let img_logical_corner_x =
+        (center.x - img_phys_siz.vec.x * 0.5).ceil() / context.dpi_scale_factor;
+    let img_logical_corner_y =
+        (center.y - img_phys_siz.vec.y * 0.5).ceil() / context.dpi_scale_factor;
 
     // This is the display width of the image in logical pixel units
     let img_adjusted_w = img_phys_siz.vec.x / context.dpi_scale_factor;
@@ -1124,7 +2439,7 @@ fn draw_tex_grid(
             -0.5 * img_adjusted_h,
             0.0,
         ));
-        let orient = orientation_to_matrix(texture.orientation);
+        let orient = orientation_to_matrix(data.shown_orientation(texture));
         let to_corner = Matrix4::from_translation(Vector3::new(
             0.5 * img_adjusted_w,
             0.5 * img_adjusted_h,
@@ -1137,66 +2452,92 @@ fn draw_tex_grid(
         img_logical_corner_y,
         0.0,
     ));
+    img_translation * orientation * img_scaling
+}
 
-    // let img_logical_w = img_w / context.dpi_scale_factor;
-    // let img_logical_h = img_h / context.dpi_scale_factor;
+/// The transformation from the unit square to the area of `cell` within the
+/// unit square of the whole image
+fn cell_transform(
+    texture: &AnimationFrameTexture,
+    cell: &TextureGridItem,
+) -> Matrix4<f32> {
+    let img_phys_w = texture.w as f32;
+    let img_phys_h = texture.h as f32;
+    let (cell_phys_w, cell_phys_h) = cell.tex.dimensions();
     let cell_phy_step = texture.cell_step_size;
-    for cell_tex in texture.tex_grid.iter() {
-        let (cell_phys_w, cell_phys_h) = cell_tex.tex.dimensions();
-
-        let cell_phy_offset_x = cell_phy_step * cell_tex.col;
-        let cell_phy_offset_y = cell_phy_step * cell_tex.row;
-        // let cell_logical_offset_x = cell_phy_offset_x as f32 / context.dpi_scale_factor;
-        // let cell_logical_offset_y = cell_phy_offset_y as f32 / context.dpi_scale_factor;
-
-        // The grid is constructed so that it is exactly of size (1, 1) and is located at (0, 0)
-        // This allows to leave most of the image transformation logic unchanged.
-        let cell_scaling = Matrix4::from_nonuniform_scale(
-            cell_phys_w as f32 / img_phys_w,
-            cell_phys_h as f32 / img_phys_h,
-            1.0,
-        );
-        let cell_translation = Matrix4::from_translation(Vector3::new(
-            cell_phy_offset_x as f32 / img_phys_w,
-            cell_phy_offset_y as f32 / img_phys_h,
-            0.0,
-        ));
+    let cell_phy_offset_x = cell_phy_step * cell.col;
+    let cell_phy_offset_y = cell_phy_step * cell.row;
+
+    // The grid is constructed so that it is exactly of size (1, 1) and is located at (0, 0)
+    // This allows to leave most of the image transformation logic unchanged.
+    let cell_scaling = Matrix4::from_nonuniform_scale(
+        cell_phys_w as f32 / img_phys_w,
+        cell_phys_h as f32 / img_phys_h,
+        1.0,
+    );
+    let cell_translation = Matrix4::from_translation(Vector3::new(
+        cell_phy_offset_x as f32 / img_phys_w,
+        cell_phy_offset_y as f32 / img_phys_h,
+        0.0,
+    ));
+    cell_translation * cell_scaling
+}
 
-        let transform = img_translation
-            * orientation
-            * img_scaling
-            * cell_translation
-            * cell_scaling;
-        // Projection tranform
-        let transform = projection_transform * transform;
-
-        let sampler = cell_tex
-            .tex
-            .sampled()
-            .minify_filter(
-                glium::uniforms::MinifySamplerFilter::LinearMipmapLinear,
-            )
-            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp);
+fn cell_sampler<'a>(
+    data: &PictureWidgetData,
+    cell: &'a TextureGridItem,
+) -> Sampler<'a, SrgbTexture2d> {
+    let filter = match data.antialiasing {
+        Antialias::Auto if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD => {
+            MagnifySamplerFilter::Linear
+        }
+        Antialias::Auto | Antialias::Never => MagnifySamplerFilter::Nearest,
+        Antialias::Always => MagnifySamplerFilter::Linear,
+    };
+    cell.tex
+        .sampled()
+        .minify_filter(glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
+        .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+        .magnify_filter(filter)
+}
 
-        let filter = match data.antialiasing {
-            Antialias::Auto
-                if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD =>
-            {
-                MagnifySamplerFilter::Linear
-            }
-            Antialias::Auto | Antialias::Never => MagnifySamplerFilter::Nearest,
-            Antialias::Always => MagnifySamplerFilter::Linear,
-        };
-        let sampler = sampler.magnify_filter(filter);
+fn lod_level(data: &PictureWidgetData) -> f32 {
+    ((1.0 / data.img_texel_size).log2().max(0.0) + 0.125).floor()
+}
+
+/// Draws `texture` placed within `view` according to the zoom of the widget
+/// with its center at `center`. Only the part of the image inside of `clip`
+/// is drawn.
+fn draw_tex_grid(
+    data: &PictureWidgetData,
+    target: &mut Frame,
+    context: &DrawContext,
+    texture: &AnimationFrameTexture,
+    center: Vector2<f32>,
+    view: LogicalRect,
+    clip: LogicalRect,
+) {
+    let size = view.size.vec;
+    let projection_transform =
+        cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0);
+
+    let viewport_rect = context.logical_rect_to_viewport(&view);
+    let image_draw_params = glium::DrawParameters {
+        viewport: Some(viewport_rect),
+        scissor: Some(context.logical_rect_to_viewport(&clip)),
+        ..Default::default()
+    };
 
-        // building the uniforms
-        let lod_level =
-            ((1.0 / data.img_texel_size).log2().max(0.0) + 0.125).floor();
+    let image_transform = image_transform(data, context, texture, center);
+    for cell_tex in texture.tex_grid.iter() {
+        let transform = projection_transform
+            * image_transform
+            * cell_transform(texture, cell_tex);
         let uniforms = uniform! {
             matrix: Into::<[[f32; 4]; 4]>::into(transform),
             bright_shade: data.bright_shade,
-            tex: sampler,
-            lod_level: lod_level,
+            tex: cell_sampler(data, cell_tex),
+            lod_level: lod_level(data),
         };
         target
             .draw(
@@ -1209,3 +2550,169 @@ fn draw_tex_grid(
             .unwrap();
     }
 }
+
+/// The mode of the overlay shader that only draws the part of an image that
+/// the other image doesn't cover
+const OVERLAY_UNCOVERED: i32 = -1;
+
+/// Draws the images `a` and `b` on top of each other. The area where they
+/// overlap is combined according to the overlay `mode`, the rest of each
+/// image is drawn as it is. The images are given with their centers.
+fn draw_overlay(
+    data: &PictureWidgetData,
+    target: &mut Frame,
+    context: &DrawContext,
+    mode: i32,
+    a: (&AnimationFrameTexture, Vector2<f32>),
+    b: (&AnimationFrameTexture, Vector2<f32>),
+    view: LogicalRect,
+) {
+    let (opacity, threshold) = {
+        let config = data.config.lock().unwrap();
+        (
+            config.onion_opacity(),
+            config.mask_threshold() as f32 / 255.0,
+        )
+    };
+    let size = view.size.vec;
+    let projection_transform =
+        cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0);
+    let draw_params = glium::DrawParameters {
+        viewport: Some(context.logical_rect_to_viewport(&view)),
+        scissor: Some(context.logical_rect_to_viewport(&view)),
+        ..Default::default()
+    };
+
+    let (a_texture, a_center) = a;
+    let (b_texture, b_center) = b;
+    let a_transform = image_transform(data, context, a_texture, a_center);
+    let b_transform = image_transform(data, context, b_texture, b_center);
+    let (Some(a_inverse), Some(b_inverse)) =
+        (a_transform.invert(), b_transform.invert())
+    else {
+        return;
+    };
+    let draw =
+        |target: &mut Frame,
+         mode: i32,
+         (a_cell, a_cell_transform): (&TextureGridItem, Matrix4<f32>),
+         (b_cell, b_cell_transform): (&TextureGridItem, Matrix4<f32>),
+         b_image_inverse: Matrix4<f32>| {
+            // Maps the texture coordinates of `a` to the ones of the `b` cell
+            let b_matrix = match b_cell_transform.invert() {
+                Some(inverse) => inverse * a_cell_transform,
+                None => return,
+            };
+            let uniforms = uniform! {
+                matrix: Into::<[[f32; 4]; 4]>::into(
+                    projection_transform * a_cell_transform
+                ),
+                b_matrix: Into::<[[f32; 4]; 4]>::into(b_matrix),
+                // and to the ones of the whole `b` image
+                b_image_matrix: Into::<[[f32; 4]; 4]>::into(
+                    b_image_inverse * a_cell_transform
+                ),
+                tex: cell_sampler(data, a_cell),
+                b_tex: cell_sampler(data, b_cell),
+                mode: mode,
+                opacity: opacity,
+                threshold: threshold,
+                bright_shade: data.bright_shade,
+                lod_level: lod_level(data),
+            };
+            target
+                .draw(
+                    context.unit_quad_vertices,
+                    context.unit_quad_indices,
+                    &data.overlay_program,
+                    &uniforms,
+                    &draw_params,
+                )
+                .unwrap();
+        };
+
+    // The parts of `b` outside of `a`
+    for b_cell in b_texture.tex_grid.iter() {
+        let b_cell_transform = b_transform * cell_transform(b_texture, b_cell);
+        let b_cell = (b_cell, b_cell_transform);
+        draw(target, OVERLAY_UNCOVERED, b_cell, b_cell, a_inverse);
+    }
+    // Each cell of `a` is drawn once for every cell of `b` that it overlaps
+    // with. The fragments outside of `b` are drawn as they are, and the ones
+    // in another cell of `b` are discarded.
+    for a_cell in a_texture.tex_grid.iter() {
+        let a_cell_transform = a_transform * cell_transform(a_texture, a_cell);
+        let a_rect = transformed_unit_rect(&a_cell_transform);
+        let mut overlaps = false;
+        for b_cell in b_texture.tex_grid.iter() {
+            let b_cell_transform =
+                b_transform * cell_transform(b_texture, b_cell);
+            if rects_overlap(a_rect, transformed_unit_rect(&b_cell_transform)) {
+                overlaps = true;
+                draw(
+                    target,
+                    mode,
+                    (a_cell, a_cell_transform),
+                    (b_cell, b_cell_transform),
+                    b_inverse,
+                );
+            }
+        }
+        if !overlaps {
+            let a_cell = (a_cell, a_cell_transform);
+            draw(target, OVERLAY_UNCOVERED, a_cell, a_cell, b_inverse);
+        }
+    }
+}
+
+/// The bounding box of the unit square after `transform` as its minimum and
+/// maximum corner
+fn transformed_unit_rect(
+    transform: &Matrix4<f32>,
+) -> (Vector2<f32>, Vector2<f32>) {
+    let p0 = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+    let p1 = transform.transform_point(Point3::new(1.0, 1.0, 0.0));
+    (
+        Vector2::new(p0.x.min(p1.x), p0.y.min(p1.y)),
+        Vector2::new(p0.x.max(p1.x), p0.y.max(p1.y)),
+    )
+}
+
+fn rects_overlap(
+    a: (Vector2<f32>, Vector2<f32>),
+    b: (Vector2<f32>, Vector2<f32>),
+) -> bool {
+    a.0.x < b.1.x && b.0.x < a.1.x && a.0.y < b.1.y && b.0.y < a.1.y
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+    use crate::image_cache::image_loader::apply_orientation;
+
+    #[test]
+    fn matrices_match_the_pixels() {
+        let image = GrayImage::from_fn(3, 2, |x, y| Luma([(y * 3 + x) as u8]));
+        for exif in 1..=8 {
+            let orientation = Orientation::from_exif(exif).unwrap();
+            let oriented = apply_orientation(image.clone(), orientation);
+            let matrix = orientation_to_matrix(orientation);
+            for (x, y, pixel) in image.enumerate_pixels() {
+                // Positions relative to the center, with y pointing down
+                let from_center = Point3::new(
+                    x as f32 + 0.5 - image.width() as f32 / 2.0,
+                    y as f32 + 0.5 - image.height() as f32 / 2.0,
+                    0.0,
+                );
+                let moved = matrix.transform_point(from_center);
+                let x = moved.x + oriented.width() as f32 / 2.0 - 0.5;
+                let y = moved.y + oriented.height() as f32 / 2.0 - 0.5;
+                let shown =
+                    oriented.get_pixel(x.round() as u32, y.round() as u32);
+                assert_eq!(shown, pixel, "{orientation:?} at {x}, {y}");
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::{cell::RefCell, rc::Rc};
+
+use glium::{
+    program,
+    texture::{MipmapsOption, RawImage2d, SrgbTexture2d},
+    uniforms::MinifySamplerFilter,
+    Frame, Program,
+};
+
+use super::{filmstrip::draw_texture, text};
+use crate::{
+    add_common_widget_functions,
+    gelatin::{
+        misc::{
+            Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
+        },
+        window::{RenderValidity, Window},
+        DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
+    },
+    image_cache::image_loader::Orientation,
+    shaders,
+};
+
+const FONT_SIZE: f32 = 18.0;
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+/// Stands for the cursor at the end of the entered text
+const CURSOR: char = '_';
+
+/// The rendered label and entered text
+struct PromptLine {
+    line: String,
+    dpi_scale: f32,
+    /// `None` if the line couldn't be rendered
+    texture: Option<SrgbTexture2d>,
+}
+
+struct PromptData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    /// Created on the first draw, when the display is available
+    program: Option<Program>,
+    rendered: Option<PromptLine>,
+
+    label: String,
+    text: String,
+}
+
+impl WidgetData for PromptData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+/// A single line of text entry in the middle of the widgets in the same
+/// container. The prompt doesn't receive the key events itself, the widget
+/// that opened it passes the typed characters on.
+pub struct Prompt {
+    data: RefCell<PromptData>,
+}
+
+impl Prompt {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> Self {
+        text::preload_fonts();
+        Prompt {
+            data: RefCell::new(PromptData {
+                placement: WidgetPlacement {
+                    width: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    height: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    ignore_layout: true,
+                    ..Default::default()
+                },
+                drawn_bounds: Default::default(),
+                visible: false,
+                render_validity: Default::default(),
+                program: None,
+                rendered: None,
+                label: String::new(),
+                text: String::new(),
+            }),
+        }
+    }
+
+    /// Shows the prompt with `label` in front of an empty text
+    pub fn open(&self, label: &str) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = true;
+        borrowed.label = label.to_owned();
+        borrowed.text.clear();
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Hides the prompt and returns the entered text
+    pub fn close(&self) -> String {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = false;
+        borrowed.render_validity.invalidate();
+        std::mem::take(&mut borrowed.text)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    pub fn push(&self, ch: char) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text.push(ch);
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Removes the last character of the entered text
+    pub fn pop(&self) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text.pop();
+        borrowed.render_validity.invalidate();
+    }
+}
+
+impl Default for Prompt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for Prompt {
+    fn before_draw(&self, _window: &Window) -> NextUpdate {
+        NextUpdate::Latest
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        let dpi_scale = context.dpi_scale_factor;
+        let line = format!("{}{}{CURSOR}", data.label, data.text);
+        #[allow(clippy::float_cmp)]
+        let outdated = match &data.rendered {
+            Some(rendered) => {
+                rendered.line != line || rendered.dpi_scale != dpi_scale
+            }
+            None => true,
+        };
+        if outdated {
+            let image =
+                text::render_text(&line, FONT_SIZE * dpi_scale, BACKGROUND);
+            let texture = image.and_then(|image| {
+                let dimensions = image.dimensions();
+                let raw_image =
+                    RawImage2d::from_raw_rgba(image.into_raw(), dimensions);
+                let texture = SrgbTexture2d::with_mipmaps(
+                    context.display,
+                    raw_image,
+                    MipmapsOption::NoMipmap,
+                );
+                texture
+                    .map_err(|e| eprintln!("Error while uploading text: {e}"))
+                    .ok()
+            });
+            data.rendered = Some(PromptLine {
+                line,
+                dpi_scale,
+                texture,
+            });
+        }
+        let Some(texture) = data
+            .rendered
+            .as_ref()
+            .and_then(|rendered| rendered.texture.as_ref())
+        else {
+            return Ok(NextUpdate::Latest);
+        };
+
+        let (w, h) = texture.dimensions();
+        let size = LogicalVector::new(w as f32, h as f32) / dpi_scale;
+        let bounds = data.drawn_bounds;
+        let area = LogicalRect {
+            pos: bounds.center() - size * 0.5,
+            size,
+        };
+        let program = data.program.get_or_insert_with(|| {
+            program!(context.display,
+                140 => {
+                    vertex: shaders::VERTEX_140,
+                    fragment: shaders::FRAGMENT_140
+                },
+                110 => {
+                    vertex: shaders::VERTEX_110,
+                    fragment: shaders::FRAGMENT_110
+                },
+            )
+            .unwrap()
+        });
+        let draw_params = glium::DrawParameters {
+            viewport: Some(*context.viewport),
+            scissor: Some(context.logical_rect_to_viewport(&bounds)),
+            ..Default::default()
+        };
+        draw_texture(
+            target,
+            context,
+            program,
+            // The text is opaque
+            1.0,
+            &draw_params,
+            (texture, Orientation::Deg0, MinifySamplerFilter::Linear),
+            area,
+        );
+        Ok(NextUpdate::Latest)
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        self.data.borrow_mut().default_layout(available_space);
+    }
+
+    fn handle_event(&self, _event: &Event) {}
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use glium::{
+    program,
+    texture::{MipmapsOption, RawImage2d, SrgbTexture2d},
+    uniforms::MinifySamplerFilter,
+    Frame, Program,
+};
+
+use super::{filmstrip::draw_texture, text};
+use crate::{
+    add_common_widget_functions,
+    gelatin::{
+        misc::{
+            Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement,
+        },
+        window::{RenderValidity, Window},
+        DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
+    },
+    image_cache::{
+        image_loader::Orientation,
+        thumbnails::{Thumbnail, ThumbnailCache},
+    },
+    shaders,
+};
+
+/// The size of the square that the thumbnail fits into in logical pixels
+const THUMBNAIL_SIZE: f32 = 160.0;
+const PADDING: f32 = 4.0;
+/// The space between the preview and the point it's shown above
+const GAP: f32 = 6.0;
+const FONT_SIZE: f32 = 13.0;
+/// The memory that the thumbnails may use, in bytes
+const THUMBNAIL_CACHE_SIZE: isize = 16 * 1024 * 1024;
+/// How often the finished thumbnails are checked while some are loading
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The file name is drawn on this, so the whole panel uses it
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+const PLACEHOLDER_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+
+/// The rendered file name of an image
+struct NameLabel {
+    path: PathBuf,
+    dpi_scale: f32,
+    /// `None` if the name couldn't be rendered
+    texture: Option<SrgbTexture2d>,
+}
+
+struct SliderPreviewData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    /// Created on the first draw, when the display is available
+    program: Option<Program>,
+    bright_shade: f32,
+    /// Only created once the preview is shown
+    thumbnails: Option<ThumbnailCache>,
+    dpi_scale: f32,
+    name_label: Option<NameLabel>,
+
+    /// The image to preview
+    path: PathBuf,
+    /// The middle of the bottom edge of the preview, relative to the window
+    anchor: LogicalVector,
+}
+
+impl WidgetData for SliderPreviewData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+/// A thumbnail and the file name of an image, floating above the point of
+/// the slider that the image belongs to. Covers the widgets in the same
+/// container.
+pub struct SliderPreview {
+    data: RefCell<SliderPreviewData>,
+}
+
+impl SliderPreview {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> Self {
+        text::preload_fonts();
+        SliderPreview {
+            data: RefCell::new(SliderPreviewData {
+                placement: WidgetPlacement {
+                    width: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    height: Length::Stretch {
+                        min: 0.0,
+                        max: f32::INFINITY,
+                    },
+                    ignore_layout: true,
+                    ..Default::default()
+                },
+                drawn_bounds: Default::default(),
+                visible: false,
+                render_validity: Default::default(),
+                program: None,
+                bright_shade: 0.95,
+                thumbnails: None,
+                dpi_scale: 1.0,
+                name_label: None,
+                path: PathBuf::new(),
+                anchor: Default::default(),
+            }),
+        }
+    }
+
+    /// Shows the preview of the image at `path` above `anchor`, which is in
+    /// logical pixels relative to the window.
+    pub fn show(&self, path: &Path, anchor: LogicalVector) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = true;
+        borrowed.path = path.to_owned();
+        borrowed.anchor = anchor;
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn hide(&self) {
+        self.set_visible(false);
+    }
+
+    pub fn set_bright_shade(&self, shade: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.bright_shade = shade;
+        borrowed.render_validity.invalidate();
+    }
+}
+
+impl Default for SliderPreview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for SliderPreview {
+    fn before_draw(&self, window: &Window) -> NextUpdate {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return NextUpdate::Latest;
+        }
+        let size = (THUMBNAIL_SIZE * data.dpi_scale).round() as u32;
+        let thumbnails = data.thumbnails.get_or_insert_with(|| {
+            ThumbnailCache::new(THUMBNAIL_CACHE_SIZE, 1)
+        });
+        thumbnails.set_size(size);
+        match thumbnails.receive(&window.display_mut()) {
+            Ok(true) => data.render_validity.invalidate(),
+            Ok(false) => (),
+            Err(e) => eprintln!("Error while uploading a thumbnail: {e}"),
+        }
+        thumbnails.request(&data.path);
+        if thumbnails.loading() {
+            NextUpdate::WaitUntil(Instant::now() + LOADING_POLL_INTERVAL)
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let mut borrowed = self.data.borrow_mut();
+        let data = &mut *borrowed;
+        if !data.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        let dpi_scale = context.dpi_scale_factor;
+        // The thumbnail is loaded again at the new size in `before_draw`
+        #[allow(clippy::float_cmp)]
+        let dpi_changed = data.dpi_scale != dpi_scale;
+        data.dpi_scale = dpi_scale;
+
+        #[allow(clippy::float_cmp)]
+        let label_outdated = match &data.name_label {
+            Some(label) => {
+                label.path != data.path || label.dpi_scale != dpi_scale
+            }
+            None => true,
+        };
+        if label_outdated {
+            let name = data.path.file_name().unwrap_or_default();
+            let image = text::render_text(
+                &name.to_string_lossy(),
+                FONT_SIZE * dpi_scale,
+                BACKGROUND,
+            );
+            let texture = image.and_then(|image| {
+                let dimensions = image.dimensions();
+                let raw_image =
+                    RawImage2d::from_raw_rgba(image.into_raw(), dimensions);
+                let texture = SrgbTexture2d::with_mipmaps(
+                    context.display,
+                    raw_image,
+                    MipmapsOption::NoMipmap,
+                );
+                texture
+                    .map_err(|e| eprintln!("Error while uploading text: {e}"))
+                    .ok()
+            });
+            data.name_label = Some(NameLabel {
+                path: data.path.clone(),
+                dpi_scale,
+                texture,
+            });
+        }
+        let label = data
+            .name_label
+            .as_ref()
+            .and_then(|label| label.texture.as_ref());
+        let label_size = match label {
+            Some(texture) => {
+                let (w, h) = texture.dimensions();
+                LogicalVector::new(w as f32, h as f32) / dpi_scale
+            }
+            None => LogicalVector::new(0.0, 0.0),
+        };
+
+        // The panel is as wide as the file name, but stays in the widget
+        let bounds = data.drawn_bounds;
+        let width = (label_size.vec.x.max(THUMBNAIL_SIZE) + PADDING * 2.0)
+            .min(bounds.size.vec.x);
+        let height = THUMBNAIL_SIZE + label_size.vec.y + PADDING * 2.0;
+        let left = (data.anchor.vec.x - width * 0.5)
+            .min(bounds.right() - width)
+            .max(bounds.left());
+        let panel = LogicalRect {
+            pos: LogicalVector::new(left, data.anchor.vec.y - GAP - height),
+            size: LogicalVector::new(width, height),
+        };
+        let background = BACKGROUND.map(|c| c as f32 / 255.0);
+        let background = [background[0], background[1], background[2], 1.0];
+        context.clear_color(target, background, Some(panel));
+
+        let program = data.program.get_or_insert_with(|| {
+            program!(context.display,
+                140 => {
+                    vertex: shaders::VERTEX_140,
+                    fragment: shaders::FRAGMENT_140
+                },
+                110 => {
+                    vertex: shaders::VERTEX_110,
+                    fragment: shaders::FRAGMENT_110
+                },
+            )
+            .unwrap()
+        });
+        let draw_params = glium::DrawParameters {
+            viewport: Some(*context.viewport),
+            scissor: Some(context.logical_rect_to_viewport(&panel)),
+            ..Default::default()
+        };
+        let thumbnail_area = LogicalRect {
+            pos: LogicalVector::new(
+                panel.center().vec.x - THUMBNAIL_SIZE * 0.5,
+                panel.top() + PADDING,
+            ),
+            size: LogicalVector::new(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        };
+        let thumbnail = match &mut data.thumbnails {
+            Some(thumbnails) => {
+                thumbnails.start_frame();
+                thumbnails.get(&data.path)
+            }
+            None => Thumbnail::Loading,
+        };
+        match &thumbnail {
+            Thumbnail::Loaded(texture) => draw_texture(
+                target,
+                context,
+                program,
+                data.bright_shade,
+                &draw_params,
+                // Thumbnails are oriented by the loader
+                (texture, Orientation::Deg0, MinifySamplerFilter::Linear),
+                thumbnail_area,
+            ),
+            Thumbnail::Loading | Thumbnail::Failed => context.clear_color(
+                target,
+                PLACEHOLDER_COLOR,
+                Some(thumbnail_area),
+            ),
+        }
+        if let Some(texture) = label {
+            // Long names are cut off at the end
+            let label_left = (panel.center().vec.x - label_size.vec.x * 0.5)
+                .max(panel.left() + PADDING);
+            let label_area = LogicalRect {
+                pos: LogicalVector::new(
+                    label_left,
+                    thumbnail_area.bottom() + PADDING,
+                ),
+                size: label_size,
+            };
+            draw_texture(
+                target,
+                context,
+                program,
+                data.bright_shade,
+                &draw_params,
+                (texture, Orientation::Deg0, MinifySamplerFilter::Linear),
+                label_area,
+            );
+        }
+        if dpi_changed {
+            Ok(NextUpdate::Soonest)
+        } else {
+            Ok(NextUpdate::Latest)
+        }
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        self.data.borrow_mut().default_layout(available_space);
+    }
+
+    fn handle_event(&self, _event: &Event) {}
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
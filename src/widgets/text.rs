@@ -0,0 +1,120 @@
+//! Renders short lines of text into images with the SVG renderer, since the
+//! GUI toolkit has no text rendering of its own.
+
+use image::RgbaImage;
+use lazy_static::lazy_static;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, fontdb::Database},
+};
+
+/// Common sans-serif fonts in the order of preference. The font database
+/// assumes Arial, which many Linux systems don't have.
+const SANS_SERIF_FAMILIES: [&str; 7] = [
+    "Arial",
+    "Segoe UI",
+    "Helvetica",
+    "Noto Sans",
+    "DejaVu Sans",
+    "Liberation Sans",
+    "Cantarell",
+];
+
+lazy_static! {
+    static ref FONTS: Database = {
+        let mut fonts = Database::new();
+        fonts.load_system_fonts();
+        let installed = |family: &str| {
+            fonts.faces().any(|face| {
+                face.families.iter().any(|(name, _)| name == family)
+            })
+        };
+        let sans_serif = SANS_SERIF_FAMILIES.into_iter().find(|f| installed(f));
+        if let Some(family) = sans_serif {
+            fonts.set_sans_serif_family(family);
+        }
+        fonts
+    };
+}
+
+/// Loads the system fonts on a background thread. Loading them can take a
+/// while, and this way the first text doesn't have to wait for it.
+pub fn preload_fonts() {
+    std::thread::spawn(|| lazy_static::initialize(&FONTS));
+}
+
+/// Renders `text` as a single line of white sans-serif text on an opaque
+/// `background`, with a margin of a quarter of the font size around the text.
+/// `font_size` is in pixels.
+///
+/// Returns `None` if there's nothing to draw, for example because no font
+/// has the characters of the text.
+pub fn render_text(
+    text: &str,
+    font_size: f32,
+    background: [u8; 3],
+) -> Option<RgbaImage> {
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1">
+            <text x="0" y="{font_size}" font-family="sans-serif"
+                font-size="{font_size}" fill="white"
+                xml:space="preserve">{}</text>
+        </svg>"#,
+        escape_xml(text)
+    );
+    let tree =
+        usvg::Tree::from_str(&svg, &usvg::Options::default(), &FONTS).ok()?;
+    if !tree.root().has_children() {
+        return None;
+    }
+    let bounds = tree.root().abs_bounding_box();
+    let margin = (font_size * 0.25).ceil();
+    let width = (bounds.width() + margin * 2.0).ceil() as u32;
+    let height = (bounds.height() + margin * 2.0).ceil() as u32;
+    let mut pixmap = Pixmap::new(width, height)?;
+    resvg::render(
+        &tree,
+        Transform::from_translate(
+            margin - bounds.left(),
+            margin - bounds.top(),
+        ),
+        &mut pixmap.as_mut(),
+    );
+    // The pixmap has premultiplied alpha, so blending it over the background
+    // is adding the background where the text is transparent
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let blend = |text: u8, background: u8| {
+                let uncovered = 255 - pixel.alpha() as u32;
+                let background = (background as u32 * uncovered + 127) / 255;
+                text.saturating_add(background as u8)
+            };
+            [
+                blend(pixel.red(), background[0]),
+                blend(pixel.green(), background[1]),
+                blend(pixel.blue(), background[2]),
+                255,
+            ]
+        })
+        .collect();
+    RgbaImage::from_raw(width, height, pixels)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters aren't allowed in XML
+            c if c.is_control() => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}